@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+
+use crate::game::Turn;
+
+// How many of the latest announcements the log keeps
+const ACCESSIBILITY_LOG_LENGTH: usize = 50;
+
+// What happened in the game, announced for screen readers and other assistive tools.
+// Each announcement is one line, its format is stable so tools can rely on it:
+//
+//   turn player=<player>                      it's the player's turn now
+//   drop player=<player> col=<col> row=<row>  the player's disk landed in the column and row
+//   win player=<player>                       the game is over, the player won
+//   draw                                      the game is over, nobody won
+//
+// Players are named by their color (like Red), columns are counted from 1 at the left
+// and rows from 1 at the bottom of the board.
+#[derive(Clone, Copy)]
+pub enum Announcement {
+    Turn(Turn),
+    Drop { turn: Turn, col: i32, row: i32 },
+    Win(Turn),
+    Draw,
+}
+
+impl std::fmt::Display for Announcement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Announcement::Turn(turn) => write!(f, "turn player={}", turn),
+            Announcement::Drop { turn, col, row } => {
+                write!(f, "drop player={} col={} row={}", turn, col, row)
+            }
+            Announcement::Win(winner) => write!(f, "win player={}", winner),
+            Announcement::Draw => write!(f, "draw"),
+        }
+    }
+}
+
+// The latest announcements, oldest first, also printed to stdout if asked for
+// (with `--announce`, for tools reading the game's output)
+#[derive(Resource)]
+pub struct AccessibilityLog {
+    pub lines: VecDeque<String>,
+    pub stdout: bool,
+}
+
+impl AccessibilityLog {
+    pub fn new(stdout: bool) -> Self {
+        Self {
+            lines: VecDeque::new(),
+            stdout,
+        }
+    }
+
+    // Adds the announcement, dropping the oldest one if the log is full
+    pub fn announce(&mut self, announcement: Announcement) {
+        let line = announcement.to_string();
+        if self.stdout {
+            println!("{}", line);
+        }
+        if self.lines.len() >= ACCESSIBILITY_LOG_LENGTH {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+}
+
+// Announces once the commands are applied, so it comes in order with the changes made with them
+pub fn announce(commands: &mut Commands, announcement: Announcement) {
+    commands.add(move |world: &mut World| {
+        world
+            .resource_mut::<AccessibilityLog>()
+            .announce(announcement);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::play_scripted_game;
+
+    #[test]
+    fn scripted_game_is_announced_in_order() {
+        // Red drops into the first four columns, blue stacks on them
+        let app = play_scripted_game(&[0, 0, 1, 1, 2, 2, 3]);
+
+        let lines: Vec<&str> = app
+            .world
+            .resource::<AccessibilityLog>()
+            .lines
+            .iter()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                "drop player=Red col=1 row=1",
+                "turn player=Blue",
+                "drop player=Blue col=1 row=2",
+                "turn player=Red",
+                "drop player=Red col=2 row=1",
+                "turn player=Blue",
+                "drop player=Blue col=2 row=2",
+                "turn player=Red",
+                "drop player=Red col=3 row=1",
+                "turn player=Blue",
+                "drop player=Blue col=3 row=2",
+                "turn player=Red",
+                "drop player=Red col=4 row=1",
+                "turn player=Blue",
+                "win player=Red",
+            ]
+        );
+    }
+
+    #[test]
+    fn log_keeps_only_the_latest_announcements() {
+        let mut log = AccessibilityLog::new(false);
+        for _ in 0..ACCESSIBILITY_LOG_LENGTH {
+            log.announce(Announcement::Draw);
+        }
+        log.announce(Announcement::Turn(Turn::FIRST));
+        assert_eq!(log.lines.len(), ACCESSIBILITY_LOG_LENGTH);
+        assert_eq!(log.lines.back().unwrap(), "turn player=Red");
+    }
+}
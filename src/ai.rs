@@ -0,0 +1,368 @@
+use std::{collections::HashMap, time::Duration};
+
+use bevy::utils::Instant;
+use rand::seq::SliceRandom;
+
+use crate::{
+    book::OpeningBook,
+    game::{Board, Disk},
+};
+
+// Score for a won position, the depth left is added so quicker wins are preferred
+const WIN_SCORE: i32 = 1_000_000;
+
+// Largest boards the solver plays out to the end (4x5 or 5x4), an empty 5x5 board already
+// takes close to a minute
+const SOLVER_MAX_CELLS: i32 = 20;
+
+// Each search a move deeper takes about this many times as long as the last one,
+// so a timed search doesn't start one that would run far past its budget
+const DEEPENING_GROWTH: u32 = 4;
+
+// Result of a position with perfect play from both sides, for the player to move.
+// Wins and losses come with the number of moves left until the game ends
+// (the winner ends it as soon as possible, the loser holds out as long as possible).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameValue {
+    Win(u32),
+    Loss(u32),
+    Draw,
+    // The board is too big to solve, or not played by two players
+    Unknown,
+}
+
+impl std::fmt::Display for GameValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GameValue::Win(moves) => write!(f, "wins in {} moves", moves),
+            GameValue::Loss(moves) => write!(f, "loses in {} moves", moves),
+            GameValue::Draw => write!(f, "draws"),
+            GameValue::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+// Picks the best column for the disk, looking depth moves ahead (at least one).
+// Columns that are equally good are picked at random, so AI games are not all the same.
+// Early in a game the opening book is used instead, when it has the position.
+// Returns None if the board is full.
+pub fn best_move(board: &Board, disk: Disk, depth: u32) -> Option<i32> {
+    if let Some(col) = OpeningBook::bundled().book_move(board) {
+        return Some(col);
+    }
+
+    best_cols(board, disk, depth)
+        .choose(&mut rand::thread_rng())
+        .copied()
+}
+
+// Like best_move, but looking further ahead until the time is up instead of to a fixed depth
+// (iterative deepening). The move comes from the deepest search that was finished, which is at
+// least one move deep, so it can take longer than the budget when that one is slow.
+pub fn best_move_timed(board: &Board, disk: Disk, budget: Duration) -> Option<i32> {
+    if let Some(col) = OpeningBook::bundled().book_move(board) {
+        return Some(col);
+    }
+
+    // Looking further ahead than the board has empty cells finds nothing new
+    let empty_cells = board.disks.iter().flatten().filter(|cell| cell.is_none()).count() as u32;
+    let start = Instant::now();
+    let mut cols = Vec::new();
+    for depth in 1..=empty_cells.max(1) {
+        let search_start = Instant::now();
+        cols = best_cols(board, disk, depth);
+        if start.elapsed() + search_start.elapsed() * DEEPENING_GROWTH > budget {
+            break;
+        }
+    }
+
+    cols.choose(&mut rand::thread_rng()).copied()
+}
+
+// The columns with the best score for the disk, looking depth moves ahead (at least one),
+// none if the board is full
+fn best_cols(board: &Board, disk: Disk, depth: u32) -> Vec<i32> {
+    let mut best_score = i32::MIN;
+    let mut best_cols = Vec::new();
+
+    for col in ordered_moves(board) {
+        let score = score_move(
+            board,
+            col,
+            disk,
+            depth.max(1),
+            -2 * WIN_SCORE,
+            2 * WIN_SCORE,
+        );
+        if score > best_score {
+            best_score = score;
+            best_cols.clear();
+        }
+        if score == best_score {
+            best_cols.push(col);
+        }
+    }
+    best_cols
+}
+
+// The disk of the player moving after the owner of this disk
+fn next_disk(board: &Board, disk: Disk) -> Disk {
+    let mut turn = disk.to_turn();
+    turn.next(board.players);
+    turn.to_disk()
+}
+
+// Legal columns, center first, since those are usually better (which makes pruning work sooner)
+fn ordered_moves(board: &Board) -> Vec<i32> {
+    let mut moves = board.legal_moves();
+    moves.sort_by_key(|col| (col - board.cols / 2).abs());
+    moves
+}
+
+// Score of dropping the disk into the column, for the player dropping it
+fn score_move(board: &Board, col: i32, disk: Disk, depth: u32, alpha: i32, beta: i32) -> i32 {
+    let mut board = board.clone();
+    board.drop_disk(col, disk);
+
+    if board.check_for_wins().is_some() {
+        return WIN_SCORE + depth as i32;
+    }
+    if depth <= 1 {
+        return evaluate(&board, disk);
+    }
+    -negamax(&board, next_disk(&board, disk), depth - 1, -beta, -alpha)
+}
+
+// Best score the player to move can get, looking depth moves ahead (alpha-beta pruned)
+fn negamax(board: &Board, disk: Disk, depth: u32, mut alpha: i32, beta: i32) -> i32 {
+    let moves = ordered_moves(board);
+
+    // Full board, it's a draw
+    if moves.is_empty() {
+        return 0;
+    }
+
+    let mut best_score = i32::MIN;
+    for col in moves {
+        let score = score_move(board, col, disk, depth, alpha, beta);
+        best_score = best_score.max(score);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    best_score
+}
+
+// Plays the position out to the end with perfect play, for the player of the disk to move.
+// Only small boards (up to 4x5) with two players are solved, others are Unknown.
+pub fn solve(board: &Board, disk: Disk) -> GameValue {
+    if board.players != 2 || board.rows * board.cols > SOLVER_MAX_CELLS {
+        return GameValue::Unknown;
+    }
+
+    // A game that is already over
+    if let Some((winner, _, _)) = board.check_for_wins() {
+        return if winner == disk.to_turn() {
+            GameValue::Win(0)
+        } else {
+            GameValue::Loss(0)
+        };
+    }
+
+    let score = solved_score(board, disk, -WIN_SCORE, WIN_SCORE, &mut HashMap::new());
+    match score {
+        0 => GameValue::Draw,
+        _ if score > 0 => GameValue::Win((WIN_SCORE - score) as u32),
+        _ => GameValue::Loss((WIN_SCORE + score) as u32),
+    }
+}
+
+// What is known about the score of a solved position: the score itself, or only that it is
+// at least or at most some score (when the search was cut off by alpha-beta pruning)
+#[derive(Clone, Copy)]
+enum SolvedScore {
+    Exact(i32),
+    AtLeast(i32),
+    AtMost(i32),
+}
+
+// Score of a position with perfect play for the player to move: WIN_SCORE less the moves left
+// for a win, the negative of that for a loss, 0 for a draw (alpha-beta pruned, so scores outside
+// of alpha to beta are only bounds). Positions already solved are remembered by their
+// Zobrist hash (the player to move always follows from the disks).
+fn solved_score(
+    board: &Board,
+    disk: Disk,
+    mut alpha: i32,
+    mut beta: i32,
+    solved: &mut HashMap<u64, SolvedScore>,
+) -> i32 {
+    let hash = board.zobrist_hash();
+    match solved.get(&hash) {
+        Some(&SolvedScore::Exact(score)) => return score,
+        Some(&SolvedScore::AtLeast(score)) => alpha = alpha.max(score),
+        Some(&SolvedScore::AtMost(score)) => beta = beta.min(score),
+        None => {}
+    }
+    if alpha >= beta {
+        return alpha;
+    }
+
+    let moves = ordered_moves(board);
+
+    // Full board, it's a draw
+    if moves.is_empty() {
+        solved.insert(hash, SolvedScore::Exact(0));
+        return 0;
+    }
+
+    let original_alpha = alpha;
+    let mut best_score = i32::MIN;
+    for col in moves {
+        let mut next_board = board.clone();
+        let Some(row) = next_board.drop_disk(col, disk) else {
+            continue;
+        };
+        let score = if next_board.wins_through(row, col) {
+            WIN_SCORE - 1
+        } else {
+            // The other player's result, one move further from the end. That moves the score
+            // by one, so the other player searches a window one wider on both sides.
+            let score = -solved_score(
+                &next_board,
+                next_disk(&next_board, disk),
+                -beta - 1,
+                -alpha + 1,
+                solved,
+            );
+            score - score.signum()
+        };
+        best_score = best_score.max(score);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+
+    let known = if best_score <= original_alpha {
+        SolvedScore::AtMost(best_score)
+    } else if best_score >= beta {
+        SolvedScore::AtLeast(best_score)
+    } else {
+        SolvedScore::Exact(best_score)
+    };
+    solved.insert(hash, known);
+    best_score
+}
+
+// Heuristic score of a position for the player, based on the lines that could still be completed
+fn evaluate(board: &Board, disk: Disk) -> i32 {
+    // Disks in the center column are part of the most lines
+    let center = &board.disks[(board.cols / 2) as usize];
+    let mut score = center.iter().filter(|&&cell| cell == Some(disk)).count() as i32 * 3;
+
+    // Go through every possible line of win_length cells
+    for row in 0..board.rows {
+        for col in 0..board.cols {
+            for (row_delta, col_delta) in board.line_directions() {
+                let end_row = row + row_delta * (board.win_length - 1);
+                let end_col = col + col_delta * (board.win_length - 1);
+                if !(0..board.rows).contains(&end_row) || !(0..board.cols).contains(&end_col) {
+                    continue;
+                }
+
+                // Nobody can complete a line through a rock
+                if (0..board.win_length)
+                    .any(|i| board.is_rock(row + row_delta * i, col + col_delta * i))
+                {
+                    continue;
+                }
+
+                let mut own = 0;
+                let mut other = 0;
+                for i in 0..board.win_length {
+                    let cell =
+                        board.disks[(col + col_delta * i) as usize][(row + row_delta * i) as usize];
+                    match cell {
+                        Some(cell_disk) if cell_disk == disk => own += 1,
+                        Some(_) => other += 1,
+                        None => {}
+                    }
+                }
+
+                // Lines with disks of both players can't be completed by anyone,
+                // the others are worth more the closer they are to being complete
+                let missing = board.win_length - own.max(other);
+                score += match (own, other, missing) {
+                    (_, 0, 1) => 5,
+                    (_, 0, 2) => 2,
+                    (0, _, 1) => -4,
+                    _ => 0,
+                };
+            }
+        }
+    }
+
+    // Open lines can be completed from either end, so they are hard to stop
+    score += board.count_open_threes(disk) as i32 * 10;
+    score += board.count_open_twos(disk) as i32;
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Turn;
+
+    #[test]
+    fn empty_4x4_board_is_a_draw() {
+        let disk = Turn::FIRST.to_disk();
+        assert_eq!(solve(&Board::new(4, 4, 2), disk), GameValue::Draw);
+    }
+
+    #[test]
+    fn completing_a_line_wins_in_one_move() {
+        let board = Board::from_ascii(
+            "....
+             B...
+             BB..
+             RRR.",
+        )
+        .unwrap();
+        assert_eq!(solve(&board, Turn::FIRST.to_disk()), GameValue::Win(1));
+    }
+
+    #[test]
+    fn boards_too_big_to_solve_are_unknown() {
+        // 4x6 is just past SOLVER_MAX_CELLS
+        let disk = Turn::FIRST.to_disk();
+        assert_eq!(solve(&Board::new(4, 6, 2), disk), GameValue::Unknown);
+        assert_eq!(solve(&Board::new(6, 7, 2), disk), GameValue::Unknown);
+    }
+
+    #[test]
+    fn timed_search_keeps_to_its_budget() {
+        // Not a board size of the opening book, so the move is searched for
+        let board = Board::from_ascii(
+            "........
+             ........
+             ........
+             ........
+             ........
+             B.B.....
+             RRR.B...",
+        )
+        .unwrap();
+        let budget = Duration::from_millis(300);
+        let start = Instant::now();
+        let col = best_move_timed(&board, Turn::FIRST.to_disk(), budget);
+        // The last search can run over a little, when it takes longer than expected
+        assert!(start.elapsed() < budget + Duration::from_secs(1));
+        assert_eq!(col, Some(3));
+
+        // A full board has no move left
+        let full = Board::from_ascii("RB\nBR").unwrap();
+        assert_eq!(best_move_timed(&full, Turn::FIRST.to_disk(), budget), None);
+    }
+}
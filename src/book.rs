@@ -0,0 +1,99 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::game::Board;
+
+// Opening book bundled with the game, worked out ahead of time by a deeper search than the AI's
+const OPENING_BOOK: &str = include_str!("../assets/opening_book.json");
+
+// The best moves for the first few moves of a game by the standard rules on one board size,
+// by the Zobrist hash of the position (see Board::zobrist_hash)
+#[derive(Deserialize, Default)]
+pub struct OpeningBook {
+    rows: i32,
+    cols: i32,
+    moves: HashMap<u64, i32>,
+}
+
+impl OpeningBook {
+    // The book bundled with the game, read the first time it is needed
+    pub fn bundled() -> &'static OpeningBook {
+        static BOOK: OnceLock<OpeningBook> = OnceLock::new();
+        BOOK.get_or_init(|| {
+            serde_json::from_str(OPENING_BOOK).unwrap_or_else(|err| {
+                warn!("Failed to read opening book: {}", err);
+                OpeningBook::default()
+            })
+        })
+    }
+
+    // The book move for the position with the hash, None if it isn't in the book
+    pub fn lookup(&self, hash: u64) -> Option<i32> {
+        self.moves.get(&hash).copied()
+    }
+
+    // The book move for the player to move on the board, if the book was made for this kind of game
+    pub fn book_move(&self, board: &Board) -> Option<i32> {
+        if !board.has_standard_rules() || board.rows != self.rows || board.cols != self.cols {
+            return None;
+        }
+        self.lookup(board.zobrist_hash())
+            .filter(|&col| !board.column_full(col))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{ai, game::Turn};
+
+    #[test]
+    fn empty_board_is_in_the_bundled_book() {
+        let board = Board::new(6, 7, 2);
+        assert_eq!(board.zobrist_hash(), 0);
+        assert_eq!(OpeningBook::bundled().lookup(0), Some(3));
+        assert_eq!(OpeningBook::bundled().book_move(&board), Some(3));
+    }
+
+    #[test]
+    fn book_move_is_played_as_it_is() {
+        // A move no search would pick, so it can only come from the book
+        let book = OpeningBook {
+            rows: 6,
+            cols: 7,
+            moves: HashMap::from([(0, 0)]),
+        };
+        assert_eq!(book.book_move(&Board::new(6, 7, 2)), Some(0));
+    }
+
+    #[test]
+    fn other_games_are_not_in_the_book() {
+        let book = OpeningBook::bundled();
+        assert_eq!(book.book_move(&Board::new(7, 8, 2)), None);
+        let mut board = Board::new(6, 7, 2);
+        board.rocks.push((5, 3));
+        assert_eq!(book.book_move(&board), None);
+    }
+
+    #[test]
+    fn ai_plays_the_book_move() {
+        let book = OpeningBook::bundled();
+        let (red, mut second) = (Turn::FIRST.to_disk(), Turn::FIRST);
+        second.next(2);
+        let mut board = Board::new(6, 7, 2);
+        board.drop_disk(3, red);
+        for (board, disk) in [(Board::new(6, 7, 2), red), (board, second.to_disk())] {
+            let col = book.book_move(&board).expect("the position is in the book");
+            // A search this shallow picks at random between equally good columns,
+            // so the same move every time can only come from the book
+            for _ in 0..20 {
+                assert_eq!(ai::best_move(&board, disk, 1), Some(col));
+                assert_eq!(ai::best_move_timed(&board, disk, Duration::ZERO), Some(col));
+            }
+        }
+    }
+}
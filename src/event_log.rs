@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+
+use crate::{game::Turn, GameState};
+
+// Target of the game events in the log, so they can be shown on their own
+// (e.g. with RUST_LOG=connect4::events=debug)
+pub const EVENT_TARGET: &str = "connect4::events";
+
+// Something that happened in the game, worth recording for debugging
+pub enum GameEvent<'a> {
+    // A disk was dropped into the column by the player, landing in the row
+    Drop {
+        col: i32,
+        turn: Turn,
+        row: i32,
+    },
+    // The player won with the line between the two disks (for a square, its corners)
+    Win {
+        winner: Turn,
+        from: (i32, i32),
+        to: (i32, i32),
+    },
+    // The game was written to a file, with the error if it failed
+    Save {
+        path: &'a str,
+        error: Option<String>,
+    },
+    // A game was read from a file, with the error if it failed
+    Load {
+        path: &'a str,
+        error: Option<String>,
+    },
+    // The app moved on to another state
+    StateChange {
+        state: GameState,
+    },
+}
+
+// Writes the event to the log. Events are only debug messages, so normal play stays quiet
+// (bevy shows info and up by default), but failures are warnings.
+pub fn log_event(event: &GameEvent) {
+    match event {
+        &GameEvent::Drop { col, turn, row } => {
+            debug!(target: EVENT_TARGET, event = "drop", col, turn = %turn, row);
+        }
+        &GameEvent::Win { winner, from, to } => {
+            debug!(
+                target: EVENT_TARGET,
+                event = "win",
+                winner = %winner,
+                from = ?from,
+                to = ?to,
+            );
+        }
+        GameEvent::Save { path, error: None } => {
+            debug!(target: EVENT_TARGET, event = "save", path);
+        }
+        GameEvent::Save {
+            path,
+            error: Some(error),
+        } => {
+            warn!(target: EVENT_TARGET, event = "save", path, error = %error);
+        }
+        GameEvent::Load { path, error: None } => {
+            debug!(target: EVENT_TARGET, event = "load", path);
+        }
+        GameEvent::Load {
+            path,
+            error: Some(error),
+        } => {
+            warn!(target: EVENT_TARGET, event = "load", path, error = %error);
+        }
+        GameEvent::StateChange { state } => {
+            debug!(target: EVENT_TARGET, event = "state", state = ?state);
+        }
+    }
+}
+
+// Logs every change of the app state (also the first one, when the app starts).
+// Bevy's state driver marks the state as changed every frame, so it is compared with the last
+// one logged instead.
+pub fn log_state_changes(state: Res<State<GameState>>, mut logged: Local<Option<GameState>>) {
+    let current = *state.current();
+    if *logged != Some(current) {
+        *logged = Some(current);
+        log_event(&GameEvent::StateChange { state: current });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use bevy::utils::tracing::{
+        field::{Field, Visit},
+        span, subscriber, Event, Metadata, Subscriber,
+    };
+
+    use super::*;
+    use crate::game::play_scripted_game;
+
+    // Keeps the fields of every game event logged, as text
+    #[derive(Clone, Default)]
+    struct EventCapture(Arc<Mutex<Vec<String>>>);
+
+    struct Fields(String);
+
+    impl Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0 += &format!("{}={:?} ", field.name(), value);
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0 += &format!("{}={} ", field.name(), value);
+        }
+    }
+
+    impl Subscriber for EventCapture {
+        fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+            metadata.target() == EVENT_TARGET
+        }
+
+        fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+            span::Id::from_u64(1)
+        }
+
+        fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut fields = Fields(String::new());
+            event.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0.trim_end().to_string());
+        }
+
+        fn enter(&self, _: &span::Id) {}
+
+        fn exit(&self, _: &span::Id) {}
+    }
+
+    #[test]
+    fn scripted_win_logs_the_win() {
+        let capture = EventCapture::default();
+        subscriber::with_default(capture.clone(), || {
+            // Red drops into the first four columns, blue stacks on them
+            play_scripted_game(&[0, 0, 1, 1, 2, 2, 3]);
+        });
+
+        // The state is logged once for each change, not every frame
+        let events = capture.0.lock().unwrap();
+        assert_eq!(
+            *events,
+            [
+                "event=state state=Playing",
+                "event=drop col=0 turn=Red row=5",
+                "event=drop col=0 turn=Blue row=4",
+                "event=drop col=1 turn=Red row=5",
+                "event=drop col=1 turn=Blue row=4",
+                "event=drop col=2 turn=Red row=5",
+                "event=drop col=2 turn=Blue row=4",
+                "event=drop col=3 turn=Red row=5",
+                "event=win winner=Red from=(5, 0) to=(5, 3)",
+                "event=state state=Menu",
+            ]
+        );
+    }
+}
@@ -1,193 +1,1097 @@
-#![allow(clippy::too_many_arguments)]
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
 //uses the Bevy and Serde libraries.
-use bevy::{prelude::*, sprite::MaterialMesh2dBundle};
-use serde::{Deserialize, Serialize};//serializing and deserializing data
+use std::{
+    collections::VecDeque,
+    ops::{Index, IndexMut},
+    time::Duration,
+};
 
-use crate::{GameChange, GameState, MainMenuInfo, BACKGROUND_COLOR, WINDOW_HEIGHT, WINDOW_WIDTH};
+use bevy::{
+    app::AppExit, prelude::*, sprite::MaterialMesh2dBundle, transform::TransformSystem,
+    window::FileDragAndDrop,
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};//serializing and deserializing data
+
+use crate::{
+    accessibility::{announce, Announcement},
+    ai::{best_move, best_move_timed, solve, GameValue},
+    event_log::{log_event, GameEvent},
+    help::{
+        ANALYSIS_KEY, APPLY_APPEARANCE_KEY, EXPORT_SVG_KEY, GRAVITY_FLIP_KEY, GROW_BOARD_KEY,
+        HELP_KEYS, HINT_KEY, KEEP_APPEARANCE_KEY, KEEP_GAME_KEY, LOAD_DROPPED_KEY, PRINT_BOARD_KEY,
+        RANDOM_MOVE_KEY, RESTART_KEY, REVIEW_BACK_KEY, REVIEW_FIRST_KEY, REVIEW_FORWARD_KEY,
+        REVIEW_LAST_KEY, UNDO_KEY, VIEW_ROTATION_KEY,
+    },
+    puzzles::{todays_puzzle, ActivePuzzle, SECONDS_PER_DAY},
+    replay::export_frames,
+    settings::{
+        AiDelay, AiSearchTime, AnimationSpeed, AutoForced, Autosave, BestTimes, BoardLayout,
+        CircleVertices, Countdown, DemoDelay, DropInputRegion, FallGravity, FogOfWar, GhostAlpha,
+        HistoryDisplayMode, HistoryLimit, LossWarning, PassScreen, ReducedMotion, RequireRelease,
+        ShowAiIntent, ShowGhost, ShowMoveNumbers, ShowParticles, ShowTrajectory,
+        SimultaneousWinRule, SwapColors, ThreatGlow, UndoLimit, WinLineStyle, WinLineThickness,
+    },
+    theme::{Theme, ThemeColors},
+    GameChange, GameState, MainMenuInfo, FONT_PATH, WINDOW_HEIGHT, WINDOW_WIDTH,
+};
 //Defining UI 
-const BOARD_COLOR: Color = Color::rgb(1.0, 1.0, 1.0);
 const BOARD_SCALE: Vec2 = Vec2::new(1.0, 0.9);
 const HOLE_PADDING: f32 = 0.9;
 const DISK_PADDING: f32 = 0.95 * HOLE_PADDING;
-const WINNER_LINE_HEIGHT: f32 = 0.5;
+// How much bigger than a disk the rings around the winning disks are, for each bit of thickness
+const WINNER_RING_SCALE: f32 = 0.4;
+// Size of the disk outlines, compared to the disks
+const DISK_OUTLINE_SCALE: f32 = 1.15;
+
+// Players are numbered in turn order, each with its own name (and disk color in the theme)
+pub const MAX_PLAYERS: u8 = 4;
+pub const PLAYER_NAMES: [&str; MAX_PLAYERS as usize] = ["Red", "Blue", "Green", "Orange"];
+// Width of the trail from the ghost disk down to where the disk lands, relative to the disks
+const TRAJECTORY_TRAIL_WIDTH: f32 = 0.15;
+
+// Ring around the most recently placed disk, slightly bigger than the holes and pulsing
+const LAST_MOVE_SCALE: f32 = 1.1;
+const LAST_MOVE_PULSE: f32 = 0.05;
+const LAST_MOVE_PULSE_SPEED: f32 = 4.0;
+
+// Number of disks in a line needed to win, unless the board says otherwise
+pub const DEFAULT_WIN_LENGTH: i32 = 4;
+
+// The save slot used by the menu, and the one written automatically when quitting
+const SAVE_FILE: &str = "save.json";
+const AUTOSAVE_FILE: &str = "autosave.json";
+// Written by the board editor, and loaded like any save (e.g. by dropping it onto the window)
+const SCENARIO_FILE: &str = "scenario.json";
+// Picture of the board, written with the export key
+const SVG_FILE: &str = "board.svg";
+
+// Flash over a full column that was clicked, fading out
+const FULL_COLUMN_FLASH_COLOR: Color = Color::rgba(0.5, 0.5, 0.5, 0.6);
+const FULL_COLUMN_FLASH_TIME: f32 = 0.3;
+
+// Size of the move numbers on the disks, compared to the disks
+const MOVE_NUMBER_SIZE: f32 = 0.4;
+
+// How long notices in the game (like about a corrected save) stay on screen
+const NOTICE_TIME: f32 = 5.0;
+// How many moves ahead the hints look, a bit further than the AI opponent
+const HINT_DEPTH: u32 = 6;
+
+// How long confirmations (like that the game was saved) stay on screen, there's less to read
+const TOAST_TIME: f32 = 2.0;
+
+// Seconds counted down before games against or between AIs, when turned on in the settings
+const COUNTDOWN_SECONDS: f32 = 3.0;
+
+// How long a second press of the restart key is waited for, to restart a game in progress
+const RESTART_CONFIRM_TIME: f32 = 2.0;
+
+// How long a finished demo game stays on screen before the next one starts (in seconds)
+const DEMO_RESTART_DELAY: f32 = 2.0;
+
+// How long a won game of a match stays on screen before the next one starts (in seconds)
+const MATCH_NEXT_GAME_DELAY: f32 = 2.0;
+
+// Rocks are permanently blocked cells in the rocks variant, gray and one for every two columns
+pub const ROCK_COLOR: Color = Color::rgb(0.5, 0.5, 0.5);
+const ROCKS_PER_COL: f32 = 0.5;
+
+// Growing the board stops at this many rows, the disks would get too small to see well
+const MAX_GROWN_ROWS: i32 = 12;
+
+// Boards can't have more rows or columns than this (saves with more are refused,
+// they can't be from a real game)
+pub const MAX_BOARD_SIZE: i32 = 50;
 
-const RED_DISK_COLOR: Color = Color::rgb(1.0, 0.0, 0.0);
-const BLUE_DISK_COLOR: Color = Color::rgb(0.0, 0.0, 1.0);
-pub const WINNER_COLOR: Color = Color::rgb(1.0, 1.0, 0.0);
+// Moves shown in the history text, older ones are cut off
+const HISTORY_TEXT_MOVES: usize = 30;
+
+// Marker above each column that can still take a disk, and its height (in pixels)
+const LEGAL_COLUMN_COLOR: Color = Color::rgba(0.8, 0.8, 0.8, 0.6);
+const LEGAL_COLUMN_HEIGHT: f32 = 6.0;
+
+// Strip above the board in the color of the player to move, faint so it's only noticed in passing
+const TURN_STRIP_ALPHA: f32 = 0.12;
+
+// Buttons over the strip above each column, faint so the ghost disk shows through:
+// normally, under the mouse, and greyed out when the column is full or moves can't be made
+const COLUMN_BUTTON_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.03);
+const COLUMN_BUTTON_HOVER_COLOR: Color = Color::rgba(1.0, 1.0, 1.0, 0.15);
+const COLUMN_BUTTON_DISABLED_COLOR: Color = Color::rgba(0.3, 0.3, 0.3, 0.4);
+// The glow of the columns where an open line of three of the player to move, or of another
+// player, ends (see ThreatGlow)
+const COLUMN_BUTTON_OWN_THREAT_COLOR: Color = Color::rgba(0.2, 1.0, 0.3, 0.2);
+const COLUMN_BUTTON_OTHER_THREAT_COLOR: Color = Color::rgba(1.0, 0.3, 0.2, 0.2);
+
+// Particles bursting from the winning lines: how many per disk in a line, at most how many in total,
+// how long they last (in seconds), how fast they fly and fall (in pixels per second),
+// and how big they are compared to a disk
+const PARTICLES_PER_DISK: usize = 10;
+const MAX_PARTICLES: usize = 150;
+const PARTICLE_LIFE: f32 = 1.0;
+const PARTICLE_SPEED: f32 = 300.0;
+const PARTICLE_GRAVITY: f32 = 600.0;
+const PARTICLE_SIZE: f32 = 0.15;
+
+// How far (at most, in pixels) and how long (in seconds) the camera shakes when someone wins
+const CAMERA_SHAKE_STRENGTH: f32 = 8.0;
+const CAMERA_SHAKE_TIME: f32 = 0.3;
+
+// The ghost trail: faded copies of the ghost disk left behind as it moves between columns
+const GHOST_TRAIL_LENGTH: usize = 4;
+const GHOST_TRAIL_LIFE: f32 = 0.25;
+
+// The gray the disks of the other players are shown in with fog of war, and how long a dropped
+// disk shows its color before that (in seconds)
+const FOG_DISK_COLOR: Color = Color::rgb(0.75, 0.75, 0.75);
+const FOG_REVEAL_TIME: f32 = 1.0;
+
+// The weakest pull on falling disks (in pixels per second squared), so they always land
+const MIN_FALL_GRAVITY: f32 = 100.0;
 
 // Holds all the materials used by the game
 #[derive(Resource)]
 struct MaterialHandles {
     background: Handle<ColorMaterial>,
-    red_disk: Handle<ColorMaterial>,
-    blue_disk: Handle<ColorMaterial>,
-    red_ghost_disk: Handle<ColorMaterial>,
-    blue_ghost_disk: Handle<ColorMaterial>,
+    // One material per player, indexed by the player number
+    disks: Vec<Handle<ColorMaterial>>,
+    ghost_disks: Vec<Handle<ColorMaterial>>,
+    last_move: Handle<ColorMaterial>,
+    rock: Handle<ColorMaterial>,
+    disk_outline: Handle<ColorMaterial>,
+    fog_disk: Handle<ColorMaterial>,
 }
 
 impl MaterialHandles {
     fn get_disk_material(&self, disk: Disk) -> Handle<ColorMaterial> {
-        match disk {
-            //If the value is Disk::Red, it returns a clone of the red_disk field
-            Disk::Red => self.red_disk.clone(),
-            Disk::Blue => self.blue_disk.clone(),
+        self.disks[disk.0 as usize].clone()
+    }
+
+    fn get_ghost_disk_material(&self, ghost_disk: GhostDisk) -> Handle<ColorMaterial> {
+        self.ghost_disks[ghost_disk.0 as usize].clone()
+    }
+
+    // Sets the colors of all the materials to the ones of the theme
+    fn set_colors(
+        &self,
+        materials: &mut Assets<ColorMaterial>,
+        colors: &ThemeColors,
+        ghost_alpha: GhostAlpha,
+    ) {
+        let mut set_color = |handle: &Handle<ColorMaterial>, color: Color| {
+            if let Some(material) = materials.get_mut(handle) {
+                material.color = color;
+            }
+        };
+
+        set_color(&self.background, colors.background);
+        set_color(&self.last_move, colors.last_move);
+        set_color(&self.disk_outline, colors.disk_outline);
+        for (player, &color) in colors.disks.iter().enumerate() {
+            set_color(&self.disks[player], color);
+            set_color(&self.ghost_disks[player], ghost_color(color, ghost_alpha));
+        }
+    }
+
+    // Makes the ghost disks of all players as see-through as the setting says
+    fn set_ghost_alpha(&self, materials: &mut Assets<ColorMaterial>, ghost_alpha: GhostAlpha) {
+        for handle in &self.ghost_disks {
+            if let Some(material) = materials.get_mut(handle) {
+                material.color.set_a(ghost_alpha.0);
+            }
         }
     }
 }
 
+// The see-through version of a disk color, for the ghost disks
+fn ghost_color(mut color: Color, ghost_alpha: GhostAlpha) -> Color {
+    color.set_a(ghost_alpha.0);
+    color
+}
+
 // Holds all the meshes used by the game
 #[derive(Resource)]
 struct MeshHandles {
     circle: Handle<Mesh>,
 }
 
-// Used to identify the ghost disks (used to show where the next disk will be placed)
+// A circle as wide as a cell scaled to 1, with as many corners as the setting asks for
+pub fn circle_mesh(vertices: CircleVertices) -> Mesh {
+    shape::Circle {
+        radius: 0.5,
+        vertices: vertices.0,
+    }
+    .into()
+}
+
+// Used to identify the ghost disks (used to show where the next disk will be placed),
+// holds the number of the player it belongs to
+#[derive(Component, PartialEq, Eq, Clone, Copy)]
+struct GhostDisk(u8);
+
+// Parts of the trajectory preview, shown along with the ghost disk of the same player
 #[derive(Component, PartialEq, Eq, Clone, Copy)]
-enum GhostDisk {
-    Red,
-    Blue,
+enum TrajectoryPreview {
+    // Faint line from the ghost disk down to where the disk lands
+    Trail,
+    // Faint disk in the cell it lands in
+    Landing,
 }
-//This enum is used to define the current turn of the game, 
-//and it could be used to switch the turn between the two players, Red and Blue.
-#[derive(Resource, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
-pub enum Turn {
-    Red,
-    Blue,
+
+// Used to identify the ring marking the last move
+#[derive(Component)]
+struct LastMoveMarker;
+
+// The number of the move a disk was dropped in, shown on it
+#[derive(Component)]
+struct MoveNumber;
+
+// Faint disk where the AI is about to drop, shown while it thinks (if turned on in the settings)
+#[derive(Component)]
+struct AiIntentMarker;
+
+// Used to identify the board sprite, which takes the board color of the theme
+#[derive(Component)]
+struct BoardSprite;
+
+// Used to identify the winning line, which takes the winner color of the theme
+// (the rings around the winning disks are part of it too)
+#[derive(Component)]
+struct WinnerLine;
+
+// Ring around a winning disk, with its own material in the winner color
+#[derive(Component)]
+struct WinnerRing;
+
+// Flash shown over a full column when it's clicked, removed when the timer is done
+#[derive(Component)]
+struct ColumnFlash(Timer);
+
+// Marker above a column that isn't full yet, hidden once it is
+#[derive(Component)]
+struct LegalColumnMarker(i32);
+
+// Buttons below the board in the editor (their text carries the same component, for the label)
+#[derive(Component, Clone, Copy)]
+enum EditorButton {
+    // Switches the player to move in the edited position
+    Turn,
+    // Starts a game from the edited position
+    Play,
+    // Writes the edited position to the scenario file
+    Save,
+}
+
+impl EditorButton {
+    fn label(self, turn: Turn) -> String {
+        match self {
+            EditorButton::Turn => format!("To Move: {}", turn),
+            EditorButton::Play => "Play".to_string(),
+            EditorButton::Save => "Save".to_string(),
+        }
+    }
 }
-//This implementation allows the Turn enum to be printed to the console or in any other place,
+
+// Used to identify the parts of the editor shown over the board, removed when it's left
+#[derive(Component)]
+struct EditorUi;
+
+// The positions of a finished game being reviewed, after each move (reconstructed from the
+// move history once when the review starts), and the one shown
+#[derive(Resource)]
+struct Review {
+    boards: Vec<Board>,
+    ply: usize,
+}
+
+// Text on a column button, showing how many threats dropping a disk there creates for the player
+// to move (+) and allows for the next one (-), while the analysis is shown
+#[derive(Component)]
+struct ThreatCounter {
+    col: i32,
+}
+
+// Whether the analysis is shown, toggled with the analysis key
+#[derive(Resource, Default)]
+struct ShowAnalysis(bool);
+
+// Used to identify the text showing which move is reviewed
+#[derive(Component)]
+struct ReviewText;
+
+// Used to identify the strip above the board, tinted in the color of the player to move
+#[derive(Component)]
+struct TurnStrip;
+
+// Button over the strip above a column, dropping a disk into it when clicked
+#[derive(Component)]
+struct ColumnButton {
+    col: i32,
+}
+
+// A particle of the winner celebration, moving until its life is over (fading out on the way)
+#[derive(Component)]
+struct Particle {
+    velocity: Vec2,
+    life: Timer,
+}
+
+// The shake of the camera after a win: how far it can be off in pixels, shrinking as the time
+// left (t) runs out. While shaking, base is where the camera really is, to put it back exactly.
+#[derive(Resource, Default)]
+struct CameraShake {
+    strength: f32,
+    t: f32,
+    base: Option<Vec3>,
+}
+
+// A faded copy of the ghost disk where it was before it moved, fading out until its life is over
+#[derive(Component)]
+struct GhostTrailDisk(Timer);
+
+// The disks of the ghost trail, oldest first, at most GHOST_TRAIL_LENGTH of them
+// (some may already have faded out, or been removed with the game)
+#[derive(Resource, Default)]
+struct GhostTrail(VecDeque<Entity>);
+
+// Used to identify text in the color of a player (like the move history)
+#[derive(Component)]
+struct PlayerText(Turn);
+
+// Finds the player number from its name (used when loading saves)
+fn player_from_name(name: &str) -> Result<u8, String> {
+    PLAYER_NAMES
+        .iter()
+        .position(|&player_name| player_name == name)
+        .map(|index| index as u8)
+        .ok_or_else(|| format!("unknown player: {}", name))
+}
+
+//This struct is used to define the current turn of the game, holding the number of the player
+//that has to move. It is saved by name ("Red", "Blue", ...) so older saves still load.
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(into = "&'static str", try_from = "String")]
+pub struct Turn(u8);
+
+//This implementation allows the Turn to be printed to the console or in any other place,
 // where the std::fmt::Display trait is used.
 impl std::fmt::Display for Turn {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                //returns "Red" if the value of self is Turn::Red and 
-                //"Blue" if the value of self is Turn::Blue.
-                Turn::Red => "Red",
-                Turn::Blue => "Blue",
-            }
-        )
+        write!(f, "{}", PLAYER_NAMES[self.0 as usize])
+    }
+}
+
+impl From<Turn> for &'static str {
+    fn from(turn: Turn) -> Self {
+        PLAYER_NAMES[turn.0 as usize]
+    }
+}
+
+impl TryFrom<String> for Turn {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        player_from_name(&name).map(Turn)
     }
 }
 
-// Some helpful functions for converting the Turn enum
-//method takes a mutable reference to self and changes the value of self
+// Some helpful functions for converting the Turn
 impl Turn {
-    fn next(&mut self) {
-        *self = match self {
-            Turn::Red => Turn::Blue,
-            Turn::Blue => Turn::Red,
-        }
+    // The first player (Red) always starts
+    pub const FIRST: Turn = Turn(0);
+
+    //method takes a mutable reference to self and moves on to the next player
+    pub fn next(&mut self, players: u8) {
+        self.0 = (self.0 + 1) % players;
     }
-//takes self as an argument and returns a Disk enum variant, 
-    fn to_disk(self) -> Disk {
-        match self {
-            Turn::Red => Disk::Red,
-            Turn::Blue => Disk::Blue,
-        }
+//returns the number of the player, counting from 0 for the first one
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+//returns the player that moved before self, without changing self
+    fn previous(self, players: u8) -> Turn {
+        Turn((self.0 + players - 1) % players)
     }
-//method takes self as an argument and returns a GhostDisk enum variant
+//takes self as an argument and returns the Disk of the same player
+    pub fn to_disk(self) -> Disk {
+        Disk(self.0)
+    }
+//method takes self as an argument and returns the GhostDisk of the same player
     fn to_ghost_disk(self) -> GhostDisk {
+        GhostDisk(self.0)
+    }
+// method takes self as an argument and returns the color of the player in the theme
+    pub fn to_color(self, colors: &ThemeColors) -> Color {
+        colors.disks[self.0 as usize]
+    }
+}
+
+// Which way the disks fall in each column of the board
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum DropPattern {
+    // Every column fills from the bottom
+    #[default]
+    Normal,
+    // Every other column (the 2nd, 4th, ...) fills from the top, the disks rise instead of falling
+    Alternating,
+    // Every column fills from the bottom, but the first disk in a column can be placed at any
+    // height (a house rule), the disks after it stack on it
+    Floating,
+}
+
+impl DropPattern {
+    // Whether disks dropped into the column fall down (or rise up)
+    pub fn falls_down(self, col: i32) -> bool {
         match self {
-            Turn::Red => GhostDisk::Red,
-            Turn::Blue => GhostDisk::Blue,
+            DropPattern::Normal | DropPattern::Floating => true,
+            DropPattern::Alternating => col % 2 == 0,
         }
     }
-// method takes self as an argument and returns a Color variant
-    fn to_color(self) -> Color {
+
+    // The pattern after this one, wrapping around (used by the menu to cycle through them)
+    pub fn next(self) -> DropPattern {
         match self {
-            Turn::Red => RED_DISK_COLOR,
-            Turn::Blue => BLUE_DISK_COLOR,
+            DropPattern::Normal => DropPattern::Alternating,
+            DropPattern::Alternating => DropPattern::Floating,
+            DropPattern::Floating => DropPattern::Normal,
+        }
+    }
+}
+
+// What a player has to form with their disks to win
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum WinCondition {
+    // win_length disks in a row, column or diagonal
+    #[default]
+    Line,
+    // A 2x2 square of disks
+    Square,
+}
+
+// Which directions lines can go in to win (with the line win condition), e.g. only diagonals
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WinDirections {
+    pub horizontal: bool,
+    pub vertical: bool,
+    pub diagonal: bool,
+}
+
+impl Default for WinDirections {
+    fn default() -> Self {
+        WinDirections::ALL
+    }
+}
+
+impl WinDirections {
+    pub const ALL: WinDirections = WinDirections {
+        horizontal: true,
+        vertical: true,
+        diagonal: true,
+    };
+    pub const DIAGONAL: WinDirections = WinDirections {
+        horizontal: false,
+        vertical: false,
+        diagonal: true,
+    };
+    pub const STRAIGHT: WinDirections = WinDirections {
+        horizontal: true,
+        vertical: true,
+        diagonal: false,
+    };
+
+    // Whether lines going the way of the step (row_delta, col_delta) count
+    pub fn allows(self, row_delta: i32, col_delta: i32) -> bool {
+        match (row_delta, col_delta) {
+            (0, _) => self.horizontal,
+            (_, 0) => self.vertical,
+            _ => self.diagonal,
         }
     }
 }
 
+// Offsets of the cells of a 2x2 square from its top left cell, as (row, col)
+const SQUARE_CELLS: [(i32, i32); 4] = [(0, 0), (0, 1), (1, 0), (1, 1)];
+
+// Why a disk can't be dropped into a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropError {
+    // The column is not on the board
+    OutOfRange,
+    // The column has no empty slots left
+    ColumnFull,
+}
+
+// Characters of the cells in the text form of a board (see Board::to_ascii), the disks by player
+const ASCII_DISKS: [char; MAX_PLAYERS as usize] = ['R', 'B', 'G', 'O'];
+const ASCII_ROCK: char = '#';
+const ASCII_EMPTY: char = '.';
+
+// Size of a cell in the SVG form of a board (see Board::to_svg), the rest is relative to it
+const SVG_CELL_SIZE: f32 = 100.0;
+const SVG_CORNER_RADIUS: f32 = 0.2;
+const SVG_WIN_LINE_WIDTH: f32 = 0.5;
+
+// Fill or stroke attributes of an SVG element in the color, with its alpha as the opacity
+fn svg_paint(attribute: &str, color: Color) -> String {
+    let [r, g, b, a] = color.as_rgba_f32();
+    format!(
+        "{attribute}=\"rgb({:.0},{:.0},{:.0})\" {attribute}-opacity=\"{}\"",
+        r * 255.0,
+        g * 255.0,
+        b * 255.0,
+        a
+    )
+}
+
+// Why a board couldn't be read from its text form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    // There are no rows
+    Empty,
+    // A row is longer or shorter than the first one
+    UnevenRows,
+    // A character that isn't a disk, rock or empty cell
+    UnknownCell(char),
+}
+
 // Used to stop click from menu spreading to game
 #[derive(Resource)]
 struct SkipClick(bool);
 
+// Whether the mouse button is still held down since it dropped a disk. With the release setting,
+// it has to be let go before it can drop another one.
+#[derive(Resource, Default)]
+struct DropRelease {
+    held: bool,
+}
+
+impl DropRelease {
+    // Forgets the last drop once the button is up again (called every frame)
+    fn update(&mut self, pressed: bool) {
+        if !pressed {
+            self.held = false;
+        }
+    }
+
+    // Whether a press may drop a disk, marking the button as held by it if so
+    fn press(&mut self, require_release: bool) -> bool {
+        if require_release && self.held {
+            return false;
+        }
+        self.held = true;
+        true
+    }
+}
+
+// A column clicked during the AI's turn, played as soon as it's the player's turn again
+// (if there is still room in it by then)
+#[derive(Resource, Default)]
+struct QueuedMove(Option<i32>);
+
+impl QueuedMove {
+    // Takes the queued column to play it, if a disk can still be dropped there
+    fn take_playable(&mut self, board: &Board) -> Option<i32> {
+        self.0.take().filter(|&col| board.try_drop(col).is_ok())
+    }
+}
+
+// Set while moves can't be made by the players: it's the AI's turn,
+// the next game of a match is about to start, or the board is covered for passing the device
+#[derive(Resource)]
+struct InputLocked(bool);
+
+// Which way round the board is shown. In hot seat play the second player may sit on the other
+// side of the screen, so the view can be turned around for them (the board itself stays the same).
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq)]
+enum ViewRotation {
+    #[default]
+    Upright,
+    HalfTurn,
+}
+
+impl ViewRotation {
+    fn toggled(self) -> Self {
+        match self {
+            ViewRotation::Upright => ViewRotation::HalfTurn,
+            ViewRotation::HalfTurn => ViewRotation::Upright,
+        }
+    }
+
+    // Center of the board and the strip above it in the window, which the view turns around
+    // (the window position starts at the bottom left)
+    fn center(layout: BoardLayout) -> Vec2 {
+        BoardArea::new(layout).center + Vec2::new(WINDOW_WIDTH, WINDOW_HEIGHT) / 2.0
+    }
+
+    // Where a position in the window (like the mouse) would be if the board was shown upright
+    fn to_upright(self, pos: Vec2, layout: BoardLayout) -> Vec2 {
+        match self {
+            ViewRotation::Upright => pos,
+            ViewRotation::HalfTurn => 2.0 * Self::center(layout) - pos,
+        }
+    }
+
+    // Turns the camera so it shows the board this way round. Everything the camera draws is turned
+    // around the center of the board, the UI is laid out on its own so the text stays readable.
+    fn apply_to_camera(self, camera: &mut Transform, layout: BoardLayout) {
+        let center = BoardArea::new(layout).center;
+        let (offset, angle) = match self {
+            ViewRotation::Upright => (Vec2::ZERO, 0.0),
+            ViewRotation::HalfTurn => (2.0 * center, std::f32::consts::PI),
+        };
+        camera.translation = offset.extend(camera.translation.z);
+        camera.rotation = Quat::from_rotation_z(angle);
+    }
+}
+
+// How the current game is played
+#[derive(Resource, Clone, Copy, PartialEq, Eq)]
+pub enum GameMode {
+    // Players take turns on the same computer
+    HotSeat,
+    // Two AIs play each other, restarting when the game is over (used as a demo)
+    AiVsAi { depth: u32 },
+    // A player against the AI, which plays the given turn
+    VsAi { depth: u32, ai: Turn },
+    // A player against their own moves from their last game against the AI, played back by the
+    // given turn (see MirrorMoves)
+    MirrorHuman { ai: Turn },
+}
+
+impl GameMode {
+    // Whether a person places the disk for this turn (with the mouse)
+    fn human_turn(self, turn: Turn) -> bool {
+        match self {
+            GameMode::HotSeat => true,
+            GameMode::AiVsAi { .. } => false,
+            GameMode::VsAi { ai, .. } | GameMode::MirrorHuman { ai } => turn != ai,
+        }
+    }
+}
+
+// A best of N series of games, inserted by the game while a match is being played.
+// The first player to win the majority of the games wins the match.
+#[derive(Resource)]
+pub struct Match {
+    // Games a player has to win to win the match
+    pub target_wins: u32,
+    // Games won so far by each player
+    wins: Vec<u32>,
+    // Games that ended without a winner
+    draws: u32,
+    // Pause after a game was won, before the next one starts (None while a game is played)
+    next_game: Option<Timer>,
+}
+
+impl Match {
+    pub fn best_of(games: u32, players: u8) -> Self {
+        Self {
+            target_wins: games / 2 + 1,
+            wins: vec![0; players as usize],
+            draws: 0,
+            next_game: None,
+        }
+    }
+
+    // Counts a game won by the player
+    pub fn record_win(&mut self, winner: Turn) {
+        self.wins[winner.0 as usize] += 1;
+    }
+
+    // Counts a game nobody won
+    pub fn record_draw(&mut self) {
+        self.draws += 1;
+    }
+
+    // The player that won the match, if someone has won enough games
+    pub fn winner(&self) -> Option<Turn> {
+        self.wins
+            .iter()
+            .position(|&wins| wins >= self.target_wins)
+            .map(|player| Turn(player as u8))
+    }
+
+    // Games won by each player in turn order, and the draws if there were any, e.g. "2-1 (1 drawn)"
+    pub fn score(&self) -> String {
+        let wins: Vec<String> = self.wins.iter().map(u32::to_string).collect();
+        if self.draws > 0 {
+            format!("{} ({} drawn)", wins.join("-"), self.draws)
+        } else {
+            wins.join("-")
+        }
+    }
+
+    // Whether the last game is over and the next one hasn't started yet
+    fn between_games(&self) -> bool {
+        self.next_game.is_some()
+    }
+
+    // Number of games in the match (matches are always an odd number of games)
+    fn games(&self) -> u32 {
+        self.target_wins * 2 - 1
+    }
+}
+
+// Paces the AI vs AI demo, between moves and before restarting a finished game
+#[derive(Resource)]
+struct DemoTimer {
+    timer: Timer,
+    game_over: bool,
+}
+
+// The AI's move when playing against it, picked as soon as its turn starts but only played
+// once the timer (the minimum think time) is done
+#[derive(Resource)]
+struct AiMove {
+    timer: Timer,
+    col: Option<i32>,
+}
+
+impl AiMove {
+    // Picks the column, to be played once the think time (in seconds) is over
+    fn pick(&mut self, col: i32, think_time: f32) {
+        self.col = Some(col);
+        self.timer.set_duration(Duration::from_secs_f32(think_time));
+        self.timer.reset();
+    }
+
+    // The picked column once the think time is over (then it's no longer picked), counting the
+    // time since the last frame towards it
+    fn ready(&mut self, delta: Duration) -> Option<i32> {
+        self.col?;
+        if !self.timer.tick(delta).finished() {
+            return None;
+        }
+        self.col.take()
+    }
+}
+
+// The columns the player dropped into in their last finished game against the AI,
+// which the mirror plays back in the next mirror game
+#[derive(Resource, Default)]
+struct MirrorMoves {
+    recorded: Vec<i32>,
+    // The columns the current mirror game plays, and how many of them were played
+    cols: Vec<i32>,
+    played: usize,
+}
+
+impl MirrorMoves {
+    // Remembers the moves of the player (everyone but the AI) from a finished game
+    fn record(&mut self, history: &MoveHistory, ai: Turn) {
+        self.recorded = history
+            .moves
+            .iter()
+            .filter(|record| record.turn != ai)
+            .map(|record| record.col)
+            .collect();
+    }
+
+    // Starts playing back the last recorded game from its first move
+    fn start(&mut self) {
+        self.cols = self.recorded.clone();
+        self.played = 0;
+    }
+
+    // The next recorded column, moved onto the board if it was smaller and skipped if it is full.
+    // Once they run out, a random column. None if the board is full.
+    fn next_col(&mut self, board: &Board) -> Option<i32> {
+        while let Some(&col) = self.cols.get(self.played) {
+            self.played += 1;
+            let col = col.clamp(0, board.cols - 1);
+            if !board.column_full(col) {
+                return Some(col);
+            }
+        }
+        board.random_move(&mut rand::thread_rng())
+    }
+}
+
 // Used to identify which entities are in the game, so they can be removed when the game ends
 #[derive(Component)]
 struct InGame;
 
+// To identify the disks placed on the board
+#[derive(Component)]
+struct BoardDisk;
+
+// A dropped (or flipped) disk on its way into its place, how fast it's going (in pixels per second)
+#[derive(Component)]
+struct FallingDisk {
+    target: Vec3,
+    speed: f32,
+}
+
+// How long a dropped disk still shows its color with fog of war
+#[derive(Component)]
+struct FogReveal(Timer);
+
+// Which players already flipped the board this game (each player can do it once)
+#[derive(Resource, Default)]
+struct GravityFlips([bool; MAX_PLAYERS as usize]);
+
 // To identify empty slots (not really used, but could be useful for any updates/other features)
 #[derive(Component)]
 struct EmptyDisk;
 
-#[derive(Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
-enum Disk {
-    Red,
-    Blue,
-}
-//This enum is used to represent the disks that are dropped into the game board, 
+#[derive(Component, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(into = "&'static str", try_from = "String")]
+pub struct Disk(u8);
+//This struct is used to represent the disks that are dropped into the game board, holding the number of the player.
 //It could be used to identify the disks on the board, and to change the color of the disks based on the current turn.
 impl Disk {
-    fn to_turn(self) -> Turn {
-        match self {
-            Disk::Red => Turn::Red,
-            Disk::Blue => Turn::Blue,
+    pub fn to_turn(self) -> Turn {
+        Turn(self.0)
+    }
+}
+
+impl From<Disk> for &'static str {
+    fn from(disk: Disk) -> Self {
+        PLAYER_NAMES[disk.0 as usize]
+    }
+}
+
+impl TryFrom<String> for Disk {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        player_from_name(&name).map(Disk)
+    }
+}
+
+// The part of the window filled by the board and the strip above it, in the world
+// (where the center of the window is at the origin)
+struct BoardArea {
+    center: Vec2,
+    size: Vec2,
+}
+
+impl BoardArea {
+    // The area the layout setting puts the board in. The classic layout fills the top of the
+    // window (as much of it as BOARD_SCALE gives), leaving the bottom for the move history.
+    fn new(layout: BoardLayout) -> Self {
+        let window = Vec2::new(WINDOW_WIDTH, WINDOW_HEIGHT);
+        if !layout.centered {
+            let size = window * BOARD_SCALE;
+            return Self {
+                center: Vec2::new(size.x - window.x, window.y - size.y) / 2.0,
+                size,
+            };
+        }
+
+        // Margins too big for the window still leave a board to play on
+        let margins = layout.margins;
+        Self {
+            center: Vec2::new(0.0, (margins.bottom - margins.top) / 2.0),
+            size: (window - Vec2::new(2.0 * margins.side, margins.top + margins.bottom))
+                .max(Vec2::ONE),
         }
     }
 }
 
 // Dimensions of the board and screen to simplify logic
 struct Dimensions {
-    board_scale_y: f32,
+    // Top left corner of the strip above the board in the world, where all positions start from
+    origin: Vec2,
+    width: f32,
+    // Height of the board under the strip
+    height: f32,
     row_height: f32,
     col_width: f32,
     scale: f32,
 }
 
+impl Dimensions {
+    // Center of the column in the world
+    fn col_x(&self, col: i32) -> f32 {
+        self.origin.x + self.col_width * (col as f32 + 0.5)
+    }
+
+    // The cell (as row and column) at a position in the world, like the center of a disk
+    // (the other way around from get_disk_transform)
+    fn cell_at(&self, pos: Vec2) -> (i32, i32) {
+        let col = ((pos.x - self.origin.x) / self.col_width).floor() as i32;
+        let row = ((self.origin.y - pos.y) / self.row_height - 1.0).floor() as i32;
+        (row, col)
+    }
+
+    // Top left corner of the strip above the board in the window (which starts at the bottom left)
+    fn window_origin(&self) -> Vec2 {
+        self.origin + Vec2::new(WINDOW_WIDTH, WINDOW_HEIGHT) / 2.0
+    }
+}
+
 // Gets the dimensions of the board and screen
-fn get_dimensions(board: &Board, padding: f32) -> Dimensions {
-    let board_scale_y = (1.0 - 1.0 / (board.rows as f32 + 1.0)) * BOARD_SCALE.y;
+fn get_dimensions(board: &Board, padding: f32, layout: BoardLayout) -> Dimensions {
+    board_dimensions(board.rows, board.cols, padding, layout)
+}
+
+// Gets the dimensions of a board of the given size and the screen
+fn board_dimensions(rows: i32, cols: i32, padding: f32, layout: BoardLayout) -> Dimensions {
+    let area = BoardArea::new(layout);
 
-    // Height of each row in the board
-    let row_height = WINDOW_HEIGHT * board_scale_y / board.rows as f32;
+    // Height of each row in the board, the strip above it being as high as a row
+    let row_height = area.size.y / (rows as f32 + 1.0);
 
     // Width of each column in the board
-    let col_width = WINDOW_WIDTH * BOARD_SCALE.x / board.cols as f32;
+    let col_width = area.size.x / cols as f32;
 
     // Scale of the disks in the board (padding may be for the hole or the disk)
     let scale = (col_width * padding).min(row_height * padding);
 
     Dimensions {
-        board_scale_y,
+        origin: area.center + Vec2::new(-area.size.x, area.size.y) / 2.0,
+        width: area.size.x,
+        height: row_height * rows as f32,
         row_height,
         col_width,
         scale,
     }
 }
 
-// Add a new disk to the board
-fn draw_disk(
-    commands: &mut Commands,//Commands is used to schedule commands to be executed by the game engine.
+// A board as the game would draw it, scaled down (used to preview the board size in the menu)
+pub struct BoardPreview {
+    pub size: Vec2,
+    // Centers of the holes, relative to the center of the board
+    pub holes: Vec<Vec2>,
+    pub hole_size: f32,
+}
+
+// Scales a board of the given size down to the width. To keep it cheap, at most max_cells
+// rows and columns of holes are shown, spread over the whole board.
+pub fn board_preview(
+    rows: i32,
+    cols: i32,
+    width: f32,
+    max_cells: i32,
+    layout: BoardLayout,
+) -> BoardPreview {
+    let dims = board_dimensions(rows, cols, HOLE_PADDING, layout);
+    let factor = width / dims.width;
+    let size = Vec2::new(width, dims.height * factor);
+
+    let shown_rows = rows.min(max_cells);
+    let shown_cols = cols.min(max_cells);
+    let cell = Vec2::new(size.x / shown_cols as f32, size.y / shown_rows as f32);
+
+    let mut holes = Vec::new();
+    for row in 0..shown_rows {
+        for col in 0..shown_cols {
+            holes.push(Vec2::new(
+                -size.x / 2.0 + cell.x * (col as f32 + 0.5),
+                size.y / 2.0 - cell.y * (row as f32 + 0.5),
+            ));
+        }
+    }
+
+    BoardPreview {
+        size,
+        holes,
+        hole_size: cell.x.min(cell.y) * HOLE_PADDING,
+    }
+}
+
+// Add a new disk to the board, falling in from the given row if there is one
+fn draw_disk(
+    commands: &mut Commands,//Commands is used to schedule commands to be executed by the game engine.
     mesh_handles: &MeshHandles,//used to render the disks in the game.
     material_handles: &MaterialHandles,//are used to color the disks in the game.
     dims: &Dimensions,
     col: i32,//i32 representing a column number on the game board.
     row: i32,
     disk: Disk,//representing the color of the disk to be placed on the board.
+    from_row: Option<i32>,
 ) {
     //calculates the position of the disk to be placed on the game board based on these values.
     let mut transform = get_disk_transform(dims, row, col);
 
     // ensure that disks appear on top of each other(depth)
     transform.translation.z = 0.2;
+    // Keeps the outline behind the disk (which is flat, so this doesn't change how it looks)
+    transform.scale.z = 1.0;
+    let target = transform.translation;
+    if let Some(from_row) = from_row {
+        transform.translation.y = get_disk_transform(dims, from_row, col).translation.y;
+    }
 
     //spawns an entity on the game board, 
     //which is a 2D circle mesh with the material set to the color of the disk, 
     //positioned at the location specified by the transform variable.
-    commands.spawn((
-        MaterialMesh2dBundle {
-            mesh: mesh_handles.circle.clone().into(),
-            material: material_handles.get_disk_material(disk),
-            transform,
-            ..default()
-        },
-        InGame,
-    ));
+    let entity = commands
+        .spawn((
+            MaterialMesh2dBundle {
+                mesh: mesh_handles.circle.clone().into(),
+                material: material_handles.get_disk_material(disk),
+                transform,
+                ..default()
+            },
+            BoardDisk,
+            disk,
+            InGame,
+        ))
+        .with_children(|parent| {
+            // Outline, a slightly bigger circle behind the disk (only visible in themes that have one)
+            parent.spawn(MaterialMesh2dBundle {
+                mesh: mesh_handles.circle.clone().into(),
+                material: material_handles.disk_outline.clone(),
+                transform: Transform {
+                    translation: Vec3::new(0.0, 0.0, -0.01),
+                    scale: Vec3::splat(DISK_OUTLINE_SCALE),
+                    ..default()
+                },
+                ..default()
+            });
+        })
+        .id();
+    if from_row.is_some() {
+        commands.entity(entity).insert((
+            FallingDisk { target, speed: 0.0 },
+            FogReveal(Timer::from_seconds(FOG_REVEAL_TIME, TimerMode::Once)),
+        ));
+    }
+}
+
+// Draws every disk and rock on the board (after loading, or when they all moved)
+fn draw_disks(
+    commands: &mut Commands,
+    mesh_handles: &MeshHandles,
+    material_handles: &MaterialHandles,
+    board: &Board,
+    layout: BoardLayout,
+) {
+    let dims = get_dimensions(board, DISK_PADDING, layout);
+    for &(row, col) in &board.rocks {
+        let mut transform = get_disk_transform(&dims, row, col);
+        transform.translation.z = 0.2;
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: mesh_handles.circle.clone().into(),
+                material: material_handles.rock.clone(),
+                transform,
+                ..default()
+            },
+            BoardDisk,
+            InGame,
+        ));
+    }
+
+    for row in 0..board.rows {
+        for col in 0..board.cols {
+            if let Some(disk) = board.disks[col as usize][row as usize] {
+                draw_disk(
+                    commands,
+                    mesh_handles,
+                    material_handles,
+                    &dims,
+                    col,
+                    row,
+                    disk,
+                    None,
+                );
+            }
+        }
+    }
 }
 
 // Add a new hole to the board (the holes are drawn as circles
@@ -216,8 +1120,8 @@ fn draw_hole(
 fn get_disk_transform(dims: &Dimensions, row: i32, col: i32) -> Transform {
     Transform {
         translation: Vec3::new(
-            -WINDOW_WIDTH / 2.0 + (dims.col_width * col as f32 + dims.col_width / 2.0),
-            WINDOW_HEIGHT / 2.0 - dims.row_height * (row as f32 + 1.5),
+            dims.col_x(col),
+            dims.origin.y - dims.row_height * (row as f32 + 1.5),
             0.1,
         ),
         scale: Vec3::new(dims.scale, dims.scale, 0.0),
@@ -225,35 +1129,463 @@ fn get_disk_transform(dims: &Dimensions, row: i32, col: i32) -> Transform {
     }
 }
 
+// A winning line, by the player and the disks at both ends of it
+// (for a square, its top left and bottom right disks)
+pub type WinningLine = (Turn, (i32, i32), (i32, i32));
+
+// Who won with the lines made by the last action of the mover, None for a draw.
+// If it connected lines for more than one player at once, the rule settles it:
+// the mover wins (or the first player after them with a line, if the mover has none), or nobody does.
+fn resolve_winner(
+    lines: &[WinningLine],
+    mover: Turn,
+    players: u8,
+    rule: SimultaneousWinRule,
+) -> Option<Turn> {
+    let &(first, _, _) = lines.first()?;
+    if lines.iter().all(|&(winner, _, _)| winner == first) {
+        return Some(first);
+    }
+    match rule {
+        SimultaneousWinRule::Mover => {
+            let mut winner = mover;
+            while !lines
+                .iter()
+                .any(|&(line_winner, _, _)| line_winner == winner)
+            {
+                winner.next(players);
+            }
+            Some(winner)
+        }
+        SimultaneousWinRule::Draw => None,
+    }
+}
+
+// The cells of the board, kept in one list column after column (the cell of a row in a column is
+// at col * rows + row), so copying a board (which the AI does a lot) is a single allocation.
+// Indexing it by a column gives that column's cells, like a list of columns would.
+#[derive(Clone)]
+pub struct Disks {
+    rows: usize,
+    cells: Vec<Option<Disk>>,
+}
+
+impl Disks {
+    pub fn new(rows: i32, cols: i32) -> Self {
+        Self {
+            rows: rows as usize,
+            cells: vec![None; (rows * cols) as usize],
+        }
+    }
+
+    // The columns, from left to right
+    pub fn iter(&self) -> std::slice::Chunks<'_, Option<Disk>> {
+        self.cells.chunks(self.rows.max(1))
+    }
+
+    pub fn iter_mut(&mut self) -> std::slice::ChunksMut<'_, Option<Disk>> {
+        self.cells.chunks_mut(self.rows.max(1))
+    }
+}
+
+impl Index<usize> for Disks {
+    type Output = [Option<Disk>];
+
+    fn index(&self, col: usize) -> &Self::Output {
+        &self.cells[col * self.rows..(col + 1) * self.rows]
+    }
+}
+
+impl IndexMut<usize> for Disks {
+    fn index_mut(&mut self, col: usize) -> &mut Self::Output {
+        &mut self.cells[col * self.rows..(col + 1) * self.rows]
+    }
+}
+
+// Saved as a list of columns, so save files look the same as before the cells were one list
+impl Serialize for Disks {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de> Deserialize<'de> for Disks {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let columns = Vec::<Vec<Option<Disk>>>::deserialize(deserializer)?;
+        let rows = columns.first().map_or(0, Vec::len);
+        if columns.iter().any(|column| column.len() != rows) {
+            return Err(serde::de::Error::custom("columns have different heights"));
+        }
+        Ok(Self {
+            rows,
+            cells: columns.concat(),
+        })
+    }
+}
+
 // Contains all the data of the current game
 #[derive(Resource, Serialize, Deserialize, Clone)]
-struct Board {
-    rows: i32,
-    cols: i32,
-    disks: Vec<Vec<Option<Disk>>>,
+pub struct Board {
+    pub rows: i32,
+    pub cols: i32,
+    pub disks: Disks,
+    // Number of players taking turns (saves from before this was added are always 2 players)
+    #[serde(default = "default_players")]
+    pub players: u8,
+    // Number of disks in a line needed to win
+    #[serde(default = "default_win_length")]
+    pub win_length: i32,
+    // Disks in a line each player needs instead, in turn order, as a handicap
+    // (empty when everyone needs win_length, like in saves from before this was added)
+    #[serde(default)]
+    pub win_lengths: Vec<i32>,
+    // Cells blocked by rocks as (row, col), no disk can go there (empty unless playing with rocks)
+    #[serde(default)]
+    pub rocks: Vec<(i32, i32)>,
+    // Which way the disks fall in each column
+    #[serde(default)]
+    pub drop_pattern: DropPattern,
+    // What has to be formed to win (saves from before this was added are always lines)
+    #[serde(default)]
+    pub win_condition: WinCondition,
+    // Directions winning lines can go in (saves from before this was added allow all of them)
+    #[serde(default)]
+    pub win_directions: WinDirections,
+    // Rows the first disk of each column was placed at with floating first disks,
+    // None while the column is empty (see base)
+    #[serde(default)]
+    pub bases: Vec<Option<i32>>,
+}
+
+// Lets a stack of disks fall back down the other way around, the top disk ending up at the bottom
+fn flip_stack<T: Copy>(slots: &mut [Option<T>]) {
+    let stack: Vec<T> = slots.iter().flatten().copied().collect();
+    let rows = slots.len();
+    let empty = rows - stack.len();
+    for (row, slot) in slots.iter_mut().enumerate() {
+        *slot = if row < empty {
+            None
+        } else {
+            Some(stack[rows - 1 - row])
+        };
+    }
+}
+
+// The random key of a disk in a cell for Zobrist hashing. Made up from the cell and player
+// (with splitmix64), so the keys are always the same and hashes can be stored in files.
+fn zobrist_key(row: i32, col: i32, disk: Disk) -> u64 {
+    let mut key = (row as u64) << 32 | (col as u64) << 8 | disk.0 as u64;
+    key = key.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    key = (key ^ (key >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    key = (key ^ (key >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    key ^ (key >> 31)
+}
+
+// Flips the stacks of a column the way a gravity flip does (see Board::apply_gravity_flip).
+// Works on anything kept in the cells, so it can also tell where each disk ends up.
+fn flip_column<T: Copy>(column: &mut [Option<T>], col: i32, rising: bool, rocks: &[(i32, i32)]) {
+    if rising {
+        column.reverse();
+    }
+
+    let rows = column.len();
+    let is_rock = |row: usize| {
+        let row = if rising { rows - 1 - row } else { row };
+        rocks.contains(&(row as i32, col))
+    };
+    let mut start = 0;
+    for end in 0..=rows {
+        if end == rows || is_rock(end) {
+            flip_stack(&mut column[start..end]);
+            start = end + 1;
+        }
+    }
+
+    if rising {
+        column.reverse();
+    }
+}
+
+fn default_players() -> u8 {
+    2
+}
+
+fn default_win_length() -> i32 {
+    DEFAULT_WIN_LENGTH
 }
-//This code creates an empty board of rows by cols dimensions. 
-//The disks variable is a 2D array of None values, which will be used to store the state of the disks on the game board. 
-//Each position in the array corresponds to a position on the game board and will be filled with either a Red or Blue value.
+//This code creates an empty board of rows by cols dimensions, for the given number of players.
+//The disks variable is a grid of None values (stored column after column), which will be used to store the state of the disks on the game board. 
+//Each position in the array corresponds to a position on the game board and will be filled with the disk of a player.
 impl Board {
-    fn new(rows: i32, cols: i32) -> Self {
-        let disks = vec![vec![None; rows as usize]; cols as usize];
-        Self { rows, cols, disks }
+    pub fn new(rows: i32, cols: i32, players: u8) -> Self {
+        Self {
+            rows,
+            cols,
+            disks: Disks::new(rows, cols),
+            players,
+            win_length: DEFAULT_WIN_LENGTH,
+            win_lengths: Vec::new(),
+            rocks: Vec::new(),
+            drop_pattern: DropPattern::Normal,
+            win_condition: WinCondition::Line,
+            win_directions: WinDirections::ALL,
+            bases: vec![None; cols as usize],
+        }
+    }
+
+    // Number of disks in a line the player of the disk needs to win
+    pub fn win_length_of(&self, disk: Disk) -> i32 {
+        self.win_lengths
+            .get(disk.0 as usize)
+            .copied()
+            .unwrap_or(self.win_length)
+    }
+
+    // The longest line any player needs to win, the board has to fit it
+    pub fn longest_win_length(&self) -> i32 {
+        (0..self.players)
+            .map(|player| self.win_length_of(Disk(player)))
+            .max()
+            .unwrap_or(self.win_length)
+    }
+
+    // The directions lines are scanned in (each line only one way), if they count for a win
+    pub fn line_directions(&self) -> impl Iterator<Item = (i32, i32)> {
+        let directions = self.win_directions;
+        [(0, 1), (1, 0), (1, 1), (1, -1)]
+            .into_iter()
+            .filter(move |&(row_delta, col_delta)| directions.allows(row_delta, col_delta))
+    }
+
+    // The row disks enter the column at: the top, or the bottom if they rise
+    pub fn entry_row(&self, col: i32) -> i32 {
+        if self.drop_pattern.falls_down(col) {
+            0
+        } else {
+            self.rows - 1
+        }
+    }
+
+    // Places rocks on random cells picked by the seed, so the same seed always gives the same board.
+    // The cells disks enter at are kept free, so every column can take at least one disk.
+    pub fn place_rocks(&mut self, seed: u64) {
+        let mut cells: Vec<(i32, i32)> = (0..self.rows)
+            .flat_map(|row| (0..self.cols).map(move |col| (row, col)))
+            .filter(|&(row, col)| row != self.entry_row(col))
+            .collect();
+        cells.shuffle(&mut StdRng::seed_from_u64(seed));
+
+        let count = (self.cols as f32 * ROCKS_PER_COL) as usize;
+        self.rocks = cells.into_iter().take(count).collect();
+    }
+
+    // Whether the cell is blocked by a rock
+    pub fn is_rock(&self, row: i32, col: i32) -> bool {
+        self.rocks.contains(&(row, col))
+    }
+
+    // Whether a board of this size has room for a winning line in every direction,
+    // smaller boards could never be won
+    pub fn fits_win_length(rows: i32, cols: i32, win_length: i32) -> bool {
+        rows >= win_length && cols >= win_length
+    }
+
+    // Makes the board bigger without moving the disks relative to each other. New columns are
+    // added on the right, new rows above the disks (below them in columns where the disks rise),
+    // so every stack stays where its disks fell to. Shrinking would throw disks away, so it's refused.
+    pub fn grow(&mut self, new_rows: i32, new_cols: i32) -> Result<(), String> {
+        if new_rows < self.rows || new_cols < self.cols {
+            return Err(format!(
+                "Board {}x{} can't shrink to {}x{}",
+                self.rows, self.cols, new_rows, new_cols
+            ));
+        }
+
+        let added_rows = (new_rows - self.rows) as usize;
+        let mut disks = Disks::new(new_rows, new_cols);
+        for (col, column) in self.disks.iter().enumerate() {
+            let start = if self.drop_pattern.falls_down(col as i32) {
+                added_rows
+            } else {
+                0
+            };
+            disks[col][start..start + column.len()].copy_from_slice(column);
+        }
+        self.disks = disks;
+        for (row, col) in &mut self.rocks {
+            if self.drop_pattern.falls_down(*col) {
+                *row += added_rows as i32;
+            }
+        }
+        // Only floating first disks have bases, and they always fall down
+        self.bases.resize(new_cols as usize, None);
+        for row in self.bases.iter_mut().flatten() {
+            *row += added_rows as i32;
+        }
+
+        self.rows = new_rows;
+        self.cols = new_cols;
+        Ok(())
+    }
+
+    // Turns the board upside down, so the disks of every column fall back down in reverse order
+    // (the top disk ends up at the bottom). This can create new lines, so wins need checking again.
+    // Rocks stay where they are, so the disks between two rocks are flipped on their own.
+    // Columns where the disks rise are flipped the same way, with the disks ending up at the top.
+    pub fn apply_gravity_flip(&mut self) {
+        for (col, column) in self.disks.iter_mut().enumerate() {
+            let rising = !self.drop_pattern.falls_down(col as i32);
+            flip_column(column, col as i32, rising, &self.rocks);
+        }
+
+        // Floating first disks fell down with the rest, so the stacks now rest on new bases
+        if self.drop_pattern == DropPattern::Floating {
+            for col in 0..self.cols {
+                let base = self.disks[col as usize]
+                    .iter()
+                    .rposition(Option::is_some)
+                    .map(|row| row as i32);
+                self.set_base(col, base);
+            }
+        }
+    }
+
+    // Where each disk goes when the board is flipped, as its column with the rows before and after
+    fn gravity_flip_rows(&self) -> Vec<(i32, i32, i32)> {
+        let mut moves = Vec::new();
+        for col in 0..self.cols {
+            let mut column: Vec<Option<i32>> = (0..self.rows)
+                .map(|row| self.disks[col as usize][row as usize].map(|_| row))
+                .collect();
+            let rising = !self.drop_pattern.falls_down(col);
+            flip_column(&mut column, col, rising, &self.rocks);
+            for (to, from) in column.into_iter().enumerate() {
+                if let Some(from) = from {
+                    moves.push((col, from, to as i32));
+                }
+            }
+        }
+        moves
+    }
+
+    // The row the first disk of the column was placed at, with floating first disks
+    // (None while the column is empty, or if the disks don't float)
+    pub fn base(&self, col: i32) -> Option<i32> {
+        self.bases.get(col as usize).copied().flatten()
+    }
+
+    fn set_base(&mut self, col: i32, base: Option<i32>) {
+        // Saves from before floating first disks have no bases
+        if self.bases.len() < self.cols as usize {
+            self.bases.resize(self.cols as usize, None);
+        }
+        self.bases[col as usize] = base;
+    }
+
+    // Finds the row a disk dropped into the column would land in, without adding it,
+    // or why it can't be dropped there
+    //The row is the position of the lowest None value in the column, above any rock
+    //(or the highest one, below any rock, if the disks rise in this column).
+    pub fn try_drop(&self, col: i32) -> Result<i32, DropError> {
+        if !(0..self.cols).contains(&col) {
+            return Err(DropError::OutOfRange);
+        }
+        // The disk moves from where it enters until it lands on another disk, a rock or the edge
+        let path: Vec<i32> = if self.drop_pattern.falls_down(col) {
+            (0..self.rows).collect()
+        } else {
+            (0..self.rows).rev().collect()
+        };
+        path.into_iter()
+            .take_while(|&row| self.is_empty(row, col))
+            .last()
+            .ok_or(DropError::ColumnFull)
+    }
+
+    // Like try_drop, but with floating first disks the first disk of a column is placed at the
+    // chosen row instead, if there is one it could reach (not below a rock).
+    // Once the column has its base, the disks stack on it like always.
+    pub fn try_drop_at(&self, col: i32, chosen_row: Option<i32>) -> Result<i32, DropError> {
+        let row = self.try_drop(col)?;
+        match chosen_row {
+            Some(chosen_row)
+                if self.drop_pattern == DropPattern::Floating
+                    && self.base(col).is_none()
+                    && (0..=row).contains(&chosen_row) =>
+            {
+                Ok(chosen_row)
+            }
+            _ => Ok(row),
+        }
     }
 
     // Add a disk to the board, checks there is space for it(col) and returns the row it was added to
-    //The method returns the position of the first None value, or None if no None value is found. 
-    //If a None value is found, it calculates the index of the element in the row array and assigns the value of disk to that position. 
-    fn drop_disk(&mut self, col: i32, disk: Disk) -> Option<i32> {
-        if (0..self.cols).contains(&col) {
-            let row = &mut self.disks[col as usize];
-            if let Some(index) = row.iter().rev().position(|disk| disk.is_none()) {//method to find the first None value in the row array
-                let index = row.len() - index - 1;
-                row[index] = Some(disk);
-                return Some(index as i32);
+    pub fn drop_disk(&mut self, col: i32, disk: Disk) -> Option<i32> {
+        self.drop_disk_at(col, disk, None)
+    }
+
+    // Add a disk to the board at the chosen row, if it can float there (see try_drop_at)
+    pub fn drop_disk_at(&mut self, col: i32, disk: Disk, chosen_row: Option<i32>) -> Option<i32> {
+        let row = self.try_drop_at(col, chosen_row).ok()?;
+        self.disks[col as usize][row as usize] = Some(disk);
+        if self.drop_pattern == DropPattern::Floating && self.base(col).is_none() {
+            self.set_base(col, Some(row));
+        }
+        Some(row)
+    }
+
+    // Replaces the disks of a column with the given ones, in the order they would be dropped
+    // (so the first one ends up at the bottom), to set up a position without playing it.
+    // If they don't all fit the column is left as it was. Only tests set up positions this way,
+    // the editor places single disks where they are clicked.
+    #[cfg(test)]
+    pub fn set_column(&mut self, col: i32, disks: &[Disk]) -> Result<(), String> {
+        if !(0..self.cols).contains(&col) {
+            return Err(format!("Column {} is not on the board", col));
+        }
+
+        let previous = self.disks[col as usize].to_vec();
+        let previous_base = self.base(col);
+        self.disks[col as usize].fill(None);
+        self.set_base(col, None);
+        for &disk in disks {
+            if self.drop_disk(col, disk).is_none() {
+                self.disks[col as usize].copy_from_slice(&previous);
+                self.set_base(col, previous_base);
+                return Err(format!("{} disks don't fit in column {}", disks.len(), col));
             }
         }
-        None
+        Ok(())
+    }
+
+    // Whether every disk rests on another disk, a rock or the edge the disks fall to, like they
+    // do after being dropped (a position set up by hand can have disks hanging in the air).
+    // With floating first disks, the lowest disk of a column may be anywhere.
+    pub fn is_settled(&self) -> bool {
+        (0..self.cols).all(|col| {
+            // From where the disks land, back towards where they enter
+            let path: Vec<i32> = if self.drop_pattern.falls_down(col) {
+                (0..self.rows).rev().collect()
+            } else {
+                (0..self.rows).collect()
+            };
+            let mut gap = false;
+            let mut may_float = self.drop_pattern == DropPattern::Floating;
+            path.into_iter().all(|row| {
+                if self.is_rock(row, col) {
+                    gap = false;
+                } else if self.disks[col as usize][row as usize].is_some() {
+                    if gap && !may_float {
+                        return false;
+                    }
+                    gap = false;
+                    may_float = false;
+                } else {
+                    gap = true;
+                }
+                true
+            })
+        })
     }
 
     // Check if the game has been won, starting from a certain disk
@@ -269,12 +1601,17 @@ impl Board {
             (1, -1),
             (-1, 1),
         ] {
+            if !self.win_directions.allows(row_delta, col_delta) {
+                continue;
+            }
+
             let mut row = row;
             let mut col = col;
             let mut count = 1;
+            let win_length = self.win_length_of(disk);
 
-//this loop looks for a sequence of 4 disks of the same color in a row, column or diagonal.
-            for _ in 1..4 {
+//this loop looks for a sequence of win_length disks of the same color in a row, column or diagonal.
+            for _ in 1..win_length {
                 //values determine the direction of the loop
                 row += row_delta;
                 col += col_delta;
@@ -286,16 +1623,71 @@ impl Board {
                     }
                 }
             }
-            if count >= 4 {
+            if count >= win_length {
                 return Some((row, col));
             }
         }
         None
     }
 
+    // Whether the disk at the position is part of a winning line, counting the same disks on both
+    // sides of it along each direction. A new win always goes through the last disk placed,
+    // so after a move this is all that needs checking.
+    pub fn wins_through(&self, row: i32, col: i32) -> bool {
+        let Some(disk) = self.disk_at(row, col) else {
+            return false;
+        };
+        if self.win_condition == WinCondition::Square {
+            return self.check_square_win(row, col, disk).is_some();
+        }
+
+        self.line_directions().any(|(row_delta, col_delta)| {
+            let count_side = |sign: i32| {
+                (1..)
+                    .take_while(|&i| {
+                        let (row, col) = (row + row_delta * i * sign, col + col_delta * i * sign);
+                        self.disk_at(row, col) == Some(disk)
+                    })
+                    .count() as i32
+            };
+            1 + count_side(1) + count_side(-1) >= self.win_length_of(disk)
+        })
+    }
+
+    // Finds a 2x2 square of the disk containing the position, returning its top left corner
+    pub fn check_square_win(&self, row: i32, col: i32, disk: Disk) -> Option<(i32, i32)> {
+        SQUARE_CELLS
+            .iter()
+            .map(|&(row_offset, col_offset)| (row - row_offset, col - col_offset))
+            .find(|&(top, left)| {
+                SQUARE_CELLS.iter().all(|&(row_offset, col_offset)| {
+                    self.disk_at(top + row_offset, left + col_offset) == Some(disk)
+                })
+            })
+    }
+
+    // Every 2x2 square of the same disks on the board, overlapping squares are each reported
+    fn all_winning_squares(&self) -> Vec<WinningLine> {
+        let mut squares = Vec::new();
+        for row in 0..self.rows - 1 {
+            for col in 0..self.cols - 1 {
+                let Some(disk) = self.disks[col as usize][row as usize] else {
+                    continue;
+                };
+                if self.check_square_win(row + 1, col + 1, disk) == Some((row, col)) {
+                    squares.push((disk.to_turn(), (row, col), (row + 1, col + 1)));
+                }
+            }
+        }
+        squares
+    }
+
     // Checks whole board for a win
     #[allow(clippy::type_complexity)]
-    fn check_for_wins(&self) -> Option<(Turn, (i32, i32), (i32, i32))> {
+    pub fn check_for_wins(&self) -> Option<WinningLine> {
+        if self.win_condition == WinCondition::Square {
+            return self.all_winning_squares().into_iter().next();
+        }
         for row in 0..self.rows {
             for col in 0..self.cols {
                 if let Some(disk) = self.disks[col as usize][row as usize] {
@@ -307,142 +1699,1489 @@ impl Board {
         }
         None
     }
-}
 
-#[derive(Resource, Serialize, Deserialize, Clone)]
-struct MoveHistory {
-    moves: Vec<(i32, Turn)>,
-}
-//Implement game history
-impl MoveHistory {
-    fn new() -> MoveHistory {
-        MoveHistory { moves: Vec::new() }
-    }
-}
+    // Every distinct winning line on the board, each reported once as its full length
+    // (a line of 5 is one line, not two of 4), with the ends ordered top to bottom, then left to right.
+    // Lines crossing each other in a shared disk are still separate lines.
+    // With the square win condition, these are the winning squares instead.
+    pub fn all_winning_lines(&self) -> Vec<WinningLine> {
+        if self.win_condition == WinCondition::Square {
+            return self.all_winning_squares();
+        }
 
-// Used to identify the move history numbers
-#[derive(Component)]
-struct Move;
+        let mut lines = Vec::new();
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let Some(disk) = self.disks[col as usize][row as usize] else {
+                    continue;
+                };
 
-// For saving/loading the game
-#[derive(Serialize, Deserialize)]
-struct GameData {
-    board: Board,
-    turn: Turn,
-    history: MoveHistory,
-}
+                // Only scanning forwards, from the first disk of each line, finds every line once
+                for (row_delta, col_delta) in self.line_directions() {
+                    if self.disk_at(row - row_delta, col - col_delta) == Some(disk) {
+                        continue;
+                    }
 
-pub struct GamePlugin;
+                    let mut length = 1;
+                    while self.disk_at(row + row_delta * length, col + col_delta * length)
+                        == Some(disk)
+                    {
+                        length += 1;
+                    }
+                    if length >= self.win_length_of(disk) {
+                        let start = (row, col);
+                        let end = (
+                            row + row_delta * (length - 1),
+                            col + col_delta * (length - 1),
+                        );
+                        lines.push((disk.to_turn(), start.min(end), start.max(end)));
+                    }
+                }
+            }
+        }
+        lines
+    }
 
-// Creating the plugin
-impl Plugin for GamePlugin {
-    //configure the systems and resources of the App.
-    fn build(&self, app: &mut App) {
-        app.insert_resource(SkipClick(false))//if the next mouse click event should be ignored.
-            .insert_resource(Turn::Red)
-            .insert_resource(Board::new(6, 7))
-            .insert_resource(MoveHistory::new())
-            .add_startup_system(setup)
-            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(skip_click))
-            .add_system_set(
-                SystemSet::on_update(GameState::Playing)
-                    .with_system(check_for_game_change)
-                    .with_system(check_for_pause)
-                    .with_system(check_for_click.after(check_for_game_change))
-                    .with_system(check_for_mouse_movement.after(check_for_game_change))
-                    .with_system(check_for_wins.after(check_for_game_change)),
-            );
+    // A column is full when the slot disks enter at is taken (columns outside the board count as full)
+    pub fn column_full(&self, col: i32) -> bool {
+        !self.is_empty(self.entry_row(col), col)
     }
-}
 
-// To prevent click in menu from spreading to game, called on enter
-fn skip_click(mut skip_click: ResMut<SkipClick>) {
-    skip_click.0 = true;
-}
+    // All the columns a disk can still be dropped into
+    pub fn legal_moves(&self) -> Vec<i32> {
+        (0..self.cols)
+            .filter(|&col| !self.column_full(col))
+            .collect()
+    }
 
-// Creates a completely new game
-fn new_game(
-    commands: &mut Commands,
-    mesh_handles: &MeshHandles,
-    material_handles: &MaterialHandles,
-    board: &mut Board,
-    turn: &mut Turn,
-    move_history: &mut MoveHistory,
-    rows: i32,
-    cols: i32,
-) {
-    *board = Board::new(rows, cols);
-    *turn = Turn::Red;
-    *move_history = MoveHistory::new();
+    // A uniformly random legal column, None if the board is full
+    pub fn random_move(&self, rng: &mut impl Rng) -> Option<i32> {
+        self.legal_moves().choose(rng).copied()
+    }
 
-    let hole_dims = get_dimensions(board, HOLE_PADDING);
-    let disk_dims = get_dimensions(board, DISK_PADDING);
+    // The disk at a position, None if the slot is empty, a rock or outside the board
+    fn disk_at(&self, row: i32, col: i32) -> Option<Disk> {
+        if (0..self.rows).contains(&row) && (0..self.cols).contains(&col) {
+            self.disks[col as usize][row as usize]
+        } else {
+            None
+        }
+    }
 
-    // Add the ghost disks (but invisible)
-    let mut red_ghost_disk_color = RED_DISK_COLOR;
-    red_ghost_disk_color.set_a(0.3);
-    commands.spawn((
-        GhostDisk::Red,
-        InGame,
-        MaterialMesh2dBundle {
-            mesh: mesh_handles.circle.clone().into(),
-            material: material_handles.red_ghost_disk.clone(),
-            transform: Transform {
-                translation: Vec3::new(0.0, 0.0, 0.0),
-                scale: Vec3::new(disk_dims.scale, disk_dims.scale, 0.0),
-                ..default()
-            },
-            visibility: Visibility { is_visible: false },
-            ..default()
-        },
-    ));
+    // Whether a position is on the board and has no disk (or rock) in it
+    fn is_empty(&self, row: i32, col: i32) -> bool {
+        (0..self.rows).contains(&row)
+            && (0..self.cols).contains(&col)
+            && self.disks[col as usize][row as usize].is_none()
+            && !self.is_rock(row, col)
+    }
 
-    let mut blue_ghost_disk_color = BLUE_DISK_COLOR;
-    blue_ghost_disk_color.set_a(0.3);
-    commands.spawn((//method to schedule the spawning of an entity with several components
-        GhostDisk::Blue,
-        InGame,
-        MaterialMesh2dBundle {
-            mesh: mesh_handles.circle.clone().into(),
-            material: material_handles.blue_ghost_disk.clone(),
-            transform: Transform {
-                translation: Vec3::new(0.0, 0.0, 0.0),
-                scale: Vec3::new(disk_dims.scale, disk_dims.scale, 0.0),
-                ..default()
-            },
-            //The ghost disk is not visible initially and it only appears when the player hover over the board
-            visibility: Visibility { is_visible: false },
+    // Whether the game is played by the standard rules: two players, four in a line in any direction,
+    // and nothing but the size changed (no rocks, disks always falling down, no handicap)
+    pub fn has_standard_rules(&self) -> bool {
+        self.players == 2
+            && (0..self.players)
+                .all(|player| self.win_length_of(Disk(player)) == DEFAULT_WIN_LENGTH)
+            && self.rocks.is_empty()
+            && self.drop_pattern == DropPattern::Normal
+            && self.win_condition == WinCondition::Line
+            && self.win_directions == WinDirections::ALL
+    }
+
+    // Hash of the disks on the board, the same for the same position however it came about.
+    // Zobrist hashing: each player's disk in each cell has its own random key,
+    // and the keys of all the disks on the board are xored together.
+    pub fn zobrist_hash(&self) -> u64 {
+        let mut hash = 0;
+        for col in 0..self.cols {
+            for row in 0..self.rows {
+                if let Some(disk) = self.disks[col as usize][row as usize] {
+                    hash ^= zobrist_key(row, col, disk);
+                }
+            }
+        }
+        hash
+    }
+
+    // The board as text, a line per row from the top: a letter for each disk (R, B, G or O),
+    // # for rocks and . for empty cells. Only the cells are kept, not the rules of the game.
+    pub fn to_ascii(&self) -> String {
+        let mut text = String::new();
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                text.push(match self.disks[col as usize][row as usize] {
+                    Some(disk) => ASCII_DISKS[disk.0 as usize],
+                    None if self.is_rock(row, col) => ASCII_ROCK,
+                    None => ASCII_EMPTY,
+                });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    // Draws the board as an SVG picture in the colors of the theme, like it looks in the game
+    // (always upright). Once the game is over, the winning lines are drawn over the disks.
+    pub fn to_svg(&self, colors: &ThemeColors) -> String {
+        let width = self.cols as f32 * SVG_CELL_SIZE;
+        let height = self.rows as f32 * SVG_CELL_SIZE;
+        let center = |row: i32, col: i32| {
+            (
+                (col as f32 + 0.5) * SVG_CELL_SIZE,
+                (row as f32 + 0.5) * SVG_CELL_SIZE,
+            )
+        };
+
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+             viewBox=\"0 0 {width} {height}\">\n"
+        );
+        svg += &format!(
+            "<rect class=\"board\" width=\"{width}\" height=\"{height}\" rx=\"{}\" {}/>\n",
+            SVG_CORNER_RADIUS * SVG_CELL_SIZE,
+            svg_paint("fill", colors.board)
+        );
+
+        // A hole in every cell, with the disk or rock in it on top
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                let (x, y) = center(row, col);
+                svg += &format!(
+                    "<circle class=\"hole\" cx=\"{x}\" cy=\"{y}\" r=\"{}\" {}/>\n",
+                    SVG_CELL_SIZE / 2.0 * HOLE_PADDING,
+                    svg_paint("fill", colors.background)
+                );
+                let (class, color) = match self.disks[col as usize][row as usize] {
+                    Some(disk) => ("disk", disk.to_turn().to_color(colors)),
+                    None if self.is_rock(row, col) => ("rock", ROCK_COLOR),
+                    None => continue,
+                };
+                svg += &format!(
+                    "<circle class=\"{class}\" cx=\"{x}\" cy=\"{y}\" r=\"{}\" {}/>\n",
+                    SVG_CELL_SIZE / 2.0 * DISK_PADDING,
+                    svg_paint("fill", color)
+                );
+            }
+        }
+
+        // Lines between the centers of their end disks, squares through the centers of theirs
+        for (_, from, to) in self.all_winning_lines() {
+            let (x1, y1) = center(from.0, from.1);
+            let (x2, y2) = center(to.0, to.1);
+            let stroke = format!(
+                "{} stroke-width=\"{}\" stroke-linecap=\"round\"",
+                svg_paint("stroke", colors.winner),
+                SVG_WIN_LINE_WIDTH * SVG_CELL_SIZE
+            );
+            svg += &if self.win_condition == WinCondition::Square {
+                format!(
+                    "<rect class=\"win\" x=\"{x1}\" y=\"{y1}\" width=\"{}\" height=\"{}\" \
+                     fill=\"none\" {stroke}/>\n",
+                    x2 - x1,
+                    y2 - y1
+                )
+            } else {
+                format!(
+                    "<line class=\"win\" x1=\"{x1}\" y1=\"{y1}\" x2=\"{x2}\" y2=\"{y2}\" \
+                     {stroke}/>\n"
+                )
+            };
+        }
+
+        svg += "</svg>\n";
+        svg
+    }
+
+    // Reads a board from its text form (see to_ascii), ignoring blank lines and spaces around rows.
+    // It has as many players as its disks need (at least 2), and the default rules.
+    // Not used by the game itself, but handy to set up a position, e.g. from a bug report.
+    #[allow(dead_code)]
+    pub fn from_ascii(text: &str) -> Result<Board, ParseError> {
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect();
+        let Some(first) = lines.first() else {
+            return Err(ParseError::Empty);
+        };
+
+        let cols = first.chars().count();
+        let mut board = Board::new(lines.len() as i32, cols as i32, 2);
+        for (row, line) in lines.iter().enumerate() {
+            if line.chars().count() != cols {
+                return Err(ParseError::UnevenRows);
+            }
+            for (col, cell) in line.chars().enumerate() {
+                match cell {
+                    ASCII_EMPTY => {}
+                    ASCII_ROCK => board.rocks.push((row as i32, col as i32)),
+                    _ => {
+                        let player = ASCII_DISKS
+                            .iter()
+                            .position(|&disk| disk == cell)
+                            .map(|player| player as u8)
+                            .ok_or(ParseError::UnknownCell(cell))?;
+                        board.disks[col][row] = Some(Disk(player));
+                        board.players = board.players.max(player + 1);
+                    }
+                }
+            }
+        }
+        Ok(board)
+    }
+
+    // Counts the lines of exactly `length` disks in a row that are open: empty on both ends, with
+    // enough empty space around them that they could still grow to win_length.
+    // Lines blocked on either end (by the edge of the board or another disk) don't count.
+    fn count_open_lines(&self, disk: Disk, length: i32) -> usize {
+        let mut count = 0;
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                for (row_delta, col_delta) in self.line_directions() {
+                    // Only count each line once, from the disk it starts at
+                    if self.disk_at(row, col) != Some(disk)
+                        || self.disk_at(row - row_delta, col - col_delta) == Some(disk)
+                    {
+                        continue;
+                    }
+
+                    let mut run = 1;
+                    while self.disk_at(row + row_delta * run, col + col_delta * run) == Some(disk) {
+                        run += 1;
+                    }
+                    if run != length
+                        || !self.is_empty(row - row_delta, col - col_delta)
+                        || !self.is_empty(row + row_delta * run, col + col_delta * run)
+                    {
+                        continue;
+                    }
+
+                    // Empty space on both sides, which the line could grow into
+                    let mut space = run;
+                    let mut i = 1;
+                    while self.is_empty(row - row_delta * i, col - col_delta * i) {
+                        space += 1;
+                        i += 1;
+                    }
+                    let mut i = run;
+                    while self.is_empty(row + row_delta * i, col + col_delta * i) {
+                        space += 1;
+                        i += 1;
+                    }
+
+                    if space >= self.win_length_of(disk) {
+                        count += 1;
+                    }
+                }
+            }
+        }
+        count
+    }
+
+    // Number of columns the player could win in right away, by dropping a disk there
+    pub fn immediate_threats(&self, disk: Disk) -> usize {
+        self.legal_moves()
+            .into_iter()
+            .filter(|&col| {
+                let mut board = self.clone();
+                board
+                    .drop_disk(col, disk)
+                    .is_some_and(|row| board.wins_through(row, col))
+            })
+            .count()
+    }
+
+    // Number of open lines of three disks (see count_open_lines), the strongest threats
+    pub fn count_open_threes(&self, disk: Disk) -> usize {
+        self.count_open_lines(disk, 3)
+    }
+
+    // Number of open lines of two disks (see count_open_lines)
+    pub fn count_open_twos(&self, disk: Disk) -> usize {
+        self.count_open_lines(disk, 2)
+    }
+
+    // The columns with an empty cell at an end of a line of three, where the player's live threats
+    // are completed, from left to right. A line blocked on one end is still a threat at the other.
+    pub fn open_three_columns(&self, disk: Disk) -> Vec<i32> {
+        let mut cols = Vec::new();
+        for row in 0..self.rows {
+            for col in 0..self.cols {
+                for (row_delta, col_delta) in self.line_directions() {
+                    // Only look at each line once, from the disk it starts at
+                    if self.disk_at(row, col) != Some(disk)
+                        || self.disk_at(row - row_delta, col - col_delta) == Some(disk)
+                    {
+                        continue;
+                    }
+
+                    let mut run = 1;
+                    while self.disk_at(row + row_delta * run, col + col_delta * run) == Some(disk) {
+                        run += 1;
+                    }
+                    if run != 3 {
+                        continue;
+                    }
+                    for (end_row, end_col) in [
+                        (row - row_delta, col - col_delta),
+                        (row + row_delta * run, col + col_delta * run),
+                    ] {
+                        if self.is_empty(end_row, end_col) {
+                            cols.push(end_col);
+                        }
+                    }
+                }
+            }
+        }
+        cols.sort_unstable();
+        cols.dedup();
+        cols
+    }
+}
+
+// A move in the history: the column played, by whom and when (on the game clock, in seconds),
+// and the row a floating first disk was placed at (None if the disk fell like any other)
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(from = "SavedMove")]
+struct MoveRecord {
+    col: i32,
+    turn: Turn,
+    time: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    row: Option<i32>,
+}
+
+// How moves are found in save files, older saves only have the column and player of each move
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedMove {
+    Old(i32, Turn),
+    Record {
+        col: i32,
+        turn: Turn,
+        #[serde(default)]
+        time: f32,
+        #[serde(default)]
+        row: Option<i32>,
+    },
+}
+
+impl From<SavedMove> for MoveRecord {
+    fn from(saved: SavedMove) -> Self {
+        match saved {
+            SavedMove::Old(col, turn) => MoveRecord {
+                col,
+                turn,
+                time: 0.0,
+                row: None,
+            },
+            SavedMove::Record {
+                col,
+                turn,
+                time,
+                row,
+            } => MoveRecord {
+                col,
+                turn,
+                time,
+                row,
+            },
+        }
+    }
+}
+
+#[derive(Resource, Serialize, Deserialize, Clone)]
+struct MoveHistory {
+    moves: Vec<MoveRecord>,
+    // How many of the first moves were dropped to keep within the history limit
+    #[serde(default)]
+    trimmed: usize,
+}
+//Implement game history
+impl MoveHistory {
+    fn new() -> MoveHistory {
+        MoveHistory {
+            moves: Vec::new(),
+            trimmed: 0,
+        }
+    }
+
+    // Adds a move, dropping the oldest ones if that keeps more than the limit
+    fn push(&mut self, record: MoveRecord, limit: HistoryLimit) {
+        self.moves.push(record);
+        if let Some(excess) = limit.0.and_then(|limit| self.moves.len().checked_sub(limit)) {
+            self.moves.drain(..excess);
+            self.trimmed += excess;
+        }
+    }
+
+    // How many moves were made in the game, including the ones no longer kept
+    fn moves_made(&self) -> usize {
+        self.trimmed + self.moves.len()
+    }
+
+    // Where the last disk was placed, always the disk nearest to where the last column played is entered
+    fn last_move(&self, board: &Board) -> Option<LastMove> {
+        let col = self.moves.last()?.col;
+        let column = &board.disks[col as usize];
+        let row = if board.drop_pattern.falls_down(col) {
+            column.iter().position(|disk| disk.is_some())?
+        } else {
+            column.iter().rposition(|disk| disk.is_some())?
+        };
+        Some(LastMove {
+            row: row as i32,
+            col,
+        })
+    }
+
+    // Takes the last move back, removing its disk and giving the turn back to its player.
+    // Not possible before the first move, or once the board was flipped after the move
+    // (the flip took a turn, and the disks are no longer where they were dropped).
+    fn undo(&mut self, board: &mut Board, turn: &mut Turn) -> bool {
+        let (Some(record), Some(last_move)) = (self.moves.last(), self.last_move(board)) else {
+            return false;
+        };
+        let mut next = record.turn;
+        next.next(board.players);
+        let cell = &mut board.disks[last_move.col as usize][last_move.row as usize];
+        if next != *turn || *cell != Some(record.turn.to_disk()) {
+            return false;
+        }
+
+        *cell = None;
+        // Taking back a floating first disk lets the next one float again
+        if board.disks[last_move.col as usize]
+            .iter()
+            .all(Option::is_none)
+        {
+            board.set_base(last_move.col, None);
+        }
+        *turn = record.turn;
+        self.moves.pop();
+        true
+    }
+
+    // Where the disk of each move is, with the number of the move (counting from 1), from the
+    // last move back. Only the moves since the last flip, the disks before it have moved.
+    fn numbered_moves(&self, board: &Board, turn: Turn) -> Vec<(LastMove, usize)> {
+        let mut history = self.clone();
+        let mut board = board.clone();
+        let mut turn = turn;
+        let mut moves = Vec::new();
+        while let Some(last_move) = history.last_move(&board) {
+            let number = history.moves_made();
+            if !history.undo(&mut board, &mut turn) {
+                break;
+            }
+            moves.push((last_move, number));
+        }
+        moves
+    }
+
+    // The boards after each move, starting with the empty board.
+    // Stops early if the moves don't fit on an empty board (like after a flip, or in a puzzle),
+    // and only has the empty board if the first moves were trimmed.
+    fn replay(&self, board: &Board) -> Vec<Board> {
+        let mut replayed = Board {
+            win_length: board.win_length,
+            win_lengths: board.win_lengths.clone(),
+            rocks: board.rocks.clone(),
+            drop_pattern: board.drop_pattern,
+            win_condition: board.win_condition,
+            win_directions: board.win_directions,
+            ..Board::new(board.rows, board.cols, board.players)
+        };
+        let mut boards = vec![replayed.clone()];
+        if self.trimmed > 0 {
+            return boards;
+        }
+        for record in &self.moves {
+            let disk = record.turn.to_disk();
+            if replayed
+                .drop_disk_at(record.col, disk, record.row)
+                .is_none()
+            {
+                break;
+            }
+            boards.push(replayed.clone());
+        }
+        boards
+    }
+}
+
+// Position of the most recently placed disk, not there before the first move
+#[derive(Resource, Clone, Copy)]
+struct LastMove {
+    row: i32,
+    col: i32,
+}
+
+// Used to identify the move history numbers
+#[derive(Component)]
+struct Move;
+
+// Seconds played in the current game, only counting while playing (not in the menu)
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, Default)]
+struct GameClock(f32);
+
+// When the current game was started and how it ended, saved with it so a save tells its story
+// without being replayed. Dates are RFC 3339 (in UTC), saves from before this was added have
+// an empty creation date.
+#[derive(Resource, Serialize, Deserialize, Clone, Default)]
+pub struct GameMeta {
+    pub created: String,
+    // Set once the game is over, with the winner (None for a draw)
+    pub finished: Option<String>,
+    pub result: Option<Turn>,
+}
+
+impl GameMeta {
+    // A game started just now
+    fn new() -> Self {
+        Self {
+            created: rfc3339_now(),
             ..default()
-        },
-    ));
+        }
+    }
 
-    // Board
-    commands.spawn((
-        SpriteBundle {
-            //component is used to specify the position, rotation, and scale of the entity in the 3D space.
-            transform: Transform {
-                translation: Vec3::new(
-                    -WINDOW_WIDTH * ((1.0 - BOARD_SCALE.x) / 2.0),
-                    WINDOW_HEIGHT * (1.0 - hole_dims.board_scale_y) / 2.0 - hole_dims.row_height,
-                    0.0,
-                ),
-                scale: Vec3::new(
-                    WINDOW_WIDTH * BOARD_SCALE.x,
-                    WINDOW_HEIGHT * hole_dims.board_scale_y,
-                    0.0,
-                ),
+    // Records that the game just ended, won by the player (or a draw)
+    fn finish(&mut self, winner: Option<Turn>) {
+        self.finished = Some(rfc3339_now());
+        self.result = winner;
+    }
+}
+
+// The current time as an RFC 3339 date in UTC, like 2023-01-31T12:34:56Z
+fn rfc3339_now() -> String {
+    // Not std's clock, which isn't there in the browser
+    let seconds = instant::SystemTime::now()
+        .duration_since(instant::SystemTime::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let (days, time) = (seconds / SECONDS_PER_DAY, seconds % SECONDS_PER_DAY);
+
+    // The date from the days since 1970-01-01, counted in 400 year eras of 146097 days
+    // that start on March 1st (so the leap day is the last day of a year)
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = (month_from_march + 2) % 12 + 1;
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+// Used to identify the text showing the game clock
+#[derive(Component)]
+struct ClockText;
+
+// Used to identify the single line of text showing the history (in the text history mode)
+#[derive(Component)]
+struct HistoryText;
+
+// How the game looked when it was saved, so it can look the same when loaded on another machine
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct Appearance {
+    pub theme: Theme,
+}
+
+// For saving/loading the game (and loading scenarios, which may leave out all but the board and turn)
+#[derive(Serialize, Deserialize)]
+pub struct GameData {
+    board: Board,
+    turn: Turn,
+    #[serde(default = "MoveHistory::new")]
+    history: MoveHistory,
+    #[serde(default)]
+    clock: GameClock,
+    #[serde(default)]
+    appearance: Option<Appearance>,
+    #[serde(default)]
+    meta: GameMeta,
+}
+
+impl GameData {
+    fn new(
+        board: &Board,
+        turn: Turn,
+        history: &MoveHistory,
+        clock: GameClock,
+        theme: Theme,
+        meta: &GameMeta,
+    ) -> Self {
+        Self {
+            board: board.clone(),
+            turn,
+            history: history.clone(),
+            clock,
+            appearance: Some(Appearance { theme }),
+            meta: meta.clone(),
+        }
+    }
+
+    // Whether the board is a size that can be played on, with the disks filling exactly that size.
+    // A hand edited or broken save can have no rows at all, or negative ones, and the number
+    // of players (with a win length for each of them, if any) has to be one the game can play.
+    pub fn validate_board_size(&self) -> bool {
+        let board = &self.board;
+        (2..=MAX_PLAYERS).contains(&board.players)
+            && (board.win_lengths.is_empty() || board.win_lengths.len() == board.players as usize)
+            && (1..=MAX_BOARD_SIZE).contains(&board.rows)
+            && (1..=MAX_BOARD_SIZE).contains(&board.cols)
+            && board.win_length > 0
+            && board.win_lengths.iter().all(|&win_length| win_length > 0)
+            && Board::fits_win_length(board.rows, board.cols, board.longest_win_length())
+            && board.disks.rows == board.rows as usize
+            && board.disks.cells.len() == (board.rows * board.cols) as usize
+    }
+
+    // Whether the turn fits the disks on the board. The players take turns in order from the first
+    // one, each dropping a disk, except for the one turn each may spend flipping the board.
+    // A hand edited or broken save can have a turn that doesn't fit.
+    pub fn validate_turn_parity(&self) -> bool {
+        self.fitting_turns().any(|turn| turn == self.turn)
+    }
+
+    // All the turns that could be next after the disks on the board, none if the number of disks
+    // of some player can't be right (the fewest flipped boards first)
+    fn fitting_turns(&self) -> impl Iterator<Item = Turn> + '_ {
+        let players = self.board.players as usize;
+        let mut disks = vec![0; players];
+        // Disks of players who aren't in the game can't be explained by any turn
+        let mut unknown_disks = false;
+        for disk in self.board.disks.iter().flatten().flatten() {
+            match disks.get_mut(disk.0 as usize) {
+                Some(count) => *count += 1,
+                None => unknown_disks = true,
+            }
+        }
+        let dropped: usize = disks.iter().sum();
+
+        // Every turn taken either dropped a disk or flipped the board,
+        // so after some number of turns each player has to have taken one or none more than their disks
+        (dropped..=dropped + players)
+            .filter(move |&turns_taken| {
+                !unknown_disks
+                    && disks.iter().enumerate().all(|(player, &count)| {
+                        let taken =
+                            turns_taken / players + usize::from(player < turns_taken % players);
+                        taken == count || taken == count + 1
+                    })
+            })
+            .map(move |turns_taken| Turn((turns_taken % players) as u8))
+    }
+
+    // Whether the save can be loaded, with its turn or one it can be corrected to
+    pub fn playable(&self) -> bool {
+        self.validate_board_size() && self.fitting_turns().next().is_some()
+    }
+
+    // The player who won the saved game, None if it isn't won (yet)
+    pub fn winner(&self) -> Option<Turn> {
+        self.board.check_for_wins().map(|(winner, _, _)| winner)
+    }
+
+    // How many disks were dropped in the saved game
+    pub fn moves_played(&self) -> usize {
+        self.history.moves_made()
+    }
+
+    // The column of the last disk dropped, if it is still where it landed
+    pub fn last_move_col(&self) -> Option<i32> {
+        self.history.last_move(&self.board).map(|last_move| last_move.col)
+    }
+
+    // The appearance saved with the game, if it differs from the current one
+    // (only then is it worth offering to apply it)
+    fn offered_appearance(&self, theme: Theme) -> Option<Appearance> {
+        self.appearance
+            .filter(|appearance| appearance.theme != theme)
+    }
+}
+
+// The appearance of a loaded save that differs from the current one, offered to the players
+// until they apply or dismiss it
+#[derive(Resource)]
+struct OfferedAppearance(Appearance);
+
+// Used to identify the text offering the appearance of a loaded save
+#[derive(Component)]
+struct AppearancePrompt;
+
+// Notice shown over the game for a while (like that the turn of a loaded save was corrected),
+// removed when the timer is done
+#[derive(Component)]
+struct GameNotice(Timer);
+
+// Undos taken in the current game, limited by the undo limit setting (reset by new_game)
+#[derive(Resource, Default)]
+struct UndosTaken(u32);
+
+impl UndosTaken {
+    // Counts an undo if the limit allows another one, false if they are used up
+    fn take(&mut self, limit: UndoLimit) -> bool {
+        if !limit.allows(self.0) {
+            return false;
+        }
+        self.0 += 1;
+        true
+    }
+}
+
+// The first press of the restart key during a game in progress, waiting for the second one
+// until the timer is done (so a nearly won game isn't thrown away by accident)
+#[derive(Resource, Default)]
+struct RestartConfirmation(Option<Timer>);
+
+impl RestartConfirmation {
+    // Whether pressing the restart key restarts the game now. A game in progress (with moves made
+    // and nobody having won yet) only restarts when the key is pressed again in time.
+    fn press(&mut self, board: &Board, history: &MoveHistory) -> bool {
+        let confirmed = self.0.take().is_some();
+        if game_in_progress(board, history) && !confirmed {
+            self.0 = Some(Timer::from_seconds(RESTART_CONFIRM_TIME, TimerMode::Once));
+            return false;
+        }
+        true
+    }
+}
+
+// Used to identify the text asking to press the restart key again
+#[derive(Component)]
+struct RestartPrompt;
+
+// A save file dropped onto the window during a game in progress, waiting for the players
+// to load it or keep their game
+#[derive(Resource)]
+struct DroppedSave(String);
+
+// Used to identify the text asking whether to load the dropped save
+#[derive(Component)]
+struct DroppedSavePrompt;
+
+// Screen covering the board between turns in hot seat play, until the next player clicks it
+#[derive(Component)]
+struct PassOverlay;
+
+// Counts down before the game starts, while the GameState is Countdown
+#[derive(Resource)]
+struct CountdownTimer(Timer);
+
+// Used to identify the countdown shown over the board, and its number
+#[derive(Component)]
+struct CountdownOverlay;
+
+#[derive(Component)]
+struct CountdownText;
+
+// Covers the board after each move in hot seat play, when turned on in the settings, so the
+// device can be passed to the next player. Only if the game goes on, a finished one is shown.
+fn check_for_pass_screen(
+    mut commands: Commands,
+    board: Res<Board>,
+    history: Res<MoveHistory>,
+    turn: Res<Turn>,
+    game_mode: Res<GameMode>,
+    pass_screen: Res<PassScreen>,
+    theme: Res<Theme>,
+    swap_colors: Res<SwapColors>,
+    asset_server: Res<AssetServer>,
+    overlay_query: Query<(), With<PassOverlay>>,
+    mut seen_moves: Local<usize>,
+) {
+    if !board.is_changed() {
+        return;
+    }
+    // Only a single new move passes the turn, not a new, loaded or undone game
+    let single_move = history.moves_made() == *seen_moves + 1;
+    *seen_moves = history.moves_made();
+
+    if !single_move
+        || !pass_screen.0
+        || *game_mode != GameMode::HotSeat
+        || !game_in_progress(&board, &history)
+        || !overlay_query.is_empty()
+    {
+        return;
+    }
+
+    let colors = &theme.colors().swapped(*swap_colors);
+    commands
+        .spawn((
+            ButtonBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                background_color: colors.background.into(),
                 ..default()
             },
-            //component defines the color of the sprite as BOARD_COLOR.
-            sprite: Sprite {
-                color: BOARD_COLOR,
+            PassOverlay,
+            InGame,
+        ))
+        .with_children(|parent| {
+            // In the next player's color, like the turn strip
+            parent.spawn(TextBundle::from_section(
+                format!("Pass to {} - click to continue", *turn),
+                TextStyle {
+                    font: asset_server.load(FONT_PATH),
+                    font_size: 40.0 * colors.text_scale,
+                    color: turn.to_color(colors),
+                },
+            ));
+        });
+}
+
+// Uncovers the board when the pass screen is clicked. The input stays locked for the click,
+// so it doesn't drop a disk as well.
+fn check_for_pass_screen_click(
+    mut commands: Commands,
+    overlay_query: Query<(Entity, &Interaction), (Changed<Interaction>, With<PassOverlay>)>,
+) {
+    for (entity, interaction) in &overlay_query {
+        if *interaction == Interaction::Clicked {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// Shows the countdown over the board, which stays locked until it is over
+fn start_countdown(
+    mut commands: Commands,
+    mut windows: ResMut<Windows>,
+    mut input_locked: ResMut<InputLocked>,
+    theme: Res<Theme>,
+    asset_server: Res<AssetServer>,
+) {
+    input_locked.0 = true;
+    set_cursor_icon(&mut windows, CursorIcon::NotAllowed);
+    commands.insert_resource(CountdownTimer(Timer::from_seconds(
+        COUNTDOWN_SECONDS,
+        TimerMode::Once,
+    )));
+
+    let colors = theme.colors();
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    position_type: PositionType::Absolute,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
                 ..default()
             },
-            ..default()
-        },
-        InGame,
+            CountdownOverlay,
+            InGame,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    format!("{}", COUNTDOWN_SECONDS.ceil()),
+                    TextStyle {
+                        font: asset_server.load(FONT_PATH),
+                        font_size: 200.0 * colors.text_scale,
+                        color: colors.text,
+                    },
+                ),
+                CountdownText,
+            ));
+        });
+}
+
+// Counts down the seconds, starting the game at the end of it.
+// Escape leaves the game for the menu instead, it can't be resumed.
+fn update_countdown(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut countdown_timer: ResMut<CountdownTimer>,
+    mut game_state: ResMut<State<GameState>>,
+    mut main_menu_info: ResMut<MainMenuInfo>,
+    mut text_query: Query<&mut Text, With<CountdownText>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        main_menu_info.allow_resume = false;
+        main_menu_info.winner = None;
+        main_menu_info.message = None;
+        game_state.set(GameState::Menu).unwrap();
+        return;
+    }
+
+    if countdown_timer.0.tick(time.delta()).finished() {
+        game_state.set(GameState::Playing).unwrap();
+        return;
+    }
+    for mut text in &mut text_query {
+        text.sections[0].value = format!("{}", countdown_timer.0.remaining_secs().ceil());
+    }
+}
+
+// Removes the countdown, however it ended
+fn end_countdown(mut commands: Commands, query: Query<Entity, With<CountdownOverlay>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<CountdownTimer>();
+}
+
+// Whether the game has moves made and nobody has won or filled the board yet,
+// so it would be lost by starting another one
+fn game_in_progress(board: &Board, history: &MoveHistory) -> bool {
+    !history.moves.is_empty() && board.check_for_wins().is_none() && !board.legal_moves().is_empty()
+}
+
+// Writes the game to a save file, returning whether it worked (failures are logged)
+fn write_save(path: &str, data: &GameData) -> bool {
+    let error = store_save(path, data).err();
+    let saved = error.is_none();
+    log_event(&GameEvent::Save { path, error });
+    saved
+}
+
+// Saves the game from the menu, with the confirmation shown once it worked
+fn save_game(path: &str, data: &GameData) -> Option<String> {
+    write_save(path, data).then(|| format!("Game saved to {}", path))
+}
+
+// Reads a game from a save file, None if it is missing or invalid
+fn read_save(path: &str) -> Option<GameData> {
+    let data = fetch_save(path);
+    log_event(&GameEvent::Load {
+        path,
+        error: data.as_ref().err().cloned(),
+    });
+    data.ok()
+}
+
+// Whether there is a valid autosave to continue from
+pub fn autosave_available() -> bool {
+    fetch_save(AUTOSAVE_FILE).is_ok_and(|data| data.validate_board_size())
+}
+
+// With the native-save feature, saves are files at their paths
+#[cfg(feature = "native-save")]
+fn store_save(path: &str, data: &GameData) -> Result<(), String> {
+    let file = std::fs::File::create(path)
+        .map_err(|err| format!("Failed to create save file: {}", err))?;
+    serde_json::to_writer(file, data).map_err(|err| format!("Failed to write save file: {}", err))
+}
+
+#[cfg(feature = "native-save")]
+fn fetch_save(path: &str) -> Result<GameData, String> {
+    let file = std::fs::File::open(path)
+        .map_err(|err| format!("Failed to open save file: {}", err))?;
+    serde_json::from_reader(file).map_err(|err| format!("Failed to read save file: {}", err))
+}
+
+#[cfg(feature = "native-save")]
+fn remove_save(path: &str) {
+    if std::path::Path::new(path).exists() {
+        if let Err(err) = std::fs::remove_file(path) {
+            warn!("Failed to remove save file {}: {}", path, err);
+        }
+    }
+}
+
+// Without it (in the browser, which has no files), saves are kept in the local storage,
+// each in a slot named like the file it would be saved to
+#[cfg(not(feature = "native-save"))]
+fn store_save(slot: &str, data: &GameData) -> Result<(), String> {
+    use gloo_storage::{LocalStorage, Storage};
+    LocalStorage::set(slot, data).map_err(|err| format!("Failed to store save: {}", err))
+}
+
+#[cfg(not(feature = "native-save"))]
+fn fetch_save(slot: &str) -> Result<GameData, String> {
+    use gloo_storage::{LocalStorage, Storage};
+    LocalStorage::get(slot).map_err(|err| format!("Failed to read stored save: {}", err))
+}
+
+#[cfg(not(feature = "native-save"))]
+fn remove_save(slot: &str) {
+    use gloo_storage::{LocalStorage, Storage};
+    LocalStorage::delete(slot);
+}
+
+pub struct GamePlugin;
+
+// Creating the plugin
+impl Plugin for GamePlugin {
+    //configure the systems and resources of the App.
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SkipClick(false))//if the next mouse click event should be ignored.
+            .insert_resource(InputLocked(false))
+            .insert_resource(QueuedMove::default())
+            .insert_resource(DropRelease::default())
+            .insert_resource(GhostTrail::default())
+            .insert_resource(CameraShake::default())
+            .insert_resource(ViewRotation::default())
+            .insert_resource(RestartConfirmation::default())
+            .insert_resource(Turn::FIRST)
+            .insert_resource(Board::new(6, 7, 2))
+            .insert_resource(MoveHistory::new())
+            .insert_resource(GameClock::default())
+            .insert_resource(GameMeta::default())
+            .insert_resource(GravityFlips::default())
+            .insert_resource(UndosTaken::default())
+            .insert_resource(ShowAnalysis::default())
+            .insert_resource(GameMode::HotSeat)
+            .insert_resource(DemoTimer {
+                timer: Timer::from_seconds(DEMO_RESTART_DELAY, TimerMode::Repeating),
+                game_over: false,
+            })
+            .insert_resource(AiMove {
+                timer: Timer::from_seconds(0.0, TimerMode::Once),
+                col: None,
+            })
+            .insert_resource(MirrorMoves::default())
+            .add_startup_system(setup)
+            .add_system_to_stage(CoreStage::Last, autosave_on_exit)
+            .add_system_to_stage(CoreStage::PreUpdate, unshake_camera)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                shake_camera.before(TransformSystem::TransformPropagate),
+            )
+            .add_system(update_theme)
+            .add_system(update_ghost_alpha)
+            .add_system(update_circle_vertices)
+            .add_system(update_history_text)
+            .add_system(trim_history_nodes)
+            .add_system(update_particles)
+            .add_system(update_ghost_trail)
+            .add_system(update_fog_of_war)
+            .add_system(update_legal_columns)
+            .add_system(update_turn_strip)
+            .add_system(check_for_export)
+            .add_system(check_for_dropped_save)
+            .add_system(update_board_layout)
+            .add_system(update_move_numbers)
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(skip_click)
+                    .with_system(apply_view_rotation),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing)
+                    .with_system(unlock_input)
+                    .with_system(hide_ai_intent),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(upright_camera))
+            .add_system_set(SystemSet::on_enter(GameState::Countdown).with_system(start_countdown))
+            .add_system_set(
+                SystemSet::on_update(GameState::Countdown).with_system(update_countdown),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Countdown)
+                    .with_system(end_countdown)
+                    .with_system(unlock_input),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Review).with_system(start_review))
+            .add_system_set(SystemSet::on_update(GameState::Review).with_system(step_review))
+            .add_system_set(SystemSet::on_exit(GameState::Review).with_system(end_review))
+            .add_system_set(SystemSet::on_enter(GameState::Editor).with_system(start_editor))
+            .add_system_set(
+                SystemSet::on_update(GameState::Editor)
+                    .with_system(edit_board)
+                    .with_system(check_for_editor_buttons)
+                    .with_system(update_game_notice),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Editor).with_system(end_editor))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(check_for_game_change)
+                    .with_system(check_for_pause)
+                    .with_system(update_input_lock.after(check_for_game_change))
+                    .with_system(update_drop_release)
+                    .with_system(
+                        check_for_click
+                            .after(update_input_lock)
+                            .after(update_drop_release),
+                    )
+                    .with_system(
+                        check_for_column_button
+                            .after(update_input_lock)
+                            .after(update_drop_release),
+                    )
+                    .with_system(update_column_buttons.after(update_input_lock))
+                    .with_system(check_for_gravity_flip.after(update_input_lock))
+                    .with_system(check_for_grow_board.after(update_input_lock))
+                    .with_system(check_for_random_move.after(update_input_lock))
+                    .with_system(check_for_forced_move.after(update_input_lock))
+                    .with_system(check_for_mouse_movement.after(update_input_lock))
+                    .with_system(check_for_wins.after(check_for_game_change))
+                    .with_system(ai_vs_ai.after(check_for_wins))
+                    .with_system(ai_turn.after(check_for_wins))
+                    .with_system(queue_move.before(ai_turn))
+                    .with_system(play_queued_move.before(update_input_lock))
+                    .with_system(update_ai_intent.after(ai_turn))
+                    .with_system(start_next_match_game.after(check_for_wins))
+                    .with_system(print_board)
+                    .with_system(export_svg)
+                    .with_system(check_for_view_rotation)
+                    .with_system(check_for_analysis_toggle)
+                    .with_system(check_for_hint.after(update_input_lock))
+                    .with_system(update_threat_counters.after(check_for_game_change))
+                    .with_system(check_for_appearance_prompt)
+                    .with_system(check_for_dropped_save_prompt)
+                    .with_system(check_for_pass_screen.after(check_for_wins))
+                    .with_system(check_for_pass_screen_click.before(update_input_lock))
+                    .with_system(update_game_notice)
+                    .with_system(check_for_undo.after(update_input_lock))
+                    .with_system(check_for_restart)
+                    .with_system(update_last_move_marker.after(check_for_game_change))
+                    .with_system(update_falling_disks)
+                    .with_system(update_column_flash)
+                    .with_system(update_clock.after(check_for_game_change))
+                    .with_system(check_for_demo_exit),
+            );
+    }
+}
+
+// To prevent click in menu from spreading to game, called on enter
+// (not when the game is entered with a key, like escape from the quick pause)
+fn skip_click(mut skip_click: ResMut<SkipClick>, buttons: Res<Input<MouseButton>>) {
+    skip_click.0 = buttons.pressed(MouseButton::Left);
+}
+
+// Locks the input while the players can't move, showing a "not allowed" cursor over the game
+// (except in the demo, which is left by clicking anywhere)
+fn update_input_lock(
+    mut windows: ResMut<Windows>,
+    game_mode: Res<GameMode>,
+    turn: Res<Turn>,
+    game_match: Option<Res<Match>>,
+    mut input_locked: ResMut<InputLocked>,
+    pass_overlay: Query<(), With<PassOverlay>>,
+    falling_disks: Query<(), With<FallingDisk>>,
+) {
+    // The pass screen is there to be clicked, so it keeps the normal cursor
+    let passing = !pass_overlay.is_empty();
+    // No moves while a disk is still falling, since whether it won is only checked once it landed
+    // (a short wait, so it doesn't change the cursor either)
+    let falling = !falling_disks.is_empty();
+    let locked = !game_mode.human_turn(*turn) || game_match.is_some_and(|m| m.between_games());
+    if input_locked.0 != (locked || passing || falling) {
+        input_locked.0 = locked || passing || falling;
+    }
+
+    // Clicks during the AI's turn queue the player's next move, so they aren't refused either
+    let queueing = matches!(*game_mode, GameMode::VsAi { .. } | GameMode::MirrorHuman { .. })
+        && !game_mode.human_turn(*turn);
+    let icon = if locked && !queueing && !matches!(*game_mode, GameMode::AiVsAi { .. }) {
+        CursorIcon::NotAllowed
+    } else {
+        CursorIcon::Default
+    };
+    set_cursor_icon(&mut windows, icon);
+}
+
+// The menu never locks the input, so it gets the normal cursor back, called on exit
+fn unlock_input(mut windows: ResMut<Windows>, mut input_locked: ResMut<InputLocked>) {
+    input_locked.0 = false;
+    set_cursor_icon(&mut windows, CursorIcon::Default);
+}
+
+// Shows the board the way round it was left when going back into the game
+fn apply_view_rotation(
+    view_rotation: Res<ViewRotation>,
+    layout: Res<BoardLayout>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+) {
+    for mut camera in &mut camera_query {
+        view_rotation.apply_to_camera(&mut camera, *layout);
+    }
+}
+
+// The menu is always shown upright, whichever way round the board is
+// (the board stays turned around while it is seen behind the quick pause panel)
+fn upright_camera(
+    layout: Res<BoardLayout>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+) {
+    for mut camera in &mut camera_query {
+        ViewRotation::Upright.apply_to_camera(&mut camera, *layout);
+    }
+}
+
+// Changes the cursor over the window, if it isn't that one already
+fn set_cursor_icon(windows: &mut Windows, icon: CursorIcon) {
+    if let Some(window) = windows.get_primary_mut() {
+        if window.cursor_icon() != icon {
+            window.set_cursor_icon(icon);
+        }
+    }
+}
+
+// Creates a completely new game
+fn new_game(
+    commands: &mut Commands,
+    mesh_handles: &MeshHandles,
+    material_handles: &MaterialHandles,
+    colors: &ThemeColors,
+    board: &mut Board,
+    turn: &mut Turn,
+    move_history: &mut MoveHistory,
+    rows: i32,
+    cols: i32,
+    players: u8,
+    asset_server: &AssetServer,
+    layout: BoardLayout,
+) {
+    *board = Board::new(rows, cols, players);
+    *turn = Turn::FIRST;
+    *move_history = MoveHistory::new();
+    commands.remove_resource::<LastMove>();
+    commands.remove_resource::<OfferedAppearance>();
+    commands.insert_resource(GameClock::default());
+    commands.insert_resource(GameMeta::new());
+    commands.insert_resource(GravityFlips::default());
+    commands.insert_resource(UndosTaken::default());
+    announce(commands, Announcement::Turn(*turn));
+
+    // Time played, in the top right corner
+    commands.spawn((
+        TextBundle::from_section(
+            format_clock(0.0),
+            TextStyle {
+                font: asset_server.load(FONT_PATH),
+                font_size: 30.0 * colors.text_scale,
+                color: colors.text,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                right: Val::Px(10.0),
+                top: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        ClockText,
+        InGame,
+    ));
+
+    // The strip above the board, tinted for the player to move by update_turn_strip
+    let dims = get_dimensions(board, 0.0, layout);
+    commands.spawn((
+        SpriteBundle {
+            transform: Transform {
+                translation: Vec3::new(
+                    dims.origin.x + dims.width / 2.0,
+                    dims.origin.y - dims.row_height / 2.0,
+                    -0.1,
+                ),
+                scale: Vec3::new(dims.width, dims.row_height, 0.0),
+                ..default()
+            },
+            sprite: Sprite {
+                color: Color::NONE,
+                ..default()
+            },
+            ..default()
+        },
+        TurnStrip,
+        InGame,
+    ));
+
+    // A button over the strip above each column, for dropping disks into it
+    // (placed by update_column_buttons, since the view can be turned around),
+    // with the threat counter of the analysis on it
+    for col in 0..cols {
+        commands
+            .spawn((
+                ButtonBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(dims.col_width), Val::Px(dims.row_height)),
+                        position_type: PositionType::Absolute,
+                        position: column_button_position(
+                            &dims,
+                            col,
+                            ViewRotation::Upright,
+                            layout,
+                        ),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    background_color: COLUMN_BUTTON_COLOR.into(),
+                    ..default()
+                },
+                ColumnButton { col },
+                InGame,
+            ))
+            .with_children(|parent| {
+                parent.spawn((
+                    TextBundle {
+                        text: Text::from_section(
+                            "",
+                            TextStyle {
+                                font: asset_server.load(FONT_PATH),
+                                font_size: 20.0 * colors.text_scale,
+                                color: colors.text,
+                            },
+                        ),
+                        visibility: Visibility::INVISIBLE,
+                        ..default()
+                    },
+                    ThreatCounter { col },
+                ));
+            });
+    }
+
+    // History as one line of text, in the bottom left corner (empty unless the text history mode is used)
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: asset_server.load(FONT_PATH),
+                font_size: 30.0 * colors.text_scale,
+                color: colors.text,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                bottom: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        HistoryText,
+        InGame,
+    ));
+
+    let hole_dims = get_dimensions(board, HOLE_PADDING, layout);
+    let disk_dims = get_dimensions(board, DISK_PADDING, layout);
+
+    // Add the ghost disks, one for each player (but invisible)
+    for player in 0..board.players {
+        let ghost_disk = GhostDisk(player);
+        commands.spawn((//method to schedule the spawning of an entity with several components
+            ghost_disk,
+            InGame,
+            MaterialMesh2dBundle {
+                mesh: mesh_handles.circle.clone().into(),
+                material: material_handles.get_ghost_disk_material(ghost_disk),
+                transform: Transform {
+                    translation: Vec3::new(0.0, 0.0, 0.0),
+                    scale: Vec3::new(disk_dims.scale, disk_dims.scale, 0.0),
+                    ..default()
+                },
+                //The ghost disk is not visible initially and it only appears when the player hover over the board
+                visibility: Visibility { is_visible: false },
+                ..default()
+            },
+        ));
+
+        // Its trajectory preview, placed (and scaled, for the trail) along with it
+        for preview in [TrajectoryPreview::Trail, TrajectoryPreview::Landing] {
+            commands.spawn((
+                ghost_disk,
+                preview,
+                InGame,
+                MaterialMesh2dBundle {
+                    mesh: mesh_handles.circle.clone().into(),
+                    material: material_handles.get_ghost_disk_material(ghost_disk),
+                    visibility: Visibility { is_visible: false },
+                    ..default()
+                },
+            ));
+        }
+    }
+
+    // Preview of the AI's reply (invisible until the AI picked one), colored for the AI's turn
+    commands.spawn((
+        AiIntentMarker,
+        InGame,
+        MaterialMesh2dBundle {
+            mesh: mesh_handles.circle.clone().into(),
+            material: material_handles.get_ghost_disk_material(GhostDisk(0)),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        },
+    ));
+
+    // Last move marker (invisible until the first move)
+    commands.spawn((
+        LastMoveMarker,
+        InGame,
+        MaterialMesh2dBundle {
+            mesh: mesh_handles.circle.clone().into(),
+            material: material_handles.last_move.clone(),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        },
+    ));
+
+    // Board
+    commands.spawn((
+        SpriteBundle {
+            //component is used to specify the position, rotation, and scale of the entity in the 3D space.
+            transform: Transform {
+                translation: Vec3::new(
+                    hole_dims.origin.x + hole_dims.width / 2.0,
+                    hole_dims.origin.y - hole_dims.row_height - hole_dims.height / 2.0,
+                    0.0,
+                ),
+                scale: Vec3::new(hole_dims.width, hole_dims.height, 0.0),
+                ..default()
+            },
+            //component defines the color of the sprite as the board color of the theme.
+            sprite: Sprite {
+                color: colors.board,
+                ..default()
+            },
+            ..default()
+        },
+        BoardSprite,
+        InGame,
     ));
 
+    // Markers just above the board, one per column (hidden once the column is full)
+    for col in 0..cols {
+        commands.spawn((
+            SpriteBundle {
+                transform: Transform {
+                    translation: Vec3::new(
+                        hole_dims.col_x(col),
+                        hole_dims.origin.y - hole_dims.row_height + LEGAL_COLUMN_HEIGHT,
+                        0.0,
+                    ),
+                    scale: Vec3::new(hole_dims.col_width * HOLE_PADDING, LEGAL_COLUMN_HEIGHT, 0.0),
+                    ..default()
+                },
+                sprite: Sprite {
+                    color: LEGAL_COLUMN_COLOR,
+                    ..default()
+                },
+                ..default()
+            },
+            LegalColumnMarker(col),
+            InGame,
+        ));
+    }
+
     // Draw all the holes
     for row in 0..rows {
         for col in 0..cols {
@@ -452,418 +3191,5174 @@ fn new_game(
                 material_handles,
                 &hole_dims,
                 col,
-                row,
-            );
+                row,
+            );
+        }
+    }
+}
+
+// Initial setup, to load all the materials and meshes
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    theme: Res<Theme>,
+    swap_colors: Res<SwapColors>,
+    ghost_alpha: Res<GhostAlpha>,
+    circle_vertices: Res<CircleVertices>,
+) {
+    let colors = &theme.colors().swapped(*swap_colors);
+    commands.insert_resource(ClearColor(colors.background));
+    commands.insert_resource(MaterialHandles {
+        background: materials.add(ColorMaterial::from(colors.background)),
+        disks: colors
+            .disks
+            .iter()
+            .map(|&color| materials.add(ColorMaterial::from(color)))
+            .collect(),
+        ghost_disks: colors
+            .disks
+            .iter()
+            .map(|&color| materials.add(ColorMaterial::from(ghost_color(color, *ghost_alpha))))
+            .collect(),
+        last_move: materials.add(ColorMaterial::from(colors.last_move)),
+        rock: materials.add(ColorMaterial::from(ROCK_COLOR)),
+        disk_outline: materials.add(ColorMaterial::from(colors.disk_outline)),
+        fog_disk: materials.add(ColorMaterial::from(FOG_DISK_COLOR)),
+    });
+
+    commands.insert_resource(MeshHandles {
+        circle: meshes.add(circle_mesh(*circle_vertices)),
+    });
+}
+
+// Removes all entities in the game
+fn cleanup(commands: &mut Commands, query: Query<Entity, With<InGame>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Replaces the current game with the given data (from a save file or scenario)
+fn load_game(
+    commands: &mut Commands,
+    mesh_handles: &MeshHandles,
+    material_handles: &MaterialHandles,
+    colors: &ThemeColors,
+    board: &mut Board,
+    turn: &mut Turn,
+    move_history: &mut MoveHistory,
+    history_mode: HistoryDisplayMode,
+    asset_server: &AssetServer,
+    layout: BoardLayout,
+    data: GameData,
+) {
+    new_game(
+        commands,
+        mesh_handles,
+        material_handles,
+        colors,
+        board,
+        turn,
+        move_history,
+        data.board.rows,
+        data.board.cols,
+        data.board.players,
+        asset_server,
+        layout,
+    );
+    *board = data.board;
+    *turn = data.turn;
+    *move_history = data.history;
+    commands.insert_resource(data.clock);
+    commands.insert_resource(data.meta);
+    if let Some(last_move) = move_history.last_move(board) {
+        commands.insert_resource(last_move);
+    }
+
+    // All the data is now loaded, but the visuals need to sync
+    // Add the disks and history
+    draw_disks(commands, mesh_handles, material_handles, board, layout);
+
+    // Add history (in the text history mode, the text updates by itself)
+    if history_mode == HistoryDisplayMode::Nodes {
+        for (i, record) in move_history.moves.iter().rev().enumerate() {
+            commands
+                .spawn((
+                    Move,
+                    NodeBundle {
+                        style: Style {
+                            size: Size::new(
+                                Val::Percent(10.0),
+                                Val::Percent((1.0 - BOARD_SCALE.y) * 100.0),
+                            ),
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                left: Val::Percent(10.0 * i as f32),
+                                bottom: Val::Percent(0.0),
+                                ..default()
+                            },
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            align_content: AlignContent::Center,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    InGame,
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle {
+                            text: Text::from_section(
+                                format!("{}", record.col + 1),
+                                TextStyle {
+                                    font: asset_server.load(FONT_PATH),
+                                    font_size: 40.0 * colors.text_scale,
+                                    color: record.turn.to_color(colors),
+                                },
+                            ),
+                            style: Style {
+                                align_content: AlignContent::Center,
+                                align_items: AlignItems::Center,
+                                justify_content: JustifyContent::Center,
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        PlayerText(record.turn),
+                    ));
+                });
+        }
+    }
+}
+
+// Autosaves the game when the app is closing, if it can still be played.
+// Otherwise the old autosave is removed, so a finished game is never offered to continue.
+fn autosave_on_exit(
+    mut exit_events: EventReader<AppExit>,
+    autosave: Res<Autosave>,
+    game_state: Res<State<GameState>>,
+    main_menu_info: Res<MainMenuInfo>,
+    game_mode: Res<GameMode>,
+    puzzle: Option<Res<ActivePuzzle>>,
+    board: Res<Board>,
+    turn: Res<Turn>,
+    history: Res<MoveHistory>,
+    clock: Res<GameClock>,
+    theme: Res<Theme>,
+    game_meta: Res<GameMeta>,
+) {
+    if exit_events.iter().next().is_none() || !autosave.0 {
+        return;
+    }
+
+    // Games left from the menu can only be continued if they could be resumed
+    let in_game = match game_state.current() {
+        GameState::Playing | GameState::Paused | GameState::Countdown => true,
+        GameState::Menu => main_menu_info.allow_resume,
+        // Only finished games are reviewed, an edited position isn't a game yet,
+        // and no game is played before loading is done
+        GameState::Review | GameState::Editor | GameState::Loading => false,
+    };
+    let resumable = in_game
+        && board.check_for_wins().is_none()
+        && !matches!(*game_mode, GameMode::AiVsAi { .. })
+        && puzzle.is_none();
+
+    if resumable {
+        write_save(
+            AUTOSAVE_FILE,
+            &GameData::new(&board, *turn, &history, *clock, *theme, &game_meta),
+        );
+    } else {
+        remove_save(AUTOSAVE_FILE);
+    }
+}
+
+// Checks for the game change event
+fn check_for_game_change(
+    mut commands: Commands,
+    query: Query<Entity, With<InGame>>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut move_history: ResMut<MoveHistory>,
+    mut game_change_events: EventReader<GameChange>,
+    asset_server: Res<AssetServer>,
+    mut game_state: ResMut<State<GameState>>,
+    mut main_menu_info: ResMut<MainMenuInfo>,
+    mut ghost_disks: Query<&mut Visibility, With<GhostDisk>>,
+    mut game_mode: ResMut<GameMode>,
+    (mut demo_timer, demo_delay, mut ai_move, mut queued_move): (
+        ResMut<DemoTimer>,
+        Res<DemoDelay>,
+        ResMut<AiMove>,
+        ResMut<QueuedMove>,
+    ),
+    (theme, swap_colors, history_mode, layout): (
+        Res<Theme>,
+        Res<SwapColors>,
+        Res<HistoryDisplayMode>,
+        Res<BoardLayout>,
+    ),
+    (clock, game_meta, mut game_match, countdown, mut mirror_moves): (
+        Res<GameClock>,
+        Res<GameMeta>,
+        Option<ResMut<Match>>,
+        Res<Countdown>,
+        ResMut<MirrorMoves>,
+    ),
+) {
+    if let Some(game_change) = game_change_events.iter().next() {
+        match game_change {
+            &GameChange::New {
+                rows,
+                cols,
+                players,
+                rocks,
+                drop_pattern,
+                win_condition,
+                win_directions,
+                ref win_lengths,
+                best_of,
+            } => {
+                // The menu doesn't allow these, but somebody could never win, so don't start it
+                let longest = win_lengths.iter().copied().max().unwrap_or(DEFAULT_WIN_LENGTH);
+                if !Board::fits_win_length(rows, cols, longest) {
+                    warn!("Board {}x{} is too small to win", rows, cols);
+                    main_menu_info.winner = None;
+                    main_menu_info.message = Some("Board too small to win".to_string());
+                    game_state.set(GameState::Menu).unwrap();
+                    return;
+                }
+
+                // Remove all the entities in the game, then create a new one
+                cleanup(&mut commands, query);
+                commands.remove_resource::<ActivePuzzle>();
+                *game_mode = GameMode::HotSeat;
+                new_game(
+                    &mut commands,
+                    &mesh_handles,
+                    &material_handles,
+                    theme.colors(),
+                    &mut board,
+                    &mut turn,
+                    &mut move_history,
+                    rows,
+                    cols,
+                    players,
+                    &asset_server,
+                    *layout,
+                );
+                board.drop_pattern = drop_pattern;
+                board.win_condition = win_condition;
+                board.win_directions = win_directions;
+                board.win_lengths = win_lengths.clone();
+                // A single game ends any match that was being played
+                match best_of {
+                    Some(games) => commands.insert_resource(Match::best_of(games, players)),
+                    None => commands.remove_resource::<Match>(),
+                }
+                if let Some(seed) = rocks {
+                    board.place_rocks(seed);
+                    draw_disks(
+                        &mut commands,
+                        &mesh_handles,
+                        &material_handles,
+                        &board,
+                        *layout,
+                    );
+                }
+            }
+            GameChange::Save => {
+                let data =
+                    GameData::new(&board, *turn, &move_history, *clock, *theme, &game_meta);
+                if let Some(confirmation) = save_game(SAVE_FILE, &data) {
+                    spawn_toast(&mut commands, &asset_server, theme.colors(), confirmation);
+                }
+            }
+            // Loading a save and continuing the autosave only differ in the file
+            GameChange::Load | GameChange::LoadFile { .. } | GameChange::Continue => {
+                let path = match game_change {
+                    GameChange::Continue => AUTOSAVE_FILE,
+                    GameChange::LoadFile { path } => path.as_str(),
+                    _ => SAVE_FILE,
+                };
+                let Some(mut data) = read_save(path) else {
+                    return;
+                };
+
+                // Drawing a board of an impossible size would crash, so it isn't loaded at all
+                if !data.validate_board_size() {
+                    warn!(
+                        "Save file {} has an impossible board size of {}x{}",
+                        path, data.board.rows, data.board.cols
+                    );
+                    main_menu_info.winner = None;
+                    main_menu_info.message = Some("The save's board size is broken".to_string());
+                    game_state.set(GameState::Menu).unwrap();
+                    return;
+                }
+
+                // A turn that doesn't fit the disks is corrected if some turn does,
+                // otherwise the save can't be from a real game
+                let corrected_turn = !data.validate_turn_parity();
+                if corrected_turn {
+                    let Some(turn) = data.fitting_turns().next() else {
+                        warn!("Save file {} has impossible disk counts", path);
+                        main_menu_info.winner = None;
+                        main_menu_info.message = Some("The save is broken".to_string());
+                        game_state.set(GameState::Menu).unwrap();
+                        return;
+                    };
+                    warn!(
+                        "Save file {} had {} to move, corrected to {}",
+                        path, data.turn, turn
+                    );
+                    data.turn = turn;
+                }
+
+                cleanup(&mut commands, query);
+                commands.remove_resource::<ActivePuzzle>();
+                commands.remove_resource::<Match>();
+                *game_mode = GameMode::HotSeat;
+                let offered_appearance = data.offered_appearance(*theme);
+                load_game(
+                    &mut commands,
+                    &mesh_handles,
+                    &material_handles,
+                    &theme.colors().swapped(*swap_colors),
+                    &mut board,
+                    &mut turn,
+                    &mut move_history,
+                    *history_mode,
+                    &asset_server,
+                    *layout,
+                    data,
+                );
+
+                // A save that looked different (like one from another machine) can be shown
+                // the way it looked, or keep the current look
+                if let Some(appearance) = offered_appearance {
+                    commands.insert_resource(OfferedAppearance(appearance));
+                    commands.spawn((
+                        TextBundle::from_section(
+                            format!(
+                                "Saved with the {} theme: {:?} to use it, {:?} to keep yours",
+                                appearance.theme.name(),
+                                APPLY_APPEARANCE_KEY,
+                                KEEP_APPEARANCE_KEY,
+                            ),
+                            TextStyle {
+                                font: asset_server.load(FONT_PATH),
+                                font_size: 25.0 * theme.colors().text_scale,
+                                color: theme.colors().text,
+                            },
+                        )
+                        .with_style(Style {
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                left: Val::Px(10.0),
+                                top: Val::Px(10.0),
+                                ..default()
+                            },
+                            ..default()
+                        }),
+                        AppearancePrompt,
+                        InGame,
+                    ));
+                }
+
+                // The correction is more important than the confirmation, they'd overlap
+                if corrected_turn {
+                    spawn_notice(
+                        &mut commands,
+                        &asset_server,
+                        theme.colors(),
+                        format!(
+                            "The save had the wrong player to move, it's {}'s turn",
+                            *turn
+                        ),
+                    );
+                } else {
+                    spawn_toast(
+                        &mut commands,
+                        &asset_server,
+                        theme.colors(),
+                        format!("Game loaded from {}", path),
+                    );
+                }
+            }
+            GameChange::Puzzle => {
+                let Ok(puzzle) = todays_puzzle() else {
+                    warn!("Failed to read today's puzzle");
+                    return;
+                };
+
+                cleanup(&mut commands, query);
+                commands.remove_resource::<Match>();
+                *game_mode = GameMode::HotSeat;
+                load_game(
+                    &mut commands,
+                    &mesh_handles,
+                    &material_handles,
+                    &theme.colors().swapped(*swap_colors),
+                    &mut board,
+                    &mut turn,
+                    &mut move_history,
+                    *history_mode,
+                    &asset_server,
+                    *layout,
+                    puzzle.scenario,
+                );
+                // The scenario has no dates of its own, the puzzle is a new game
+                commands.insert_resource(GameMeta::new());
+                commands.insert_resource(ActivePuzzle {
+                    player: *turn,
+                    moves: puzzle.moves,
+                    start: move_history.moves_made(),
+                });
+
+                // Objective, shown above the board for the whole puzzle
+                commands.spawn((
+                    TextBundle::from_section(
+                        puzzle.objective,
+                        TextStyle {
+                            font: asset_server.load(FONT_PATH),
+                            font_size: 30.0 * theme.colors().text_scale,
+                            color: turn.to_color(&theme.colors().swapped(*swap_colors)),
+                        },
+                    )
+                    .with_style(Style {
+                        position_type: PositionType::Absolute,
+                        position: UiRect {
+                            left: Val::Px(10.0),
+                            top: Val::Px(10.0),
+                            ..default()
+                        },
+                        ..default()
+                    }),
+                    PlayerText(*turn),
+                    InGame,
+                ));
+            }
+            &GameChange::Demo { rows, cols, depth } => {
+                // Demo games are always between two AIs
+                cleanup(&mut commands, query);
+                commands.remove_resource::<ActivePuzzle>();
+                commands.remove_resource::<Match>();
+                new_game(
+                    &mut commands,
+                    &mesh_handles,
+                    &material_handles,
+                    theme.colors(),
+                    &mut board,
+                    &mut turn,
+                    &mut move_history,
+                    rows,
+                    cols,
+                    2,
+                    &asset_server,
+                    *layout,
+                );
+                *game_mode = GameMode::AiVsAi { depth };
+                demo_timer.game_over = false;
+                demo_timer
+                    .timer
+                    .set_duration(Duration::from_secs_f32(demo_delay.0));
+                demo_timer.timer.reset();
+                if countdown.0 {
+                    game_state.set(GameState::Countdown).unwrap();
+                }
+            }
+            &GameChange::VsAi { rows, cols, depth } => {
+                // The player moves first, the AI second
+                cleanup(&mut commands, query);
+                commands.remove_resource::<ActivePuzzle>();
+                commands.remove_resource::<Match>();
+                new_game(
+                    &mut commands,
+                    &mesh_handles,
+                    &material_handles,
+                    theme.colors(),
+                    &mut board,
+                    &mut turn,
+                    &mut move_history,
+                    rows,
+                    cols,
+                    2,
+                    &asset_server,
+                    *layout,
+                );
+                let mut ai = Turn::FIRST;
+                ai.next(2);
+                *game_mode = GameMode::VsAi { depth, ai };
+                ai_move.col = None;
+                queued_move.0 = None;
+                if countdown.0 {
+                    game_state.set(GameState::Countdown).unwrap();
+                }
+            }
+            &GameChange::MirrorHuman { rows, cols } => {
+                // Set up like a game against the AI, which plays the recorded moves instead
+                cleanup(&mut commands, query);
+                commands.remove_resource::<ActivePuzzle>();
+                commands.remove_resource::<Match>();
+                new_game(
+                    &mut commands,
+                    &mesh_handles,
+                    &material_handles,
+                    theme.colors(),
+                    &mut board,
+                    &mut turn,
+                    &mut move_history,
+                    rows,
+                    cols,
+                    2,
+                    &asset_server,
+                    *layout,
+                );
+                let mut ai = Turn::FIRST;
+                ai.next(2);
+                *game_mode = GameMode::MirrorHuman { ai };
+                mirror_moves.start();
+                ai_move.col = None;
+                queued_move.0 = None;
+                if countdown.0 {
+                    game_state.set(GameState::Countdown).unwrap();
+                }
+            }
+            // Handled by check_for_grow_board, which keeps the game going
+            GameChange::GrowBoard => {}
+            // Handled by check_for_export, which also runs in the menu
+            GameChange::ExportReplay { .. } => {}
+            GameChange::Resign => {
+                // Hide all ghost disks, the board stays as it is
+                for mut ghost_disk_visibility in &mut ghost_disks {
+                    ghost_disk_visibility.is_visible = false;
+                }
+
+                // The player whose turn it is gives up (the whole match, if one is played),
+                // so the player that moved before them wins
+                commands.remove_resource::<Match>();
+                announce(&mut commands, Announcement::Win(turn.previous(board.players)));
+                main_menu_info.allow_resume = false;
+                main_menu_info.winner = Some(turn.previous(board.players));
+                main_menu_info.message = None;
+                main_menu_info.won_match = false;
+                game_state.set(GameState::Menu).unwrap();
+            }
+            GameChange::Draw => {
+                // Hide all ghost disks, the board stays as it is
+                for mut ghost_disk_visibility in &mut ghost_disks {
+                    ghost_disk_visibility.is_visible = false;
+                }
+
+                // Nobody wins the game. A match ends with it, counted as a drawn game.
+                announce(&mut commands, Announcement::Draw);
+                end_in_draw(&mut main_menu_info, game_match.as_deref_mut());
+                commands.remove_resource::<Match>();
+                game_state.set(GameState::Menu).unwrap();
+            }
+        }
+    }
+
+    game_change_events.clear();
+}
+
+// Exports the replay of the current game when asked to, the result is shown in the menu
+fn check_for_export(
+    mut game_change_events: EventReader<GameChange>,
+    board: Res<Board>,
+    history: Res<MoveHistory>,
+    theme: Res<Theme>,
+    swap_colors: Res<SwapColors>,
+    mut main_menu_info: ResMut<MainMenuInfo>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    for game_change in game_change_events.iter() {
+        let GameChange::ExportReplay { dir } = game_change else {
+            continue;
+        };
+
+        let boards = history.replay(&board);
+        let colors = theme.colors().swapped(*swap_colors);
+        main_menu_info.message = Some(match export_frames(dir, &boards, &colors) {
+            Ok(frames) => format!("Saved {} frames to {}", frames, dir),
+            Err(err) => {
+                warn!("{}", err);
+                "Failed to export replay".to_string()
+            }
+        });
+        if *game_state.current() == GameState::Menu {
+            game_state.restart().unwrap();
+        }
+    }
+}
+
+// Loads a save file dropped onto the window, in the menu or in the game. Files that aren't
+// valid saves are ignored with a notice, and a game in progress is only replaced once confirmed.
+fn check_for_dropped_save(
+    mut commands: Commands,
+    mut drop_events: EventReader<FileDragAndDrop>,
+    board: Res<Board>,
+    history: Res<MoveHistory>,
+    mut game_change: EventWriter<GameChange>,
+    mut game_state: ResMut<State<GameState>>,
+    mut main_menu_info: ResMut<MainMenuInfo>,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    prompt_query: Query<Entity, With<DroppedSavePrompt>>,
+) {
+    // Only one game can be loaded, so the last file dropped is the one used
+    let Some(path_buf) = drop_events
+        .iter()
+        .filter_map(|event| match event {
+            FileDragAndDrop::DroppedFile { path_buf, .. } => Some(path_buf.clone()),
+            _ => None,
+        })
+        .next_back()
+    else {
+        return;
+    };
+    let path = path_buf.to_string_lossy().into_owned();
+    let name = path_buf
+        .file_name()
+        .map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+
+    // Checked the same way as when it's loaded, so nothing is discarded for a file that fails
+    let is_json = path_buf.extension().is_some_and(|extension| extension == "json");
+    if !is_json || !read_save(&path).is_some_and(|data| data.playable()) {
+        warn!("Dropped file {} is not a valid save", path);
+        let text = format!("{} is not a valid save", name);
+        if *game_state.current() == GameState::Menu {
+            main_menu_info.message = Some(text);
+            game_state.restart().unwrap();
+        } else {
+            spawn_notice(&mut commands, &asset_server, theme.colors(), text);
+        }
+        return;
+    }
+
+    let in_game = matches!(
+        game_state.current(),
+        GameState::Playing | GameState::Paused
+    );
+    if in_game && game_in_progress(&board, &history) {
+        for entity in &prompt_query {
+            commands.entity(entity).despawn_recursive();
+        }
+        commands.insert_resource(DroppedSave(path));
+        commands.spawn((
+            TextBundle::from_section(
+                format!(
+                    "Load {}? {:?} to load it, {:?} to keep this game",
+                    name, LOAD_DROPPED_KEY, KEEP_GAME_KEY,
+                ),
+                TextStyle {
+                    font: asset_server.load(FONT_PATH),
+                    font_size: 25.0 * theme.colors().text_scale,
+                    color: theme.colors().text,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(70.0),
+                    ..default()
+                },
+                ..default()
+            }),
+            DroppedSavePrompt,
+            InGame,
+        ));
+    } else {
+        game_change.send(GameChange::LoadFile { path });
+    }
+    // The answer to the prompt (and the load) happens in the game
+    if *game_state.current() != GameState::Playing {
+        game_state.set(GameState::Playing).unwrap();
+    }
+}
+
+// Loads the dropped save, or keeps the game in progress, when a key is pressed
+fn check_for_dropped_save_prompt(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    dropped_save: Option<Res<DroppedSave>>,
+    mut game_change: EventWriter<GameChange>,
+    prompt_query: Query<Entity, With<DroppedSavePrompt>>,
+) {
+    let Some(dropped_save) = dropped_save else {
+        return;
+    };
+
+    // The prompt is gone with the game it was shown over, and so is the question
+    if prompt_query.is_empty() {
+        commands.remove_resource::<DroppedSave>();
+        return;
+    }
+
+    if keyboard_input.just_pressed(LOAD_DROPPED_KEY) {
+        game_change.send(GameChange::LoadFile {
+            path: dropped_save.0.clone(),
+        });
+    } else if !keyboard_input.just_pressed(KEEP_GAME_KEY) {
+        return;
+    }
+
+    commands.remove_resource::<DroppedSave>();
+    for entity in &prompt_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Check for player pressing escape to pause the game (the quick pause panel leads to the menu)
+fn check_for_pause(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut game_state: ResMut<State<GameState>>,
+    mut main_menu_info: ResMut<MainMenuInfo>,
+    game_mode: Res<GameMode>,
+    autosave: Res<Autosave>,
+    puzzle: Option<Res<ActivePuzzle>>,
+    board: Res<Board>,
+    turn: Res<Turn>,
+    history: Res<MoveHistory>,
+    clock: Res<GameClock>,
+    theme: Res<Theme>,
+    game_meta: Res<GameMeta>,
+) {
+    // The demo handles all keys itself
+    if let GameMode::AiVsAi { .. } = *game_mode {
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        // Puzzles can't be continued from a save
+        if autosave.0 && puzzle.is_none() {
+            write_save(
+                AUTOSAVE_FILE,
+                &GameData::new(&board, *turn, &history, *clock, *theme, &game_meta),
+            );
+        }
+
+        main_menu_info.allow_resume = true;
+        main_menu_info.winner = None;
+        main_menu_info.message = None;
+        game_state.set(GameState::Paused).unwrap();
+    }
+}
+
+// Convert the mouse position to the row of the board it is over, as it is shown in the view
+// (None over the strip above the board, or outside it)
+fn mouse_pos_to_row(
+    mouse_pos: Vec2,
+    board: &Board,
+    view_rotation: ViewRotation,
+    layout: BoardLayout,
+) -> Option<i32> {
+    let mouse_pos = view_rotation.to_upright(mouse_pos, layout);
+    // The mouse position starts at the bottom of the window, the rows go down from the strip
+    let dims = get_dimensions(board, 0.0, layout);
+    let row = ((dims.window_origin().y - mouse_pos.y) / dims.row_height).floor() as i32 - 1;
+    (0..board.rows).contains(&row).then_some(row)
+}
+
+// Convert the mouse position to the cell of the board it is over as (row, col), as it is shown
+// in the view (None outside the board)
+fn mouse_pos_to_cell(
+    mouse_pos: Vec2,
+    board: &Board,
+    view_rotation: ViewRotation,
+    layout: BoardLayout,
+) -> Option<(i32, i32)> {
+    let row = mouse_pos_to_row(mouse_pos, board, view_rotation, layout)?;
+    let col = mouse_pos_to_col(mouse_pos, board, DropInputRegion::Board, view_rotation, layout)?;
+    Some((row, col))
+}
+
+// Convert the mouse position to the column in the board, as it is shown in the view
+// (only counting the board and the strip above it, unless clicks anywhere are allowed)
+fn mouse_pos_to_col(
+    mouse_pos: Vec2,
+    board: &Board,
+    region: DropInputRegion,
+    view_rotation: ViewRotation,
+    layout: BoardLayout,
+) -> Option<i32> {
+    let mouse_pos = view_rotation.to_upright(mouse_pos, layout);
+    // The mouse position starts at the bottom of the window, the board goes down from the strip
+    let dims = get_dimensions(board, 0.0, layout);
+    let origin = dims.window_origin();
+    if region == DropInputRegion::Board && mouse_pos.y < origin.y - dims.row_height - dims.height {
+        return None;
+    }
+
+    x_to_col(mouse_pos.x - origin.x, dims.width, board.cols)
+}
+
+// The column at x on a board of the given width, None left of it (like a cursor slightly off the
+// window) or from its right edge on. Rounding can't push the last pixel into a column past the end.
+fn x_to_col(x: f32, board_width: f32, cols: i32) -> Option<i32> {
+    if !(0.0..board_width).contains(&x) {
+        return None;
+    }
+    let col = (x / (board_width / cols as f32)).floor() as i32;
+    Some(col.min(cols - 1))
+}
+
+// Add a disk to the board and screen, recording the move with the time on the game clock
+// (a floating first disk is placed at the chosen row, if given)
+fn drop_disk(
+    mut commands: Commands,
+    mesh_handles: &MeshHandles,
+    material_handles: &MaterialHandles,
+    colors: &ThemeColors,
+    board: &mut Board,
+    turn: &mut Turn,
+    history: &mut MoveHistory,
+    history_mode: HistoryDisplayMode,
+    history_limit: HistoryLimit,
+    layout: BoardLayout,
+    mut query: Query<&mut Style, With<Move>>,
+    asset_server: Res<AssetServer>,
+    col: i32,
+    chosen_row: Option<i32>,
+    time: f32,
+) {
+    if let Some(row) = board.drop_disk_at(col, turn.to_disk(), chosen_row) {
+        log_event(&GameEvent::Drop {
+            col,
+            turn: *turn,
+            row,
+        });
+        // The disk falls in from just outside the edge of the board it enters the column at
+        let entry_row = if board.drop_pattern.falls_down(col) {
+            -1
+        } else {
+            board.rows
+        };
+        draw_disk(
+            &mut commands,
+            mesh_handles,
+            material_handles,
+            &get_dimensions(board, DISK_PADDING, layout),
+            col,
+            row,
+            turn.to_disk(),
+            Some(entry_row),
+        );
+        announce(
+            &mut commands,
+            Announcement::Drop {
+                turn: *turn,
+                col: col + 1,
+                row: board.rows - row,
+            },
+        );
+
+        // Add to history
+        history.push(
+            MoveRecord {
+                col,
+                turn: *turn,
+                time,
+                row: chosen_row.filter(|&chosen_row| chosen_row == row),
+            },
+            history_limit,
+        );
+        commands.insert_resource(LastMove { row, col });
+
+        // The history is either a node per move, or one line of text (updated by its own system)
+        if history_mode == HistoryDisplayMode::Nodes {
+            // Shift all other history moves to the right
+            for mut style in &mut query {
+                style.position.left = style.position.left.try_add(Val::Percent(10.0)).unwrap();
+            }
+
+            commands
+                .spawn((
+                    Move,
+                    NodeBundle {
+                        style: Style {
+                            size: Size::new(
+                                Val::Percent(10.0),
+                                Val::Percent((1.0 - BOARD_SCALE.y) * 100.0),
+                            ),
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                left: Val::Percent(0.0),
+                                bottom: Val::Percent(0.0),
+                                ..default()
+                            },
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            align_content: AlignContent::Center,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    InGame,
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle {
+                            text: Text::from_section(
+                                format!("{}", col + 1),
+                                TextStyle {
+                                    font: asset_server.load(FONT_PATH),
+                                    font_size: 40.0 * colors.text_scale,
+                                    color: turn.to_color(colors),
+                                },
+                            ),
+                            style: Style {
+                                align_content: AlignContent::Center,
+                                align_items: AlignItems::Center,
+                                justify_content: JustifyContent::Center,
+                                ..default()
+                            },
+                            ..default()
+                        },
+                        PlayerText(*turn),
+                    ));
+                });
+        }
+
+        turn.next(board.players);
+        announce(&mut commands, Announcement::Turn(*turn));
+    }
+}
+
+// Recolors everything in the game when the theme or the disk colors are changed (in the menu),
+// and resizes the text
+fn update_theme(
+    theme: Res<Theme>,
+    swap_colors: Res<SwapColors>,
+    ghost_alpha: Res<GhostAlpha>,
+    material_handles: Res<MaterialHandles>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut clear_color: ResMut<ClearColor>,
+    mut board_query: Query<&mut Sprite, (With<BoardSprite>, Without<WinnerLine>)>,
+    mut winner_line_query: Query<&mut Sprite, With<WinnerLine>>,
+    winner_ring_query: Query<&Handle<ColorMaterial>, With<WinnerRing>>,
+    mut text_query: Query<(&mut Text, &PlayerText)>,
+    mut clock_text_query: Query<&mut Text, (With<ClockText>, Without<PlayerText>)>,
+    mut previous_theme: Local<Theme>,
+) {
+    let previous = std::mem::replace(&mut *previous_theme, *theme);
+    if !(theme.is_changed() || swap_colors.is_changed()) || theme.is_added() {
+        return;
+    }
+
+    let colors = &theme.colors().swapped(*swap_colors);
+    let text_scale = colors.text_scale / previous.colors().text_scale;
+    material_handles.set_colors(&mut materials, colors, *ghost_alpha);
+    clear_color.0 = colors.background;
+    for mut sprite in &mut board_query {
+        sprite.color = colors.board;
+    }
+    for mut sprite in &mut winner_line_query {
+        sprite.color = colors.winner;
+    }
+    for handle in &winner_ring_query {
+        if let Some(material) = materials.get_mut(handle) {
+            material.color = colors.winner;
+        }
+    }
+    for (mut text, &PlayerText(turn)) in &mut text_query {
+        text.sections[0].style.color = turn.to_color(colors);
+        text.sections[0].style.font_size *= text_scale;
+    }
+    for mut text in &mut clock_text_query {
+        text.sections[0].style.color = colors.text;
+        text.sections[0].style.font_size *= text_scale;
+    }
+}
+
+// Changes how see-through the ghost disks are when the setting changes. Their materials are
+// shared by all the ghost disks (and made once in setup), so only those need to change.
+fn update_ghost_alpha(
+    ghost_alpha: Res<GhostAlpha>,
+    material_handles: Res<MaterialHandles>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if !ghost_alpha.is_changed() || ghost_alpha.is_added() {
+        return;
+    }
+    material_handles.set_ghost_alpha(&mut materials, *ghost_alpha);
+}
+
+// Rebuilds the circle with the new number of corners when the setting changes. It is shared by
+// everything round in the game, so replacing it changes them all at once.
+fn update_circle_vertices(
+    circle_vertices: Res<CircleVertices>,
+    mesh_handles: Res<MeshHandles>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    if !circle_vertices.is_changed() || circle_vertices.is_added() {
+        return;
+    }
+    if let Some(mesh) = meshes.get_mut(&mesh_handles.circle) {
+        *mesh = circle_mesh(*circle_vertices);
+    }
+}
+
+// Ends the game without a winner, counting it as a drawn game of the match if one is played
+fn end_in_draw(main_menu_info: &mut MainMenuInfo, game_match: Option<&mut Match>) {
+    main_menu_info.allow_resume = false;
+    main_menu_info.winner = None;
+    main_menu_info.won_match = false;
+    main_menu_info.message = Some(match game_match {
+        Some(game_match) => {
+            game_match.record_draw();
+            format!("Game drawn, final score: {}", game_match.score())
+        }
+        None => "Game drawn".to_string(),
+    });
+}
+
+// Name of a column in the history, a letter from the left (a, b, c, ...),
+// or its number on boards too wide for the alphabet
+pub fn column_label(col: i32) -> String {
+    match u8::try_from(col) {
+        Ok(col) if col < 26 => char::from(b'a' + col).to_string(),
+        _ => (col + 1).to_string(),
+    }
+}
+
+// Shows the markers of the columns that can still take a disk, and hides the ones of full columns
+fn update_legal_columns(
+    board: Res<Board>,
+    mut query: Query<(
+        &LegalColumnMarker,
+        &mut Visibility,
+        ChangeTrackers<LegalColumnMarker>,
+    )>,
+) {
+    for (marker, mut visibility, tracker) in &mut query {
+        if board.is_changed() || tracker.is_added() {
+            visibility.is_visible = !board.column_full(marker.0);
+        }
+    }
+}
+
+// Removes the history nodes of moves the history no longer keeps. The nodes are a tenth of the
+// width apart with the latest move on the left, so those are the ones past the kept moves.
+fn trim_history_nodes(
+    mut commands: Commands,
+    history: Res<MoveHistory>,
+    query: Query<(Entity, &Style), With<Move>>,
+) {
+    if !history.is_changed() || history.trimmed == 0 {
+        return;
+    }
+    let kept_width = 10.0 * history.moves.len() as f32;
+    for (entity, style) in &query {
+        if matches!(style.position.left, Val::Percent(left) if left > kept_width - 5.0) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// Rebuilds the history text when a move is made or the history is replaced, each move in the color of its player.
+// Only the latest moves fit on the line, older ones are cut off.
+fn update_history_text(
+    history: Res<MoveHistory>,
+    theme: Res<Theme>,
+    swap_colors: Res<SwapColors>,
+    history_mode: Res<HistoryDisplayMode>,
+    asset_server: Res<AssetServer>,
+    mut text_query: Query<(&mut Text, ChangeTrackers<HistoryText>)>,
+) {
+    for (mut text, tracker) in &mut text_query {
+        let changed = history.is_changed()
+            || theme.is_changed()
+            || swap_colors.is_changed()
+            || history_mode.is_changed();
+        if !changed && !tracker.is_added() {
+            continue;
+        }
+
+        text.sections.clear();
+        if *history_mode != HistoryDisplayMode::Text {
+            continue;
+        }
+
+        let style = |color| TextStyle {
+            font: asset_server.load(FONT_PATH),
+            font_size: 30.0 * theme.colors().text_scale,
+            color,
+        };
+        let colors = &theme.colors().swapped(*swap_colors);
+        let skipped = history.moves.len().saturating_sub(HISTORY_TEXT_MOVES);
+        if skipped > 0 {
+            text.sections
+                .push(TextSection::new("... ", style(colors.text)));
+        }
+        for record in &history.moves[skipped..] {
+            text.sections.push(TextSection::new(
+                format!("{} ", column_label(record.col)),
+                style(record.turn.to_color(colors)),
+            ));
+        }
+    }
+}
+
+// Time as minutes and seconds, like 03:25
+fn format_clock(seconds: f32) -> String {
+    let seconds = seconds as u32;
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
+
+// Advances the game clock (this only runs while playing, so the menu pauses it) and shows it
+fn update_clock(
+    time: Res<Time>,
+    mut clock: ResMut<GameClock>,
+    mut text_query: Query<&mut Text, With<ClockText>>,
+) {
+    clock.0 += time.delta_seconds();
+    for mut text in &mut text_query {
+        text.sections[0].value = format_clock(clock.0);
+    }
+}
+
+// Keeps the last move marker around the last placed disk, pulsing (unless motion is reduced)
+fn update_last_move_marker(
+    time: Res<Time>,
+    animation_speed: Res<AnimationSpeed>,
+    reduced_motion: Res<ReducedMotion>,
+    board: Res<Board>,
+    layout: Res<BoardLayout>,
+    last_move: Option<Res<LastMove>>,
+    mut marker_query: Query<(&mut Transform, &mut Visibility), With<LastMoveMarker>>,
+) {
+    for (mut transform, mut visibility) in &mut marker_query {
+        let Some(last_move) = &last_move else {
+            visibility.is_visible = false;
+            continue;
+        };
+
+        let dims = get_dimensions(&board, HOLE_PADDING, *layout);
+        let pulse = if reduced_motion.0 {
+            0.0
+        } else {
+            let phase = time.elapsed_seconds() * LAST_MOVE_PULSE_SPEED * animation_speed.0;
+            LAST_MOVE_PULSE * phase.sin()
+        };
+
+        // Between the hole and the disk, so only a ring around the disk shows
+        *transform = get_disk_transform(&dims, last_move.row, last_move.col);
+        transform.translation.z = 0.15;
+        transform.scale *= Vec3::splat(LAST_MOVE_SCALE + pulse);
+        visibility.is_visible = true;
+    }
+}
+
+// Lets the dropped disks fall into their places, faster and faster with the fall gravity
+// (right away when motion is reduced)
+fn update_falling_disks(
+    mut commands: Commands,
+    time: Res<Time>,
+    animation_speed: Res<AnimationSpeed>,
+    reduced_motion: Res<ReducedMotion>,
+    fall_gravity: Res<FallGravity>,
+    mut query: Query<(Entity, &mut FallingDisk, &mut Transform)>,
+) {
+    let delta = time.delta_seconds() * animation_speed.0;
+    for (entity, mut falling, mut transform) in &mut query {
+        falling.speed += fall_gravity.0.max(MIN_FALL_GRAVITY) * delta;
+        let distance = falling.target.y - transform.translation.y;
+        let step = falling.speed * delta;
+        if reduced_motion.0 || step >= distance.abs() {
+            transform.translation = falling.target;
+            commands.entity(entity).remove::<FallingDisk>();
+        } else {
+            transform.translation.y += step * distance.signum();
+        }
+    }
+}
+
+// Shows the disks of the other players in gray with fog of war, except for a moment after each is
+// dropped. The others are the ones of whoever's turn it is (of the player, against the AI).
+// Once the game is over (or outside of games, like in the editor), all disks show their colors.
+fn update_fog_of_war(
+    time: Res<Time>,
+    fog_of_war: Res<FogOfWar>,
+    board: Res<Board>,
+    turn: Res<Turn>,
+    game_mode: Res<GameMode>,
+    state: Res<State<GameState>>,
+    main_menu_info: Res<MainMenuInfo>,
+    game_meta: Res<GameMeta>,
+    material_handles: Res<MaterialHandles>,
+    mut query: Query<(&Disk, &mut Handle<ColorMaterial>, Option<&mut FogReveal>), With<BoardDisk>>,
+) {
+    let viewer = match *game_mode {
+        GameMode::HotSeat => Some(*turn),
+        GameMode::VsAi { ai, .. } | GameMode::MirrorHuman { ai } => {
+            Some(ai.previous(board.players))
+        }
+        GameMode::AiVsAi { .. } => None,
+    };
+    // The win check already marked the game as over, the board isn't scanned again every frame
+    let game_over = match *state.current() {
+        GameState::Playing | GameState::Paused | GameState::Countdown => {
+            game_meta.finished.is_some()
+        }
+        GameState::Menu => !main_menu_info.allow_resume,
+        _ => true,
+    };
+
+    for (&disk, mut material, reveal) in &mut query {
+        let revealed = reveal.is_some_and(|mut reveal| !reveal.0.tick(time.delta()).finished());
+        let fogged = fog_of_war.0
+            && !game_over
+            && !revealed
+            && viewer.is_some_and(|viewer| disk.to_turn() != viewer);
+        let wanted = if fogged {
+            material_handles.fog_disk.clone()
+        } else {
+            material_handles.get_disk_material(disk)
+        };
+        if *material != wanted {
+            *material = wanted;
+        }
+    }
+}
+
+// Checking for placing a disk
+fn check_for_click(
+    commands: Commands,
+    windows: Res<Windows>,
+    buttons: Res<Input<MouseButton>>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut history: ResMut<MoveHistory>,
+    (mut skip_click, mut drop_release, require_release): (
+        ResMut<SkipClick>,
+        ResMut<DropRelease>,
+        Res<RequireRelease>,
+    ),
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    query: Query<&mut Style, With<Move>>,
+    asset_server: Res<AssetServer>,
+    (input_locked, column_buttons): (Res<InputLocked>, Query<&Interaction, With<ColumnButton>>),
+    (theme, swap_colors): (Res<Theme>, Res<SwapColors>),
+    (drop_input_region, view_rotation, layout): (
+        Res<DropInputRegion>,
+        Res<ViewRotation>,
+        Res<BoardLayout>,
+    ),
+    (history_mode, history_limit): (Res<HistoryDisplayMode>, Res<HistoryLimit>),
+    clock: Res<GameClock>,
+) {
+    // Only players place disks by clicking, not while the input is locked
+    // (so no click during the AI's turn ends up as a move for it)
+    if input_locked.0 {
+        return;
+    }
+
+    if buttons.just_pressed(MouseButton::Left) {
+        if skip_click.0 {
+            skip_click.0 = false;
+            return;
+        }
+        // Clicks on the column buttons are handled by them
+        if column_buttons
+            .iter()
+            .any(|interaction| *interaction != Interaction::None)
+        {
+            return;
+        }
+        if !drop_release.press(require_release.0) {
+            return;
+        }
+        if let Some(window) = windows.get_primary() {
+            let position = window.cursor_position();
+            if let Some(position) = position {
+                if let Some(col) = mouse_pos_to_col(
+                    position,
+                    &board,
+                    *drop_input_region,
+                    *view_rotation,
+                    *layout,
+                ) {
+                    // A floating first disk goes where it was clicked
+                    let chosen_row = mouse_pos_to_row(position, &board, *view_rotation, *layout);
+                    match board.try_drop(col) {
+                        Ok(_) => drop_disk(
+                            commands,
+                            &mesh_handles,
+                            &material_handles,
+                            &theme.colors().swapped(*swap_colors),
+                            &mut board,
+                            &mut turn,
+                            &mut history,
+                            *history_mode,
+                            *history_limit,
+                            *layout,
+                            query,
+                            asset_server,
+                            col,
+                            chosen_row,
+                            clock.0,
+                        ),
+                        // Show the column can't take any more disks
+                        Err(DropError::ColumnFull) => {
+                            flash_column(commands, &board, col, *layout)
+                        }
+                        // Clicks outside the board are ignored
+                        Err(DropError::OutOfRange) => {}
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Lets the mouse drop again once it was let go (see DropRelease)
+fn update_drop_release(buttons: Res<Input<MouseButton>>, mut drop_release: ResMut<DropRelease>) {
+    drop_release.update(buttons.pressed(MouseButton::Left));
+}
+
+// Drops a disk into the column of a clicked column button (full columns can't be clicked)
+fn check_for_column_button(
+    commands: Commands,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut history: ResMut<MoveHistory>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    query: Query<&mut Style, With<Move>>,
+    asset_server: Res<AssetServer>,
+    input_locked: Res<InputLocked>,
+    (theme, swap_colors): (Res<Theme>, Res<SwapColors>),
+    (history_mode, history_limit): (Res<HistoryDisplayMode>, Res<HistoryLimit>),
+    layout: Res<BoardLayout>,
+    clock: Res<GameClock>,
+    button_query: Query<(&Interaction, &ColumnButton), Changed<Interaction>>,
+    mut drop_release: ResMut<DropRelease>,
+    require_release: Res<RequireRelease>,
+) {
+    if input_locked.0 {
+        return;
+    }
+
+    let clicked = button_query
+        .iter()
+        .find(|(interaction, _)| **interaction == Interaction::Clicked);
+    if let Some((_, &ColumnButton { col })) = clicked {
+        if !drop_release.press(require_release.0) {
+            return;
+        }
+        if board.try_drop(col).is_ok() {
+            drop_disk(
+                commands,
+                &mesh_handles,
+                &material_handles,
+                &theme.colors().swapped(*swap_colors),
+                &mut board,
+                &mut turn,
+                &mut history,
+                *history_mode,
+                *history_limit,
+                *layout,
+                query,
+                asset_server,
+                col,
+                None,
+                clock.0,
+            );
+        }
+    }
+}
+
+// Drops into a random column for the player whose turn it is, when they can't decide
+// (does nothing if the board is full)
+fn check_for_random_move(
+    commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut history: ResMut<MoveHistory>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    query: Query<&mut Style, With<Move>>,
+    asset_server: Res<AssetServer>,
+    input_locked: Res<InputLocked>,
+    theme: Res<Theme>,
+    swap_colors: Res<SwapColors>,
+    history_mode: Res<HistoryDisplayMode>,
+    history_limit: Res<HistoryLimit>,
+    layout: Res<BoardLayout>,
+    clock: Res<GameClock>,
+) {
+    if !keyboard_input.just_pressed(RANDOM_MOVE_KEY) || input_locked.0 {
+        return;
+    }
+
+    let Some(col) = board.random_move(&mut rand::thread_rng()) else {
+        return;
+    };
+    if board.try_drop(col).is_ok() {
+        drop_disk(
+            commands,
+            &mesh_handles,
+            &material_handles,
+            &theme.colors().swapped(*swap_colors),
+            &mut board,
+            &mut turn,
+            &mut history,
+            *history_mode,
+            *history_limit,
+            *layout,
+            query,
+            asset_server,
+            col,
+            None,
+            clock.0,
+        );
+    }
+}
+
+// The column to drop into when it's the only one left, None if there is a choice
+// or the game is already over
+fn forced_move(board: &Board) -> Option<i32> {
+    match board.legal_moves().as_slice() {
+        &[col] if board.check_for_wins().is_none() => Some(col),
+        _ => None,
+    }
+}
+
+// Drops into the only column left for the player whose turn it is, if the setting is on
+// (not for the AI, which moves by itself anyway, and not once the game is over)
+fn check_for_forced_move(
+    commands: Commands,
+    auto_forced: Res<AutoForced>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut history: ResMut<MoveHistory>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    query: Query<&mut Style, With<Move>>,
+    asset_server: Res<AssetServer>,
+    input_locked: Res<InputLocked>,
+    theme: Res<Theme>,
+    swap_colors: Res<SwapColors>,
+    history_mode: Res<HistoryDisplayMode>,
+    history_limit: Res<HistoryLimit>,
+    layout: Res<BoardLayout>,
+    clock: Res<GameClock>,
+) {
+    if !auto_forced.0 || input_locked.0 {
+        return;
+    }
+
+    let Some(col) = forced_move(&board) else {
+        return;
+    };
+    if board.try_drop(col).is_ok() {
+        drop_disk(
+            commands,
+            &mesh_handles,
+            &material_handles,
+            &theme.colors().swapped(*swap_colors),
+            &mut board,
+            &mut turn,
+            &mut history,
+            *history_mode,
+            *history_limit,
+            *layout,
+            query,
+            asset_server,
+            col,
+            None,
+            clock.0,
+        );
+    }
+}
+
+// Where the button over the column goes in the window. With the view turned around,
+// the strip is under the board and the columns go from right to left.
+fn column_button_position(
+    dims: &Dimensions,
+    col: i32,
+    view_rotation: ViewRotation,
+    layout: BoardLayout,
+) -> UiRect {
+    // The UI is laid out from the top left of the window
+    let left = dims.window_origin().x + dims.col_width * col as f32;
+    let top = WINDOW_HEIGHT - dims.window_origin().y;
+    let (left, top) = match view_rotation {
+        ViewRotation::Upright => (left, top),
+        ViewRotation::HalfTurn => {
+            let center = ViewRotation::center(layout);
+            (
+                2.0 * center.x - left - dims.col_width,
+                2.0 * (WINDOW_HEIGHT - center.y) - top - dims.row_height,
+            )
+        }
+    };
+    UiRect {
+        left: Val::Px(left),
+        top: Val::Px(top),
+        ..default()
+    }
+}
+
+// Keeps the column buttons over the strip above the board, greyed out when they can't be used
+// (and hidden in the demo, which nobody plays), glowing over live threats if asked for
+fn update_column_buttons(
+    board: Res<Board>,
+    input_locked: Res<InputLocked>,
+    game_mode: Res<GameMode>,
+    turn: Res<Turn>,
+    threat_glow: Res<ThreatGlow>,
+    view_rotation: Res<ViewRotation>,
+    layout: Res<BoardLayout>,
+    mut query: Query<(
+        &ColumnButton,
+        &Interaction,
+        &mut BackgroundColor,
+        &mut Style,
+        &mut Visibility,
+    )>,
+    mut threats: Local<(Vec<i32>, Vec<i32>)>,
+) {
+    let dims = get_dimensions(&board, 0.0, *layout);
+    let demo = matches!(*game_mode, GameMode::AiVsAi { .. });
+
+    // The threats are only looked for again when the position (or what glows) changed
+    if board.is_changed() || turn.is_changed() || threat_glow.is_changed() {
+        threats.0 = if *threat_glow == ThreatGlow::Off {
+            Vec::new()
+        } else {
+            board.open_three_columns(turn.to_disk())
+        };
+        threats.1 = if *threat_glow == ThreatGlow::Both {
+            (0..board.players)
+                .map(Turn)
+                .filter(|&other| other != *turn)
+                .flat_map(|other| board.open_three_columns(other.to_disk()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+    }
+    let (own_threats, other_threats) = &*threats;
+    for (button, interaction, mut background_color, mut style, mut visibility) in &mut query {
+        let color = if input_locked.0 || board.column_full(button.col) {
+            COLUMN_BUTTON_DISABLED_COLOR
+        } else if *interaction != Interaction::None {
+            COLUMN_BUTTON_HOVER_COLOR
+        } else if own_threats.contains(&button.col) {
+            COLUMN_BUTTON_OWN_THREAT_COLOR
+        } else if other_threats.contains(&button.col) {
+            COLUMN_BUTTON_OTHER_THREAT_COLOR
+        } else {
+            COLUMN_BUTTON_COLOR
+        };
+        if background_color.0 != color {
+            background_color.0 = color;
+        }
+
+        // Only changed when needed, since changing the style lays out the UI again
+        let position = column_button_position(&dims, button.col, *view_rotation, *layout);
+        if style.position != position {
+            style.position = position;
+        }
+        if visibility.is_visible == demo {
+            visibility.is_visible = !demo;
+        }
+    }
+}
+
+// Shows or hides the analysis when the analysis key is pressed
+fn check_for_analysis_toggle(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut show_analysis: ResMut<ShowAnalysis>,
+) {
+    if keyboard_input.just_pressed(ANALYSIS_KEY) {
+        show_analysis.0 = !show_analysis.0;
+    }
+}
+
+// Suggests a move for the player whose turn it is when the hint key is pressed. When the position
+// is lost with perfect play (and the board is small enough to know), the loss warning says so
+// instead, if it is turned on. Puzzles are solved without hints.
+fn check_for_hint(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    board: Res<Board>,
+    turn: Res<Turn>,
+    input_locked: Res<InputLocked>,
+    puzzle: Option<Res<ActivePuzzle>>,
+    loss_warning: Res<LossWarning>,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+) {
+    if !keyboard_input.just_pressed(HINT_KEY)
+        || input_locked.0
+        || puzzle.is_some()
+        || board.check_for_wins().is_some()
+    {
+        return;
+    }
+
+    if let Some(text) = hint_text(&board, turn.to_disk(), *loss_warning) {
+        spawn_notice(&mut commands, &asset_server, theme.colors(), text);
+    }
+}
+
+// The hint for the player of the disk, None if there is no move left to suggest
+fn hint_text(board: &Board, disk: Disk, loss_warning: LossWarning) -> Option<String> {
+    if loss_warning.0 && matches!(solve(board, disk), GameValue::Loss(_)) {
+        return Some("You're in a losing position, with perfect play".to_string());
+    }
+    let col = best_move(board, disk, HINT_DEPTH)?;
+    Some(format!("Hint: try column {}", column_label(col)))
+}
+
+// What the threat counter of a column shows: the threats dropping a disk there creates for the
+// player to move and allows for the next one, counted before and after the drop. Empty for a
+// full column, and a move that wins right away has no threats worth counting.
+fn threat_counter_text(board: &Board, turn: Turn, col: i32) -> String {
+    let disk = turn.to_disk();
+    let mut next = turn;
+    next.next(board.players);
+    let opponent = next.to_disk();
+
+    let mut after = board.clone();
+    let Some(row) = after.drop_disk(col, disk) else {
+        return String::new();
+    };
+    if after.wins_through(row, col) {
+        return "Win".to_string();
+    }
+
+    let created = after
+        .immediate_threats(disk)
+        .saturating_sub(board.immediate_threats(disk));
+    let allowed = after
+        .immediate_threats(opponent)
+        .saturating_sub(board.immediate_threats(opponent));
+    format!("+{}/-{}", created, allowed)
+}
+
+// Updates the threat counters while the analysis is shown. Counting takes a board for every
+// possible reply, so it's only done when the position changes (or new counters were spawned).
+fn update_threat_counters(
+    board: Res<Board>,
+    turn: Res<Turn>,
+    show_analysis: Res<ShowAnalysis>,
+    new_counters: Query<(), Added<ThreatCounter>>,
+    mut query: Query<(&ThreatCounter, &mut Text, &mut Visibility)>,
+) {
+    if !(board.is_changed()
+        || turn.is_changed()
+        || show_analysis.is_changed()
+        || !new_counters.is_empty())
+    {
+        return;
+    }
+
+    for (counter, mut text, mut visibility) in &mut query {
+        visibility.is_visible = show_analysis.0;
+        if show_analysis.0 {
+            text.sections[0].value = threat_counter_text(&board, *turn, counter.col);
+        }
+    }
+}
+
+// Shows a flash over a full column
+fn flash_column(mut commands: Commands, board: &Board, col: i32, layout: BoardLayout) {
+    let dims = get_dimensions(board, 0.0, layout);
+    commands.spawn((
+        SpriteBundle {
+            transform: Transform {
+                translation: Vec3::new(
+                    dims.col_x(col),
+                    dims.origin.y - dims.row_height - dims.height / 2.0,
+                    0.3,
+                ),
+                scale: Vec3::new(dims.col_width, dims.height, 0.0),
+                ..default()
+            },
+            sprite: Sprite {
+                color: FULL_COLUMN_FLASH_COLOR,
+                ..default()
+            },
+            ..default()
+        },
+        ColumnFlash(Timer::from_seconds(FULL_COLUMN_FLASH_TIME, TimerMode::Once)),
+        InGame,
+    ));
+}
+
+// Tints the strip above the board in the color of the player to move
+fn update_turn_strip(
+    turn: Res<Turn>,
+    theme: Res<Theme>,
+    swap_colors: Res<SwapColors>,
+    mut query: Query<(&mut Sprite, ChangeTrackers<TurnStrip>), With<TurnStrip>>,
+) {
+    for (mut sprite, tracker) in &mut query {
+        if turn.is_changed() || theme.is_changed() || swap_colors.is_changed() || tracker.is_added()
+        {
+            let colors = theme.colors().swapped(*swap_colors);
+            sprite.color = *turn.to_color(&colors).set_a(TURN_STRIP_ALPHA);
+        }
+    }
+}
+
+// Fades out the full column flashes, removing them when they are gone
+fn update_column_flash(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Sprite, &mut ColumnFlash)>,
+) {
+    for (entity, mut sprite, mut flash) in &mut query {
+        if flash.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            sprite
+                .color
+                .set_a(FULL_COLUMN_FLASH_COLOR.a() * flash.0.percent_left());
+        }
+    }
+}
+
+// Applies the offered appearance of a loaded save, or keeps the current one, when a key is pressed
+fn check_for_appearance_prompt(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    offered_appearance: Option<Res<OfferedAppearance>>,
+    mut theme: ResMut<Theme>,
+    prompt_query: Query<Entity, With<AppearancePrompt>>,
+) {
+    let Some(offered_appearance) = offered_appearance else {
+        return;
+    };
+
+    if keyboard_input.just_pressed(APPLY_APPEARANCE_KEY) {
+        *theme = offered_appearance.0.theme;
+    } else if !keyboard_input.just_pressed(KEEP_APPEARANCE_KEY) {
+        return;
+    }
+
+    commands.remove_resource::<OfferedAppearance>();
+    for entity in &prompt_query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Shows a notice over the game for a few seconds, under the prompt for a save's appearance
+fn spawn_notice(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    colors: &ThemeColors,
+    text: String,
+) {
+    spawn_timed_notice(commands, asset_server, colors, text, NOTICE_TIME);
+}
+
+// Shows a short confirmation that something worked in the same place, gone again quickly
+fn spawn_toast(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    colors: &ThemeColors,
+    text: String,
+) {
+    spawn_timed_notice(commands, asset_server, colors, text, TOAST_TIME);
+}
+
+fn spawn_timed_notice(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    colors: &ThemeColors,
+    text: String,
+    seconds: f32,
+) {
+    commands.spawn((
+        TextBundle::from_section(
+            text,
+            TextStyle {
+                font: asset_server.load(FONT_PATH),
+                font_size: 25.0 * colors.text_scale,
+                color: colors.text,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                top: Val::Px(40.0),
+                ..default()
+            },
+            ..default()
+        }),
+        GameNotice(Timer::from_seconds(seconds, TimerMode::Once)),
+        InGame,
+    ));
+}
+
+// Writes the move numbers on the disks when turned on in the settings, again whenever the board
+// changes (so disks that were taken back or moved by a flip don't keep theirs)
+fn update_move_numbers(
+    mut commands: Commands,
+    board: Res<Board>,
+    history: Res<MoveHistory>,
+    turn: Res<Turn>,
+    show_move_numbers: Res<ShowMoveNumbers>,
+    game_state: Res<State<GameState>>,
+    theme: Res<Theme>,
+    layout: Res<BoardLayout>,
+    asset_server: Res<AssetServer>,
+    query: Query<Entity, With<MoveNumber>>,
+) {
+    let changed = board.is_changed()
+        || history.is_changed()
+        || show_move_numbers.is_changed()
+        || game_state.is_changed()
+        || theme.is_changed()
+        || layout.is_changed();
+    if !changed {
+        return;
+    }
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    // The review shows other positions than the one on the board
+    if !show_move_numbers.0 || *game_state.current() == GameState::Review {
+        return;
+    }
+
+    let dims = get_dimensions(&board, DISK_PADDING, *layout);
+    for (disk, number) in history.numbered_moves(&board, *turn) {
+        let mut transform = get_disk_transform(&dims, disk.row, disk.col);
+        transform.translation.z = 0.3;
+        transform.scale = Vec3::ONE;
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    number.to_string(),
+                    TextStyle {
+                        font: asset_server.load(FONT_PATH),
+                        font_size: dims.scale * MOVE_NUMBER_SIZE,
+                        color: theme.colors().text,
+                    },
+                )
+                .with_alignment(TextAlignment::CENTER),
+                transform,
+                ..default()
+            },
+            MoveNumber,
+            InGame,
+        ));
+    }
+}
+
+// Removes notices once they have been shown for long enough
+fn update_game_notice(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut GameNotice)>,
+) {
+    for (entity, mut notice) in &mut query {
+        if notice.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+// Takes back the last move when the undo key is pressed, as often as the undo limit allows.
+// Only in hot seat games, where the players can agree on it.
+fn check_for_undo(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut history: ResMut<MoveHistory>,
+    game_mode: Res<GameMode>,
+    input_locked: Res<InputLocked>,
+    puzzle: Option<Res<ActivePuzzle>>,
+    undo_limit: Res<UndoLimit>,
+    mut undos: ResMut<UndosTaken>,
+    disk_query: Query<Entity, With<BoardDisk>>,
+    mut move_query: Query<(Entity, &mut Style), With<Move>>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    asset_server: Res<AssetServer>,
+    (theme, layout): (Res<Theme>, Res<BoardLayout>),
+) {
+    if !keyboard_input.just_pressed(UNDO_KEY) || *game_mode != GameMode::HotSeat || input_locked.0 {
+        return;
+    }
+    // The moves of a puzzle's position aren't the player's to take back
+    if puzzle.is_some_and(|puzzle| history.moves_made() <= puzzle.start) {
+        return;
+    }
+
+    // Check the move can be taken back before counting it
+    let mut undone_board = board.clone();
+    let mut undone_turn = *turn;
+    let mut undone_history = history.clone();
+    if !undone_history.undo(&mut undone_board, &mut undone_turn) {
+        return;
+    }
+    if !undos.take(*undo_limit) {
+        spawn_notice(
+            &mut commands,
+            &asset_server,
+            theme.colors(),
+            "No undos left".to_string(),
+        );
+        return;
+    }
+    *board = undone_board;
+    *turn = undone_turn;
+    *history = undone_history;
+    announce(&mut commands, Announcement::Turn(*turn));
+
+    // The disk is gone, so draw them all again
+    for entity in &disk_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    draw_disks(&mut commands, &mesh_handles, &material_handles, &board, *layout);
+    match history.last_move(&board) {
+        Some(last_move) => commands.insert_resource(last_move),
+        None => commands.remove_resource::<LastMove>(),
+    }
+
+    // The newest history node is the one on the left, the others shift back into place
+    for (entity, mut style) in &mut move_query {
+        if style.position.left == Val::Percent(0.0) {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            style.position.left = style.position.left.try_sub(Val::Percent(10.0)).unwrap();
+        }
+    }
+}
+
+// Starts the game over when the restart key is pressed, the same way it was started.
+// A game in progress asks for the key to be pressed again first.
+fn check_for_restart(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    mut confirmation: ResMut<RestartConfirmation>,
+    board: Res<Board>,
+    history: Res<MoveHistory>,
+    game_mode: Res<GameMode>,
+    puzzle: Option<Res<ActivePuzzle>>,
+    game_match: Option<Res<Match>>,
+    mut game_change: EventWriter<GameChange>,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    prompt_query: Query<Entity, With<RestartPrompt>>,
+) {
+    // The first press is forgotten once the time to confirm it is over
+    if let Some(timer) = &mut confirmation.0 {
+        if timer.tick(time.delta()).finished() {
+            confirmation.0 = None;
+        }
+    }
+    if confirmation.0.is_none() {
+        for entity in &prompt_query {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    // The demo handles all keys itself
+    if !keyboard_input.just_pressed(RESTART_KEY) || matches!(*game_mode, GameMode::AiVsAi { .. }) {
+        return;
+    }
+
+    if !confirmation.press(&board, &history) {
+        commands.spawn((
+            TextBundle::from_section(
+                format!("Press {:?} again to restart", RESTART_KEY),
+                TextStyle {
+                    font: asset_server.load(FONT_PATH),
+                    font_size: 25.0 * theme.colors().text_scale,
+                    color: theme.colors().text,
+                },
+            )
+            .with_style(Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(70.0),
+                    ..default()
+                },
+                ..default()
+            }),
+            RestartPrompt,
+            InGame,
+        ));
+        return;
+    }
+
+    let restart = if puzzle.is_some() {
+        GameChange::Puzzle
+    } else if let GameMode::VsAi { depth, .. } = *game_mode {
+        GameChange::VsAi {
+            rows: board.rows,
+            cols: board.cols,
+            depth,
+        }
+    } else if let GameMode::MirrorHuman { .. } = *game_mode {
+        GameChange::MirrorHuman {
+            rows: board.rows,
+            cols: board.cols,
+        }
+    } else {
+        // A match starts over from the first game, with new rocks if there were any
+        GameChange::New {
+            rows: board.rows,
+            cols: board.cols,
+            players: board.players,
+            rocks: (!board.rocks.is_empty()).then(rand::random),
+            drop_pattern: board.drop_pattern,
+            win_condition: board.win_condition,
+            win_directions: board.win_directions,
+            win_lengths: board.win_lengths.clone(),
+            best_of: game_match.map(|game_match| game_match.games()),
+        }
+    };
+    game_change.send(restart);
+}
+
+// Starts the editor on an empty board the size of the last game's, replacing that game
+fn start_editor(
+    mut commands: Commands,
+    query: Query<Entity, With<InGame>>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut history: ResMut<MoveHistory>,
+    mut game_mode: ResMut<GameMode>,
+    mut main_menu_info: ResMut<MainMenuInfo>,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    layout: Res<BoardLayout>,
+) {
+    let (rows, cols) = (board.rows, board.cols);
+    cleanup(&mut commands, query);
+    commands.remove_resource::<ActivePuzzle>();
+    commands.remove_resource::<Match>();
+    *game_mode = GameMode::HotSeat;
+    main_menu_info.allow_resume = false;
+    new_game(
+        &mut commands,
+        &mesh_handles,
+        &material_handles,
+        theme.colors(),
+        &mut board,
+        &mut turn,
+        &mut history,
+        rows,
+        cols,
+        2,
+        &asset_server,
+        *layout,
+    );
+
+    let colors = theme.colors();
+    let font = asset_server.load(FONT_PATH);
+    commands.spawn((
+        TextBundle::from_section(
+            format!(
+                "Click a cell to change its disk, {:?} to go back",
+                KeyCode::Escape
+            ),
+            TextStyle {
+                font: font.clone(),
+                font_size: 25.0 * colors.text_scale,
+                color: colors.text,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                top: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        EditorUi,
+        InGame,
+    ));
+
+    // The buttons go where the history is shown in a game
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(
+                        Val::Percent(100.0),
+                        Val::Percent((1.0 - BOARD_SCALE.y) * 100.0),
+                    ),
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Percent(0.0),
+                        bottom: Val::Percent(0.0),
+                        ..default()
+                    },
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            EditorUi,
+            InGame,
+        ))
+        .with_children(|parent| {
+            for button in [EditorButton::Turn, EditorButton::Play, EditorButton::Save] {
+                parent
+                    .spawn((
+                        ButtonBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(200.0), Val::Px(50.0)),
+                                margin: UiRect::all(Val::Px(5.0)),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                ..default()
+                            },
+                            background_color: colors.button.into(),
+                            ..default()
+                        },
+                        button,
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextBundle::from_section(
+                                button.label(*turn),
+                                TextStyle {
+                                    font: font.clone(),
+                                    font_size: 30.0 * colors.text_scale,
+                                    color: colors.text,
+                                },
+                            ),
+                            button,
+                        ));
+                    });
+            }
+        });
+}
+
+// Changes the disk in the clicked cell to the next player's (or back to none after the last one),
+// without the disk falling. Goes back to the menu on escape.
+fn edit_board(
+    mut commands: Commands,
+    windows: Res<Windows>,
+    buttons: Res<Input<MouseButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut game_state: ResMut<State<GameState>>,
+    mut board: ResMut<Board>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    layout: Res<BoardLayout>,
+    disk_query: Query<Entity, With<BoardDisk>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        game_state.set(GameState::Menu).unwrap();
+        return;
+    }
+    if !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    // The menu always leaves the camera upright
+    let Some((row, col)) = windows
+        .get_primary()
+        .and_then(Window::cursor_position)
+        .and_then(|mouse_pos| {
+            mouse_pos_to_cell(mouse_pos, &board, ViewRotation::Upright, *layout)
+        })
+    else {
+        return;
+    };
+    if board.is_rock(row, col) {
+        return;
+    }
+
+    let players = board.players;
+    let cell = &mut board.disks[col as usize][row as usize];
+    *cell = match *cell {
+        None => Some(Disk(0)),
+        Some(Disk(player)) if player + 1 < players => Some(Disk(player + 1)),
+        Some(_) => None,
+    };
+
+    for entity in &disk_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    draw_disks(&mut commands, &mesh_handles, &material_handles, &board, *layout);
+}
+
+// Why a game can't be played from the edited position, None if it could come from a real game
+fn editor_problem(data: &GameData) -> Option<&'static str> {
+    if !data.board.is_settled() {
+        Some("Some disks are hanging in the air")
+    } else if data.winner().is_some() {
+        Some("Someone has already won")
+    } else if data.board.legal_moves().is_empty() {
+        Some("The board is full")
+    } else if !data.validate_turn_parity() {
+        Some("The number of disks doesn't fit the player to move")
+    } else {
+        None
+    }
+}
+
+// Switches the player to move, starts the game from the edited position (if it could come from
+// a real game, otherwise a notice says why not) or saves it as a scenario
+fn check_for_editor_buttons(
+    mut commands: Commands,
+    button_query: Query<(&Interaction, &EditorButton), Changed<Interaction>>,
+    mut text_query: Query<(&mut Text, &EditorButton), Without<Interaction>>,
+    mut turn: ResMut<Turn>,
+    board: Res<Board>,
+    history: Res<MoveHistory>,
+    theme: Res<Theme>,
+    asset_server: Res<AssetServer>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    for (interaction, &button) in &button_query {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+
+        let data = GameData::new(
+            &board,
+            *turn,
+            &history,
+            GameClock::default(),
+            *theme,
+            &GameMeta::new(),
+        );
+        match button {
+            EditorButton::Turn => turn.next(board.players),
+            EditorButton::Play => match editor_problem(&data) {
+                Some(problem) => spawn_notice(
+                    &mut commands,
+                    &asset_server,
+                    theme.colors(),
+                    problem.to_string(),
+                ),
+                None => game_state.set(GameState::Playing).unwrap(),
+            },
+            EditorButton::Save => {
+                if write_save(SCENARIO_FILE, &data) {
+                    spawn_toast(
+                        &mut commands,
+                        &asset_server,
+                        theme.colors(),
+                        format!("Saved the position to {}", SCENARIO_FILE),
+                    );
+                }
+            }
+        }
+    }
+
+    if turn.is_changed() {
+        for (mut text, &button) in &mut text_query {
+            text.sections[0].value = button.label(*turn);
+        }
+    }
+}
+
+// Removes the editor's text and buttons, the edited board stays
+fn end_editor(mut commands: Commands, query: Query<Entity, With<EditorUi>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Starts reviewing the finished game from its final position
+fn start_review(
+    mut commands: Commands,
+    board: Res<Board>,
+    history: Res<MoveHistory>,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+) {
+    // After a flip the moves no longer replay on an empty board,
+    // so the review jumps from the last position that did to the final one
+    let mut boards = history.replay(&board);
+    if boards.len() == history.moves.len() + 1 {
+        boards.pop();
+    }
+    boards.push(board.clone());
+    let ply = boards.len() - 1;
+
+    commands.spawn((
+        TextBundle::from_section(
+            review_text(ply, boards.len()),
+            TextStyle {
+                font: asset_server.load(FONT_PATH),
+                font_size: 25.0 * theme.colors().text_scale,
+                color: theme.colors().text,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.0),
+                top: Val::Px(10.0),
+                ..default()
+            },
+            ..default()
+        }),
+        ReviewText,
+        InGame,
+    ));
+    commands.insert_resource(Review { boards, ply });
+}
+
+// Which position of the review is shown, and the keys to move through them
+fn review_text(ply: usize, positions: usize) -> String {
+    format!(
+        "Move {} of {}  ({:?}/{:?} to step, {:?}/{:?} to jump, {:?} to go back)",
+        ply,
+        positions - 1,
+        REVIEW_BACK_KEY,
+        REVIEW_FORWARD_KEY,
+        REVIEW_FIRST_KEY,
+        REVIEW_LAST_KEY,
+        KeyCode::Escape,
+    )
+}
+
+// Steps through the positions of the reviewed game with the arrow keys, home and end
+fn step_review(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut game_state: ResMut<State<GameState>>,
+    review: Option<ResMut<Review>>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    layout: Res<BoardLayout>,
+    last_move: Option<Res<LastMove>>,
+    disk_query: Query<Entity, With<BoardDisk>>,
+    mut text_query: Query<&mut Text, With<ReviewText>>,
+    mut winner_line_query: Query<&mut Visibility, (With<WinnerLine>, Without<LastMoveMarker>)>,
+    mut marker_query: Query<&mut Visibility, With<LastMoveMarker>>,
+) {
+    let Some(mut review) = review else {
+        return;
+    };
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        game_state.set(GameState::Menu).unwrap();
+        return;
+    }
+
+    let last = review.boards.len() - 1;
+    let ply = if keyboard_input.just_pressed(REVIEW_BACK_KEY) {
+        review.ply.saturating_sub(1)
+    } else if keyboard_input.just_pressed(REVIEW_FORWARD_KEY) {
+        (review.ply + 1).min(last)
+    } else if keyboard_input.just_pressed(REVIEW_FIRST_KEY) {
+        0
+    } else if keyboard_input.just_pressed(REVIEW_LAST_KEY) {
+        last
+    } else {
+        return;
+    };
+    if ply == review.ply {
+        return;
+    }
+    review.ply = ply;
+
+    for entity in &disk_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    draw_disks(
+        &mut commands,
+        &mesh_handles,
+        &material_handles,
+        &review.boards[ply],
+        *layout,
+    );
+    for mut text in &mut text_query {
+        text.sections[0].value = review_text(ply, review.boards.len());
+    }
+
+    // The winning line and the last move only belong to the final position
+    for mut visibility in &mut winner_line_query {
+        visibility.is_visible = ply == last;
+    }
+    for mut visibility in &mut marker_query {
+        visibility.is_visible = ply == last && last_move.is_some();
+    }
+}
+
+// Leaves the final position on the board again, for the menu to show behind it
+fn end_review(
+    mut commands: Commands,
+    review: Option<Res<Review>>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    layout: Res<BoardLayout>,
+    last_move: Option<Res<LastMove>>,
+    disk_query: Query<Entity, With<BoardDisk>>,
+    text_query: Query<Entity, With<ReviewText>>,
+    mut winner_line_query: Query<&mut Visibility, (With<WinnerLine>, Without<LastMoveMarker>)>,
+    mut marker_query: Query<&mut Visibility, With<LastMoveMarker>>,
+) {
+    let Some(review) = review else {
+        return;
+    };
+    if review.ply + 1 < review.boards.len() {
+        for entity in &disk_query {
+            commands.entity(entity).despawn_recursive();
+        }
+        if let Some(board) = review.boards.last() {
+            draw_disks(&mut commands, &mesh_handles, &material_handles, board, *layout);
+        }
+    }
+    for entity in &text_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    for mut visibility in &mut winner_line_query {
+        visibility.is_visible = true;
+    }
+    for mut visibility in &mut marker_query {
+        visibility.is_visible = last_move.is_some();
+    }
+    commands.remove_resource::<Review>();
+}
+
+// Prints the board as text when the print key is pressed, to paste it into a bug report.
+// Small boards are labelled with how the game ends with perfect play.
+fn print_board(keyboard_input: Res<Input<KeyCode>>, board: Res<Board>, turn: Res<Turn>) {
+    if keyboard_input.just_pressed(PRINT_BOARD_KEY) {
+        println!("{}", board.to_ascii());
+        let value = solve(&board, turn.to_disk());
+        if value != GameValue::Unknown {
+            println!("{} to move {}", *turn, value);
+        }
+    }
+}
+
+// Writes a picture of the board to a file when the export key is pressed, for sharing a position
+fn export_svg(
+    keyboard_input: Res<Input<KeyCode>>,
+    board: Res<Board>,
+    theme: Res<Theme>,
+    swap_colors: Res<SwapColors>,
+) {
+    if keyboard_input.just_pressed(EXPORT_SVG_KEY) {
+        let colors = theme.colors().swapped(*swap_colors);
+        match std::fs::write(SVG_FILE, board.to_svg(&colors)) {
+            Ok(()) => info!("Saved a picture of the board to {}", SVG_FILE),
+            Err(err) => warn!("Failed to write {}: {}", SVG_FILE, err),
+        }
+    }
+}
+
+// Turns the view of the board around when the view key is pressed
+fn check_for_view_rotation(
+    keyboard_input: Res<Input<KeyCode>>,
+    game_mode: Res<GameMode>,
+    mut view_rotation: ResMut<ViewRotation>,
+    layout: Res<BoardLayout>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+) {
+    // The demo handles all keys itself
+    if let GameMode::AiVsAi { .. } = *game_mode {
+        return;
+    }
+
+    if keyboard_input.just_pressed(VIEW_ROTATION_KEY) {
+        *view_rotation = view_rotation.toggled();
+        for mut camera in &mut camera_query {
+            view_rotation.apply_to_camera(&mut camera, *layout);
+        }
+    }
+}
+
+// Flips the board when a player presses the flip key, once per game for each player.
+// Flipping takes the player's turn.
+fn check_for_gravity_flip(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut flips: ResMut<GravityFlips>,
+    input_locked: Res<InputLocked>,
+    disk_query: Query<(Entity, &Transform), (With<BoardDisk>, With<Disk>)>,
+    layout: Res<BoardLayout>,
+) {
+    if !keyboard_input.just_pressed(GRAVITY_FLIP_KEY) || input_locked.0 {
+        return;
+    }
+
+    let used = &mut flips.0[turn.0 as usize];
+    if *used {
+        return;
+    }
+    *used = true;
+
+    let moves = board.gravity_flip_rows();
+    board.apply_gravity_flip();
+
+    // The disks fall to their new rows like dropped ones do (see update_falling_disks)
+    let dims = get_dimensions(&board, DISK_PADDING, *layout);
+    for (entity, transform) in &disk_query {
+        let (row, col) = dims.cell_at(transform.translation.truncate());
+        let to = moves
+            .iter()
+            .find(|&&(move_col, from, _)| move_col == col && from == row)
+            .map(|&(_, _, to)| to);
+        let Some(to) = to.filter(|&to| to != row) else {
+            continue;
+        };
+        let target = Vec3::new(
+            transform.translation.x,
+            get_disk_transform(&dims, to, col).translation.y,
+            transform.translation.z,
+        );
+        commands.entity(entity).insert(FallingDisk { target, speed: 0.0 });
+    }
+    commands.remove_resource::<LastMove>();
+
+    turn.next(board.players);
+}
+
+// Grows the board by a row and a column when the grow key is pressed (or the pause panel asks to),
+// then lays out the whole game again for the new size
+fn check_for_grow_board(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut game_change_events: EventReader<GameChange>,
+    input_locked: Res<InputLocked>,
+    puzzle: Option<Res<ActivePuzzle>>,
+    query: Query<Entity, With<InGame>>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut move_history: ResMut<MoveHistory>,
+    asset_server: Res<AssetServer>,
+    (theme, swap_colors, history_mode, clock, game_meta): (
+        Res<Theme>,
+        Res<SwapColors>,
+        Res<HistoryDisplayMode>,
+        Res<GameClock>,
+        Res<GameMeta>,
+    ),
+    (flips, undos_taken, layout): (Res<GravityFlips>, Res<UndosTaken>, Res<BoardLayout>),
+) {
+    let asked = game_change_events
+        .iter()
+        .any(|game_change| matches!(game_change, GameChange::GrowBoard));
+    if !(asked || keyboard_input.just_pressed(GROW_BOARD_KEY)) || input_locked.0 {
+        return;
+    }
+
+    // A puzzle's solution only works on the board it was made for
+    if puzzle.is_some() {
+        spawn_notice(
+            &mut commands,
+            &asset_server,
+            theme.colors(),
+            "Puzzles can't grow".to_string(),
+        );
+        return;
+    }
+    if board.rows >= MAX_GROWN_ROWS {
+        spawn_notice(
+            &mut commands,
+            &asset_server,
+            theme.colors(),
+            "The board can't grow any bigger".to_string(),
+        );
+        return;
+    }
+
+    let mut grown = board.clone();
+    if let Err(err) = grown.grow(board.rows + 1, board.cols + 1) {
+        warn!("{}", err);
+        return;
+    }
+
+    // Everything is placed for the size of the board, so the game is set up again like a loaded
+    // save, keeping what new_game would reset
+    let data = GameData::new(&grown, *turn, &move_history, *clock, *theme, &game_meta);
+    let (flips, undos_taken) = (flips.0, undos_taken.0);
+    cleanup(&mut commands, query);
+    load_game(
+        &mut commands,
+        &mesh_handles,
+        &material_handles,
+        &theme.colors().swapped(*swap_colors),
+        &mut board,
+        &mut turn,
+        &mut move_history,
+        *history_mode,
+        &asset_server,
+        *layout,
+        data,
+    );
+    commands.insert_resource(GravityFlips(flips));
+    commands.insert_resource(UndosTaken(undos_taken));
+}
+
+// Lays out the game again when the board layout setting changes, the same way as a grown board.
+// The camera turns around the new center of the board too.
+fn update_board_layout(
+    mut commands: Commands,
+    layout: Res<BoardLayout>,
+    query: Query<Entity, With<InGame>>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut move_history: ResMut<MoveHistory>,
+    asset_server: Res<AssetServer>,
+    (theme, swap_colors, history_mode, clock, game_meta): (
+        Res<Theme>,
+        Res<SwapColors>,
+        Res<HistoryDisplayMode>,
+        Res<GameClock>,
+        Res<GameMeta>,
+    ),
+    (flips, undos_taken): (Res<GravityFlips>, Res<UndosTaken>),
+) {
+    // Nothing is laid out before the first game
+    if !layout.is_changed() || query.is_empty() {
+        return;
+    }
+
+    let data = GameData::new(&board, *turn, &move_history, *clock, *theme, &game_meta);
+    let (flips, undos_taken) = (flips.0, undos_taken.0);
+    cleanup(&mut commands, query);
+    load_game(
+        &mut commands,
+        &mesh_handles,
+        &material_handles,
+        &theme.colors().swapped(*swap_colors),
+        &mut board,
+        &mut turn,
+        &mut move_history,
+        *history_mode,
+        &asset_server,
+        *layout,
+        data,
+    );
+    commands.insert_resource(GravityFlips(flips));
+    commands.insert_resource(UndosTaken(undos_taken));
+}
+
+// Used to display the ghost disks in the correct place
+fn check_for_mouse_movement(
+    windows: Res<Windows>,
+    board: Res<Board>,
+    turn: Res<Turn>,
+    show_ghost: Res<ShowGhost>,
+    show_trajectory: Res<ShowTrajectory>,
+    input_locked: Res<InputLocked>,
+    drop_input_region: Res<DropInputRegion>,
+    view_rotation: Res<ViewRotation>,
+    layout: Res<BoardLayout>,
+    mut ghost_disk_query: Query<(
+        &mut Transform,
+        &mut Visibility,
+        &GhostDisk,
+        Option<&TrajectoryPreview>,
+    )>,
+    mut commands: Commands,
+    mut ghost_trail: ResMut<GhostTrail>,
+    mesh_handles: Res<MeshHandles>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    (theme, swap_colors, ghost_alpha): (Res<Theme>, Res<SwapColors>, Res<GhostAlpha>),
+    reduced_motion: Res<ReducedMotion>,
+) {
+    // Ghost disks are turned off in the settings (or the input is locked), keep them all hidden
+    if !show_ghost.0 || input_locked.0 {
+        for (_, mut ghost_disk_visibility, _, _) in &mut ghost_disk_query {
+            ghost_disk_visibility.is_visible = false;
+        }
+        return;
+    }
+
+    if let Some(mouse_pos) = windows.get_primary().unwrap().cursor_position() {
+        for (mut ghost_disk_transform, mut ghost_disk_visibility, &ghost_disk_type, preview) in
+            &mut ghost_disk_query
+        {
+            if ghost_disk_type == turn.to_ghost_disk() {
+                if let Some(col) = mouse_pos_to_col(
+                    mouse_pos,
+                    &board,
+                    *drop_input_region,
+                    *view_rotation,
+                    *layout,
+                ) {
+                    let dims = get_dimensions(&board, 0.0, *layout);
+                    let disk_dims = get_dimensions(&board, DISK_PADDING, *layout);
+
+                    let above = Vec3::new(
+                        dims.col_x(col),
+                        dims.origin.y - dims.row_height / 2.0,
+                        0.0,
+                    );
+                    // Where the disk lands, None if the column is full
+                    // (a floating first disk stays at the row under the mouse)
+                    let chosen_row = mouse_pos_to_row(mouse_pos, &board, *view_rotation, *layout);
+                    let landing_row = board.try_drop_at(col, chosen_row).ok();
+                    let landing = landing_row.map(|row| {
+                        let mut landing = get_disk_transform(&disk_dims, row, col).translation;
+                        landing.z = 0.2;
+                        landing
+                    });
+                    let floats = landing_row != board.try_drop(col).ok();
+                    let falls_down = board.drop_pattern.falls_down(col) && !floats;
+                    let shown_at = ghost_disk_visibility
+                        .is_visible
+                        .then_some(ghost_disk_transform.translation);
+
+                    match (preview, landing) {
+                        // Set correct ghost disk to visible and the right loaction.
+                        // Disks rising from the bottom (or floating) don't pass the strip above
+                        // the board, so their ghost is shown where they will land instead.
+                        (None, _) if falls_down => {
+                            ghost_disk_visibility.is_visible = true;
+                            ghost_disk_transform.translation = above;
+                        }
+                        (None, Some(landing)) => {
+                            ghost_disk_visibility.is_visible = true;
+                            ghost_disk_transform.translation = landing;
+                        }
+                        // The path down to where the disk lands (not needed for rising disks,
+                        // and hidden for full columns, which flash when clicked instead)
+                        (Some(&preview), Some(landing)) if show_trajectory.0 && falls_down => {
+                            ghost_disk_visibility.is_visible = true;
+                            *ghost_disk_transform = match preview {
+                                TrajectoryPreview::Trail => Transform {
+                                    translation: ((above + landing) / 2.0).truncate().extend(0.15),
+                                    scale: Vec3::new(
+                                        disk_dims.scale * TRAJECTORY_TRAIL_WIDTH,
+                                        above.y - landing.y,
+                                        0.0,
+                                    ),
+                                    ..default()
+                                },
+                                TrajectoryPreview::Landing => Transform {
+                                    translation: landing,
+                                    scale: Vec3::new(disk_dims.scale, disk_dims.scale, 0.0),
+                                    ..default()
+                                },
+                            };
+                        }
+                        _ => ghost_disk_visibility.is_visible = false,
+                    }
+
+                    // The ghost disk leaves a trail behind when it moves on (not its preview)
+                    if let Some(shown_at) = shown_at {
+                        if preview.is_none()
+                            && !reduced_motion.0
+                            && ghost_disk_visibility.is_visible
+                            && ghost_disk_transform.translation != shown_at
+                        {
+                            leave_ghost_trail(
+                                &mut commands,
+                                &mut ghost_trail,
+                                &mesh_handles,
+                                &mut materials,
+                                Transform {
+                                    translation: shown_at.truncate().extend(0.05),
+                                    ..*ghost_disk_transform
+                                },
+                                ghost_color(
+                                    turn.to_color(&theme.colors().swapped(*swap_colors)),
+                                    *ghost_alpha,
+                                ),
+                            );
+                        }
+                    }
+                    continue;
+                }
+            }
+
+            // Set all other ghost disks to invisible
+            ghost_disk_visibility.is_visible = false;
+        }
+    }
+}
+
+// Moves the camera off its place by a random offset while it shakes, shrinking as the shake runs out.
+// Runs after all the game systems, so where the camera really is can be kept as the base.
+fn shake_camera(
+    time: Res<Time>,
+    animation_speed: Res<AnimationSpeed>,
+    mut camera_shake: ResMut<CameraShake>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+) {
+    if camera_shake.t <= 0.0 {
+        return;
+    }
+
+    let strength = camera_shake.strength * camera_shake.t / CAMERA_SHAKE_TIME;
+    let mut rng = rand::thread_rng();
+    for mut camera in &mut camera_query {
+        camera_shake.base = Some(camera.translation);
+        let offset = Vec2::from_angle(rng.gen_range(0.0..std::f32::consts::TAU)) * strength;
+        camera.translation += offset.extend(0.0);
+    }
+    camera_shake.t -= time.delta_seconds() * animation_speed.0;
+}
+
+// Puts the camera back where it really is before the game systems run, so they never see the
+// shake (and it ends with the camera exactly where it was)
+fn unshake_camera(
+    mut camera_shake: ResMut<CameraShake>,
+    mut camera_query: Query<&mut Transform, With<Camera2d>>,
+) {
+    let Some(base) = camera_shake.base.take() else {
+        return;
+    };
+    for mut camera in &mut camera_query {
+        camera.translation = base;
+    }
+}
+
+// Adds a disk to the ghost trail, removing the oldest one if the trail is already full
+fn leave_ghost_trail(
+    commands: &mut Commands,
+    ghost_trail: &mut GhostTrail,
+    mesh_handles: &MeshHandles,
+    materials: &mut Assets<ColorMaterial>,
+    transform: Transform,
+    color: Color,
+) {
+    if ghost_trail.0.len() >= GHOST_TRAIL_LENGTH {
+        if let Some(oldest) = ghost_trail.0.pop_front() {
+            if let Some(mut entity) = commands.get_entity(oldest) {
+                entity.despawn();
+            }
+        }
+    }
+
+    // Each disk has its own material, so it can fade out by itself
+    let entity = commands
+        .spawn((
+            GhostTrailDisk(Timer::from_seconds(GHOST_TRAIL_LIFE, TimerMode::Once)),
+            MaterialMesh2dBundle {
+                mesh: mesh_handles.circle.clone().into(),
+                material: materials.add(ColorMaterial::from(color)),
+                transform,
+                ..default()
+            },
+            InGame,
+        ))
+        .id();
+    ghost_trail.0.push_back(entity);
+}
+
+// Fades out the disks of the ghost trail, removing them when their life is over
+fn update_ghost_trail(
+    mut commands: Commands,
+    time: Res<Time>,
+    animation_speed: Res<AnimationSpeed>,
+    mut ghost_trail: ResMut<GhostTrail>,
+    ghost_alpha: Res<GhostAlpha>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(Entity, &mut GhostTrailDisk, &Handle<ColorMaterial>)>,
+) {
+    let delta = time.delta().mul_f32(animation_speed.0);
+    for (entity, mut trail_disk, material) in &mut query {
+        if trail_disk.0.tick(delta).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        if let Some(material) = materials.get_mut(material) {
+            material
+                .color
+                .set_a(ghost_alpha.0 * trail_disk.0.percent_left());
+        }
+    }
+
+    // Forget the disks that are gone (faded out, or removed with the game)
+    ghost_trail.0.retain(|&entity| query.contains(entity));
+}
+
+// Number of moves the puzzle player has made since the puzzle was loaded
+fn puzzle_moves_played(puzzle: &ActivePuzzle, history: &MoveHistory) -> usize {
+    history.moves[puzzle.start.saturating_sub(history.trimmed)..]
+        .iter()
+        .filter(|record| record.turn == puzzle.player)
+        .count()
+}
+
+// Checking for a win whenever the board changes (and for running out of moves in a puzzle)
+fn check_for_wins(
+    mut commands: Commands,
+    board: Res<Board>,
+    history: Res<MoveHistory>,
+    puzzle: Option<Res<ActivePuzzle>>,
+    mut game_state: ResMut<State<GameState>>,
+    mut ghost_disks: Query<&mut Visibility, With<GhostDisk>>,
+    mut main_menu_info: ResMut<MainMenuInfo>,
+    game_mode: Res<GameMode>,
+    mut demo_timer: ResMut<DemoTimer>,
+    (theme, swap_colors, layout, win_line_style, win_line_thickness): (
+        Res<Theme>,
+        Res<SwapColors>,
+        Res<BoardLayout>,
+        Res<WinLineStyle>,
+        Res<WinLineThickness>,
+    ),
+    (show_particles, reduced_motion, mut camera_shake): (
+        Res<ShowParticles>,
+        Res<ReducedMotion>,
+        ResMut<CameraShake>,
+    ),
+    (
+        turn,
+        simultaneous_win_rule,
+        mut game_meta,
+        mut mirror_moves,
+        clock,
+        ai_search_time,
+        mut best_times,
+    ): (
+        Res<Turn>,
+        Res<SimultaneousWinRule>,
+        ResMut<GameMeta>,
+        ResMut<MirrorMoves>,
+        Res<GameClock>,
+        Res<AiSearchTime>,
+        ResMut<BestTimes>,
+    ),
+    mesh_handles: Res<MeshHandles>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut game_match: Option<ResMut<Match>>,
+    (mut checked_moves, mut unchecked, falling_disks): (
+        Local<usize>,
+        Local<bool>,
+        Query<(), With<FallingDisk>>,
+    ),
+) {
+    // A finished demo game is waiting to restart, and its winning line is already drawn
+    if matches!(*game_mode, GameMode::AiVsAi { .. }) && demo_timer.game_over {
+        return;
+    }
+
+    // Nothing on the board changed since the last check, so there is nothing new to find.
+    // A change is only checked once the dropped disk landed, so the win isn't shown before it.
+    *unchecked |= board.is_changed();
+    if !*unchecked || !falling_disks.is_empty() {
+        return;
+    }
+    *unchecked = false;
+    let single_move = history.moves_made() == *checked_moves + 1;
+    *checked_moves = history.moves_made();
+
+    // After a single move, only the lines through it can have become a win.
+    // Anything else (a new or loaded game, a flip) needs the whole board scanned.
+    let lines = match history.last_move(&board) {
+        Some(last_move) if single_move && !board.wins_through(last_move.row, last_move.col) => {
+            Vec::new()
+        }
+        _ => board.all_winning_lines(),
+    };
+    if !lines.is_empty() {
+        for &(winner, from, to) in &lines {
+            log_event(&GameEvent::Win { winner, from, to });
+        }
+        // The last action (a move or a flip) was made by the player before the one to move
+        let winner = resolve_winner(
+            &lines,
+            turn.previous(board.players),
+            board.players,
+            *simultaneous_win_rule,
+        );
+        game_meta.finish(winner);
+        announce(&mut commands, winner.map_or(Announcement::Draw, Announcement::Win));
+        // The player's moves are what the mirror plays next time
+        if let GameMode::VsAi { ai, .. } | GameMode::MirrorHuman { ai } = *game_mode {
+            mirror_moves.record(&history, ai);
+        }
+
+        // Draw every winning line (there can be more than one, like after flipping the board),
+        // as a bar, rings around its disks or both
+        let dims = get_dimensions(&board, 0.0, *layout);
+        let disk_dims = get_dimensions(&board, DISK_PADDING, *layout);
+        let color = theme.colors().winner;
+        let thickness = win_line_thickness.0;
+        for &(_, from, to) in &lines {
+            let square = board.win_condition == WinCondition::Square;
+            if *win_line_style != WinLineStyle::Bar {
+                let cells: Vec<(i32, i32)> = if square {
+                    SQUARE_CELLS
+                        .iter()
+                        .map(|&(row, col)| (from.0 + row, from.1 + col))
+                        .collect()
+                } else {
+                    line_cells(from, to).collect()
+                };
+                draw_winning_rings(
+                    &mut commands,
+                    &mesh_handles,
+                    &mut materials,
+                    &disk_dims,
+                    &cells,
+                    color,
+                    thickness,
+                );
+            }
+            if *win_line_style != WinLineStyle::Rings {
+                if square {
+                    draw_winning_square(&mut commands, &dims, from, to, color, thickness);
+                } else {
+                    draw_winning_line(&mut commands, &dims, from, to, color, thickness);
+                }
+            }
+        }
+
+        // Celebrate with particles in the winner's colors, with a limit on them for huge wins
+        if show_particles.0 && !reduced_motion.0 {
+            let disk_dims = get_dimensions(&board, DISK_PADDING, *layout);
+            let mut remaining = MAX_PARTICLES;
+            for &(line_winner, from, to) in &lines {
+                let disks = (to.0 - from.0).abs().max((to.1 - from.1).abs()) as usize + 1;
+                let count = (disks * PARTICLES_PER_DISK).min(remaining);
+                remaining -= count;
+                spawn_particles(
+                    &mut commands,
+                    &mesh_handles,
+                    &mut materials,
+                    &disk_dims,
+                    (from, to),
+                    line_winner.to_color(&theme.colors().swapped(*swap_colors)),
+                    count,
+                );
+            }
+        }
+
+        // Shake the camera a bit, unless motion is reduced
+        if !reduced_motion.0 {
+            camera_shake.strength = CAMERA_SHAKE_STRENGTH;
+            camera_shake.t = CAMERA_SHAKE_TIME;
+        }
+
+        // Hide all ghost disks
+        for mut ghost_disk_visibility in &mut ghost_disks {
+            ghost_disk_visibility.is_visible = false;
+        }
+
+        // The demo keeps going, the next game starts after a short pause
+        if let GameMode::AiVsAi { .. } = *game_mode {
+            finish_demo_game(&mut demo_timer);
+            return;
+        }
+
+        // In a match, the game counts towards it.
+        // Until someone has won the match, the next game starts after a short pause.
+        // A draw doesn't count towards winning the match.
+        if let Some(game_match) = &mut game_match {
+            match winner {
+                Some(winner) => game_match.record_win(winner),
+                None => game_match.record_draw(),
+            }
+            if game_match.winner().is_none() {
+                game_match.next_game =
+                    Some(Timer::from_seconds(MATCH_NEXT_GAME_DELAY, TimerMode::Once));
+                return;
+            }
+        }
+
+        // A puzzle is only solved by the right player, within the allowed moves
+        main_menu_info.message = puzzle.map(|puzzle| {
+            if winner == Some(puzzle.player)
+                && puzzle_moves_played(&puzzle, &history) <= puzzle.moves
+            {
+                "Puzzle solved!".to_string()
+            } else {
+                "Puzzle failed".to_string()
+            }
+        });
+        commands.remove_resource::<ActivePuzzle>();
+
+        // Single games won against the AI are timed, the best time per board and difficulty is kept
+        if let (GameMode::VsAi { depth, ai }, Some(winner)) = (*game_mode, winner) {
+            if winner != ai && game_match.is_none() && main_menu_info.message.is_none() {
+                let key = BestTimes::key(board.rows, board.cols, depth, ai_search_time.0);
+                let time = format_clock(clock.0);
+                main_menu_info.message = Some(if best_times.record(&key, clock.0) {
+                    format!("New best time! {}", time)
+                } else {
+                    format!("Won in {}, best time {}", time, format_clock(best_times.0[&key]))
+                });
+            }
+        }
+
+        // Send to game menu with a winner (of the whole match, if one was played),
+        // the match is over then and its final score is shown
+        main_menu_info.allow_resume = false;
+        main_menu_info.winner = winner;
+        main_menu_info.won_match = game_match.is_some();
+        if winner.is_none() && main_menu_info.message.is_none() {
+            main_menu_info.message =
+                Some("Lines for more than one player, it's a draw".to_string());
+        }
+        if let Some(game_match) = game_match {
+            main_menu_info.message = Some(format!("Final score: {}", game_match.score()));
+            commands.remove_resource::<Match>();
+        }
+        game_state.set(GameState::Menu).unwrap();
+    } else if let Some(puzzle) = puzzle {
+        // The puzzle player used all their moves without winning
+        if puzzle_moves_played(&puzzle, &history) >= puzzle.moves {
+            for mut ghost_disk_visibility in &mut ghost_disks {
+                ghost_disk_visibility.is_visible = false;
+            }
+
+            main_menu_info.allow_resume = false;
+            main_menu_info.winner = None;
+            main_menu_info.message = Some("Puzzle failed".to_string());
+            commands.remove_resource::<ActivePuzzle>();
+            game_state.set(GameState::Menu).unwrap();
+        }
+    }
+}
+
+// The cells of a straight line from one end to the other, both included
+fn line_cells(from: (i32, i32), to: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+    let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs());
+    let (row_delta, col_delta) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    (0..=steps).map(move |i| (from.0 + row_delta * i, from.1 + col_delta * i))
+}
+
+// Draws a ring around each of the winning disks, behind them (thicker rings stick out further)
+fn draw_winning_rings(
+    commands: &mut Commands,
+    mesh_handles: &MeshHandles,
+    materials: &mut Assets<ColorMaterial>,
+    disk_dims: &Dimensions,
+    cells: &[(i32, i32)],
+    color: Color,
+    thickness: f32,
+) {
+    // One material for all the rings, so the theme can recolor them
+    let material = materials.add(ColorMaterial::from(color));
+    for &(row, col) in cells {
+        let mut transform = get_disk_transform(disk_dims, row, col);
+        transform.translation.z = 0.15;
+        transform.scale *= Vec3::splat(1.0 + thickness * WINNER_RING_SCALE);
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: mesh_handles.circle.clone().into(),
+                material: material.clone(),
+                transform,
+                ..default()
+            },
+            WinnerLine,
+            WinnerRing,
+            InGame,
+        ));
+    }
+}
+
+// Draws a line over the disks from one end of a winning line to the other,
+// as thick as the given part of a cell
+fn draw_winning_line(
+    commands: &mut Commands,
+    dims: &Dimensions,
+    from: (i32, i32),
+    to: (i32, i32),
+    color: Color,
+    thickness: f32,
+) {
+    let mut from = get_disk_transform(dims, from.0, from.1).translation;
+    from.z = 0.4;
+
+    let mut to = get_disk_transform(dims, to.0, to.1).translation;
+    to.z = 0.4;
+
+    commands.spawn((
+        SpriteBundle {
+            transform: Transform {
+                translation: from + (to - from) / 2.0,
+                scale: Vec3::new(
+                    (to - from).length()
+                        + (dims.col_width.powf(2.0) + dims.row_height.powf(2.0)).sqrt() / 2.5,
+                    (dims.col_width * thickness).min(dims.row_height * thickness),
+                    0.0,
+                ),
+                rotation: Quat::from_rotation_z((to.y - from.y).atan2(to.x - from.x)),
+            },
+            sprite: Sprite { color, ..default() },
+            ..default()
+        },
+        WinnerLine,
+        InGame,
+    ));
+}
+
+// Outlines a winning square by its top left and bottom right disks,
+// with a line along each side through the four disks
+fn draw_winning_square(
+    commands: &mut Commands,
+    dims: &Dimensions,
+    top_left: (i32, i32),
+    bottom_right: (i32, i32),
+    color: Color,
+    thickness: f32,
+) {
+    let top_right = (top_left.0, bottom_right.1);
+    let bottom_left = (bottom_right.0, top_left.1);
+    for (from, to) in [
+        (top_left, top_right),
+        (top_right, bottom_right),
+        (bottom_right, bottom_left),
+        (bottom_left, top_left),
+    ] {
+        draw_winning_line(commands, dims, from, to, color, thickness);
+    }
+}
+
+// Spawns particles at random spots along a winning line, flying off in random directions
+fn spawn_particles(
+    commands: &mut Commands,
+    mesh_handles: &MeshHandles,
+    materials: &mut Assets<ColorMaterial>,
+    dims: &Dimensions,
+    (from, to): ((i32, i32), (i32, i32)),
+    color: Color,
+    count: usize,
+) {
+    let from = get_disk_transform(dims, from.0, from.1).translation;
+    let to = get_disk_transform(dims, to.0, to.1).translation;
+    let mut rng = rand::thread_rng();
+
+    for _ in 0..count {
+        let mut translation = from.lerp(to, rng.gen());
+        translation.z = 0.5;
+        let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+        let speed = rng.gen_range(0.3..1.0) * PARTICLE_SPEED;
+
+        // Each particle has its own material, so it can fade out by itself
+        commands.spawn((
+            Particle {
+                velocity: Vec2::from_angle(angle) * speed,
+                life: Timer::from_seconds(PARTICLE_LIFE, TimerMode::Once),
+            },
+            MaterialMesh2dBundle {
+                mesh: mesh_handles.circle.clone().into(),
+                material: materials.add(ColorMaterial::from(color)),
+                transform: Transform {
+                    translation,
+                    scale: Vec3::new(dims.scale, dims.scale, 0.0) * PARTICLE_SIZE,
+                    ..default()
+                },
+                ..default()
+            },
+            InGame,
+        ));
+    }
+}
+
+// Moves the particles (pulled down by gravity) and fades them out, removing them when their life is over.
+// They keep going in the menu, which shows up as soon as someone wins.
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    animation_speed: Res<AnimationSpeed>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut query: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        &Handle<ColorMaterial>,
+    )>,
+) {
+    let delta = time.delta().mul_f32(animation_speed.0);
+    for (entity, mut particle, mut transform, material) in &mut query {
+        if particle.life.tick(delta).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        particle.velocity.y -= PARTICLE_GRAVITY * delta.as_secs_f32();
+        transform.translation += particle.velocity.extend(0.0) * delta.as_secs_f32();
+        if let Some(material) = materials.get_mut(material) {
+            material.color.set_a(particle.life.percent_left());
+        }
+    }
+}
+
+// Stops the demo game, waiting a bit before the next one starts
+fn finish_demo_game(demo_timer: &mut DemoTimer) {
+    demo_timer.game_over = true;
+    demo_timer
+        .timer
+        .set_duration(Duration::from_secs_f32(DEMO_RESTART_DELAY));
+    demo_timer.timer.reset();
+}
+
+// Starts the next game of a match once the pause after the last one is over,
+// with the same board and options (rocks are placed anew)
+fn start_next_match_game(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_match: Option<ResMut<Match>>,
+    in_game_query: Query<Entity, With<InGame>>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut history: ResMut<MoveHistory>,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    layout: Res<BoardLayout>,
+) {
+    let Some(mut game_match) = game_match else {
+        return;
+    };
+    let Some(timer) = &mut game_match.next_game else {
+        return;
+    };
+    if !timer.tick(time.delta()).finished() {
+        return;
+    }
+    game_match.next_game = None;
+
+    let last_board = board.clone();
+    cleanup(&mut commands, in_game_query);
+    new_game(
+        &mut commands,
+        &mesh_handles,
+        &material_handles,
+        theme.colors(),
+        &mut board,
+        &mut turn,
+        &mut history,
+        last_board.rows,
+        last_board.cols,
+        last_board.players,
+        &asset_server,
+        *layout,
+    );
+    board.drop_pattern = last_board.drop_pattern;
+    board.win_condition = last_board.win_condition;
+    board.win_directions = last_board.win_directions;
+    board.win_lengths = last_board.win_lengths;
+    if !last_board.rocks.is_empty() {
+        board.place_rocks(rand::random());
+        draw_disks(&mut commands, &mesh_handles, &material_handles, &board, *layout);
+    }
+}
+
+// Plays the AI moves in the demo, and restarts it once a game is over
+fn ai_vs_ai(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_mode: Res<GameMode>,
+    mut demo_timer: ResMut<DemoTimer>,
+    demo_delay: Res<DemoDelay>,
+    in_game_query: Query<Entity, With<InGame>>,
+    move_query: Query<&mut Style, With<Move>>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut history: ResMut<MoveHistory>,
+    asset_server: Res<AssetServer>,
+    (theme, swap_colors): (Res<Theme>, Res<SwapColors>),
+    (history_mode, history_limit, layout): (
+        Res<HistoryDisplayMode>,
+        Res<HistoryLimit>,
+        Res<BoardLayout>,
+    ),
+    clock: Res<GameClock>,
+) {
+    let GameMode::AiVsAi { depth } = *game_mode else {
+        return;
+    };
+
+    if !demo_timer.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    // The last move won, the game ends once its disk landed
+    if !demo_timer.game_over && board.check_for_wins().is_some() {
+        return;
+    }
+
+    if demo_timer.game_over {
+        // Start the next game on the same board size
+        let (rows, cols) = (board.rows, board.cols);
+        cleanup(&mut commands, in_game_query);
+        new_game(
+            &mut commands,
+            &mesh_handles,
+            &material_handles,
+            theme.colors(),
+            &mut board,
+            &mut turn,
+            &mut history,
+            rows,
+            cols,
+            2,
+            &asset_server,
+            *layout,
+        );
+        demo_timer.game_over = false;
+        demo_timer
+            .timer
+            .set_duration(Duration::from_secs_f32(demo_delay.0));
+        demo_timer.timer.reset();
+    } else if let Some(col) = best_move(&board, turn.to_disk(), depth) {
+        drop_disk(
+            commands,
+            &mesh_handles,
+            &material_handles,
+            &theme.colors().swapped(*swap_colors),
+            &mut board,
+            &mut turn,
+            &mut history,
+            *history_mode,
+            *history_limit,
+            *layout,
+            move_query,
+            asset_server,
+            col,
+            None,
+            clock.0,
+        );
+    } else {
+        // The board is full, it's a draw
+        finish_demo_game(&mut demo_timer);
+    }
+}
+
+// Plays the AI's moves in a game against it, taking at least the AI delay for each move
+// (the time spent picking the move counts towards it)
+fn ai_turn(
+    commands: Commands,
+    time: Res<Time>,
+    game_mode: Res<GameMode>,
+    mut ai_move: ResMut<AiMove>,
+    (ai_delay, ai_search_time, mut mirror_moves): (
+        Res<AiDelay>,
+        Res<AiSearchTime>,
+        ResMut<MirrorMoves>,
+    ),
+    move_query: Query<&mut Style, With<Move>>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut history: ResMut<MoveHistory>,
+    asset_server: Res<AssetServer>,
+    (theme, swap_colors): (Res<Theme>, Res<SwapColors>),
+    (history_mode, history_limit): (Res<HistoryDisplayMode>, Res<HistoryLimit>),
+    layout: Res<BoardLayout>,
+    clock: Res<GameClock>,
+) {
+    // The mirror has no depth, it plays back the recorded moves instead of searching
+    let (ai, depth) = match *game_mode {
+        GameMode::VsAi { depth, ai } => (ai, Some(depth)),
+        GameMode::MirrorHuman { ai } => (ai, None),
+        _ => return,
+    };
+    if *turn != ai {
+        return;
+    }
+
+    if ai_move.col.is_none() {
+        // The player's move ended the game, there is nothing to reply to
+        if board.check_for_wins().is_some() {
+            return;
+        }
+        // The AI's turn just started, the board is full if there is no move
+        let col = match (depth, ai_search_time.0) {
+            (None, _) => mirror_moves.next_col(&board),
+            (Some(_), Some(seconds)) => {
+                best_move_timed(&board, turn.to_disk(), Duration::from_secs_f32(seconds))
+            }
+            (Some(depth), None) => best_move(&board, turn.to_disk(), depth),
+        };
+        let Some(col) = col else {
+            return;
+        };
+        ai_move.pick(col, ai_delay.0);
+    }
+
+    let Some(col) = ai_move.ready(time.delta()) else {
+        return;
+    };
+    drop_disk(
+        commands,
+        &mesh_handles,
+        &material_handles,
+        &theme.colors().swapped(*swap_colors),
+        &mut board,
+        &mut turn,
+        &mut history,
+        *history_mode,
+        *history_limit,
+        *layout,
+        move_query,
+        asset_server,
+        col,
+        None,
+        clock.0,
+    );
+}
+
+// Remembers the column clicked while the AI is thinking, the last click counts
+fn queue_move(
+    windows: Res<Windows>,
+    buttons: Res<Input<MouseButton>>,
+    game_mode: Res<GameMode>,
+    turn: Res<Turn>,
+    board: Res<Board>,
+    drop_input_region: Res<DropInputRegion>,
+    view_rotation: Res<ViewRotation>,
+    layout: Res<BoardLayout>,
+    mut queued_move: ResMut<QueuedMove>,
+) {
+    let (GameMode::VsAi { ai, .. } | GameMode::MirrorHuman { ai }) = *game_mode else {
+        return;
+    };
+    if *turn != ai || !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let Some(mouse_pos) = windows.get_primary().and_then(Window::cursor_position) else {
+        return;
+    };
+    if let Some(col) =
+        mouse_pos_to_col(mouse_pos, &board, *drop_input_region, *view_rotation, *layout)
+    {
+        queued_move.0 = Some(col);
+    }
+}
+
+// Plays the queued move once the player can move again. The column may have filled up
+// in the meantime, then the move is forgotten. Runs before the input lock is updated,
+// so the lock is back on before a click in the same frame could drop another disk.
+fn play_queued_move(
+    commands: Commands,
+    mut queued_move: ResMut<QueuedMove>,
+    input_locked: Res<InputLocked>,
+    game_mode: Res<GameMode>,
+    mesh_handles: Res<MeshHandles>,
+    material_handles: Res<MaterialHandles>,
+    mut board: ResMut<Board>,
+    mut turn: ResMut<Turn>,
+    mut history: ResMut<MoveHistory>,
+    query: Query<&mut Style, With<Move>>,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    swap_colors: Res<SwapColors>,
+    (history_mode, history_limit): (Res<HistoryDisplayMode>, Res<HistoryLimit>),
+    layout: Res<BoardLayout>,
+    clock: Res<GameClock>,
+) {
+    if queued_move.0.is_none() {
+        return;
+    }
+    // The AI's move ended the game, so the queued one is never played
+    if board.check_for_wins().is_some() {
+        queued_move.0 = None;
+        return;
+    }
+    if input_locked.0 || !game_mode.human_turn(*turn) {
+        return;
+    }
+    let Some(col) = queued_move.take_playable(&board) else {
+        return;
+    };
+    drop_disk(
+        commands,
+        &mesh_handles,
+        &material_handles,
+        &theme.colors().swapped(*swap_colors),
+        &mut board,
+        &mut turn,
+        &mut history,
+        *history_mode,
+        *history_limit,
+        *layout,
+        query,
+        asset_server,
+        col,
+        None,
+        clock.0,
+    );
+}
+
+// Shows where the AI will drop while it waits out its think time, when turned on in the settings
+fn update_ai_intent(
+    game_mode: Res<GameMode>,
+    ai_move: Res<AiMove>,
+    show_ai_intent: Res<ShowAiIntent>,
+    ai_delay: Res<AiDelay>,
+    board: Res<Board>,
+    turn: Res<Turn>,
+    layout: Res<BoardLayout>,
+    material_handles: Res<MaterialHandles>,
+    mut query: Query<
+        (&mut Transform, &mut Visibility, &mut Handle<ColorMaterial>),
+        With<AiIntentMarker>,
+    >,
+) {
+    let intent = match *game_mode {
+        GameMode::VsAi { ai, .. } | GameMode::MirrorHuman { ai }
+            if show_ai_intent.0 && ai_delay.0 > 0.0 && *turn == ai =>
+        {
+            ai_move.col
+        }
+        _ => None,
+    };
+    let landing = intent.and_then(|col| Some((board.try_drop(col).ok()?, col)));
+
+    for (mut transform, mut visibility, mut material) in &mut query {
+        let Some((row, col)) = landing else {
+            visibility.is_visible = false;
+            continue;
+        };
+        let disk_dims = get_dimensions(&board, DISK_PADDING, *layout);
+        *transform = get_disk_transform(&disk_dims, row, col);
+        transform.translation.z = 0.2;
+        *material = material_handles.get_ghost_disk_material(turn.to_ghost_disk());
+        visibility.is_visible = true;
+    }
+}
+
+// Hides the preview of the AI's reply when the game is left, so it doesn't linger over the board
+fn hide_ai_intent(mut query: Query<&mut Visibility, With<AiIntentMarker>>) {
+    for mut visibility in &mut query {
+        visibility.is_visible = false;
+    }
+}
+
+// Any key (or click) in the demo goes back to the main menu, except for the help keys
+fn check_for_demo_exit(
+    keyboard_input: Res<Input<KeyCode>>,
+    buttons: Res<Input<MouseButton>>,
+    mut skip_click: ResMut<SkipClick>,
+    game_mode: Res<GameMode>,
+    mut game_state: ResMut<State<GameState>>,
+    mut main_menu_info: ResMut<MainMenuInfo>,
+) {
+    let GameMode::AiVsAi { .. } = *game_mode else {
+        return;
+    };
+
+    // The help keys only show the help
+    let mut exit = keyboard_input
+        .get_just_pressed()
+        .any(|key| !HELP_KEYS.contains(key));
+    if buttons.get_just_pressed().next().is_some() {
+        // The click that started the demo from the menu doesn't count
+        if skip_click.0 {
+            skip_click.0 = false;
+        } else {
+            exit = true;
+        }
+    }
+
+    if exit {
+        main_menu_info.allow_resume = false;
+        main_menu_info.winner = None;
+        main_menu_info.message = None;
+        game_state.set(GameState::Menu).unwrap();
+    }
+}
+
+// An app playing the columns one after the other through the game's own systems, the next disk
+// dropped once the last one landed, for the tests of what a game reports. The systems run on the
+// calling thread, so what they log can be captured by a subscriber set for it.
+#[cfg(test)]
+pub fn play_scripted_game(cols: &'static [i32]) -> App {
+    let mut app = App::new();
+    app.add_plugin(bevy::asset::AssetPlugin::default())
+        .add_asset::<ColorMaterial>()
+        .add_asset::<Mesh>()
+        .add_state(GameState::Playing)
+        .insert_resource(crate::accessibility::AccessibilityLog::new(false))
+        .insert_resource(Time::default())
+        .insert_resource(Board::new(6, 7, 2))
+        .insert_resource(Turn::FIRST)
+        .insert_resource(MoveHistory::new())
+        .insert_resource(GameMode::HotSeat)
+        .insert_resource(GameMeta::new())
+        .insert_resource(GameClock::default())
+        .insert_resource(MainMenuInfo {
+            allow_resume: true,
+            winner: None,
+            message: None,
+            won_match: false,
+        })
+        .insert_resource(DemoTimer {
+            timer: Timer::from_seconds(DEMO_RESTART_DELAY, TimerMode::Repeating),
+            game_over: false,
+        })
+        .insert_resource(MirrorMoves::default())
+        .insert_resource(BestTimes::default())
+        .insert_resource(CameraShake::default())
+        .insert_resource(Theme::default())
+        .insert_resource(SwapColors(false))
+        .insert_resource(BoardLayout::default())
+        .insert_resource(WinLineStyle::default())
+        .insert_resource(WinLineThickness(1.0))
+        .insert_resource(SimultaneousWinRule::default())
+        .insert_resource(ShowParticles(false))
+        .insert_resource(ReducedMotion(true))
+        .insert_resource(AnimationSpeed(1.0))
+        .insert_resource(FallGravity(6000.0))
+        .insert_resource(AiSearchTime(None));
+
+    let mut materials = app.world.resource_mut::<Assets<ColorMaterial>>();
+    let mut add = |color| materials.add(ColorMaterial::from(color));
+    let material_handles = MaterialHandles {
+        background: add(Color::WHITE),
+        disks: vec![add(Color::RED), add(Color::BLUE)],
+        ghost_disks: vec![add(Color::RED), add(Color::BLUE)],
+        last_move: add(Color::WHITE),
+        rock: add(ROCK_COLOR),
+        disk_outline: add(Color::BLACK),
+        fog_disk: add(Color::GRAY),
+    };
+    let circle = app
+        .world
+        .resource_mut::<Assets<Mesh>>()
+        .add(circle_mesh(CircleVertices(32)));
+    app.insert_resource(material_handles)
+        .insert_resource(MeshHandles { circle });
+
+    // Stands in for the player, clicking the next column once nothing is falling anymore
+    let mut script = cols.iter().copied();
+    let play_next = move |commands: Commands,
+                          mesh_handles: Res<MeshHandles>,
+                          material_handles: Res<MaterialHandles>,
+                          (mut board, mut turn, mut history): (
+        ResMut<Board>,
+        ResMut<Turn>,
+        ResMut<MoveHistory>,
+    ),
+                          state: Res<State<GameState>>,
+                          query: Query<&mut Style, With<Move>>,
+                          asset_server: Res<AssetServer>,
+                          falling_disks: Query<(), With<FallingDisk>>| {
+        if *state.current() != GameState::Playing || !falling_disks.is_empty() {
+            return;
+        }
+        if let Some(col) = script.next() {
+            drop_disk(
+                commands,
+                &mesh_handles,
+                &material_handles,
+                Theme::default().colors(),
+                &mut board,
+                &mut turn,
+                &mut history,
+                HistoryDisplayMode::Text,
+                HistoryLimit(None),
+                BoardLayout::default(),
+                query,
+                asset_server,
+                col,
+                None,
+                0.0,
+            );
+        }
+    };
+    app.add_stage_after(
+        CoreStage::Update,
+        "scripted game",
+        SystemStage::single_threaded()
+            .with_system(play_next.label("play"))
+            .with_system(update_falling_disks.after("play"))
+            .with_system(check_for_wins.after(update_falling_disks))
+            .with_system(crate::event_log::log_state_changes.before("play")),
+    );
+
+    // A few frames for every disk to land and be checked, and for the game to end
+    for _ in 0..cols.len() * 3 + 3 {
+        app.update();
+    }
+    app
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::BoardMargins;
+
+    fn game_data(board: Board, turn: Turn) -> GameData {
+        GameData {
+            board,
+            turn,
+            history: MoveHistory::new(),
+            clock: GameClock::default(),
+            appearance: None,
+            meta: GameMeta::default(),
+        }
+    }
+
+    // The turns taken one after the other, starting with the first player
+    fn turns(players: u8, count: usize) -> Vec<usize> {
+        let mut turn = Turn::FIRST;
+        let mut turns = Vec::new();
+        for _ in 0..count {
+            turns.push(turn.index());
+            turn.next(players);
+        }
+        turns
+    }
+
+    #[test]
+    fn turns_cycle_through_three_players() {
+        assert_eq!(turns(3, 7), [0, 1, 2, 0, 1, 2, 0]);
+    }
+
+    #[test]
+    fn turns_cycle_through_four_players() {
+        assert_eq!(turns(4, 9), [0, 1, 2, 3, 0, 1, 2, 3, 0]);
+    }
+
+    // Open lines of three and two of Red's disks on a board of the rows (from the top)
+    fn open_lines(rows: &str) -> (usize, usize) {
+        let board = Board::from_ascii(rows).unwrap();
+        (
+            board.count_open_threes(Disk(0)),
+            board.count_open_twos(Disk(0)),
+        )
+    }
+
+    #[test]
+    fn open_lines_with_space_on_both_ends() {
+        assert_eq!(open_lines(".......\n.RRR...\n"), (1, 0));
+        assert_eq!(open_lines(".......\n..RR...\n"), (0, 1));
+    }
+
+    #[test]
+    fn lines_blocked_on_one_end_are_not_open() {
+        assert_eq!(open_lines(".......\nBRRR...\n"), (0, 0));
+        assert_eq!(open_lines(".......\nRRR....\n"), (0, 0));
+        assert_eq!(open_lines(".......\n.RR#...\n"), (0, 0));
+    }
+
+    #[test]
+    fn lines_blocked_on_both_ends_are_not_open() {
+        assert_eq!(open_lines(".......\nBRRRB..\n"), (0, 0));
+    }
+
+    #[test]
+    fn open_lines_need_space_for_the_win_length() {
+        let mut board = Board::from_ascii("......\n.RRR.B\n").unwrap();
+        board.win_length = 5;
+        assert_eq!(board.count_open_threes(Disk(0)), 1);
+        board.win_length = 6;
+        assert_eq!(board.count_open_threes(Disk(0)), 0);
+    }
+
+    #[test]
+    fn ai_move_waits_out_the_think_time() {
+        let mut ai_move = AiMove {
+            timer: Timer::from_seconds(0.0, TimerMode::Once),
+            col: None,
+        };
+        let frame = Duration::from_millis(100);
+        assert_eq!(ai_move.ready(frame), None);
+
+        // Picked in the first frame of the AI's turn, then played once a second has gone by
+        ai_move.pick(3, 1.0);
+        for _ in 0..9 {
+            assert_eq!(ai_move.ready(frame), None);
+        }
+        assert_eq!(ai_move.ready(frame), Some(3));
+        assert_eq!(ai_move.ready(frame), None);
+    }
+
+    #[test]
+    fn try_drop_lands_on_the_stack() {
+        let board = Board::from_ascii(
+            "...
+             R..
+             RB.",
+        )
+        .unwrap();
+        assert_eq!(board.try_drop(0), Ok(0));
+        assert_eq!(board.try_drop(1), Ok(1));
+        assert_eq!(board.try_drop(2), Ok(2));
+    }
+
+    #[test]
+    fn try_drop_outside_the_board_is_out_of_range() {
+        let board = Board::new(6, 7, 2);
+        assert_eq!(board.try_drop(-1), Err(DropError::OutOfRange));
+        assert_eq!(board.try_drop(7), Err(DropError::OutOfRange));
+    }
+
+    #[test]
+    fn try_drop_into_a_full_column_is_refused() {
+        let board = Board::from_ascii(
+            "R..
+             B..
+             R..",
+        )
+        .unwrap();
+        assert_eq!(board.try_drop(0), Err(DropError::ColumnFull));
+    }
+
+    #[test]
+    fn game_clock_stops_in_the_menu() {
+        let mut app = App::new();
+        app.add_state(GameState::Playing)
+            .insert_resource(Time::default())
+            .insert_resource(GameClock::default())
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(update_clock));
+
+        // Runs a frame that ends at the second since the start
+        let start = bevy::utils::Instant::now();
+        let frame = |app: &mut App, second: u64| {
+            let instant = start + Duration::from_secs(second);
+            app.world
+                .resource_mut::<Time>()
+                .update_with_instant(instant);
+            app.update();
+            app.world.resource::<GameClock>().0
+        };
+
+        frame(&mut app, 0);
+        assert_eq!(frame(&mut app, 1), 1.0);
+
+        let set_state = |app: &mut App, state| {
+            app.world
+                .resource_mut::<State<GameState>>()
+                .set(state)
+                .unwrap();
+        };
+        set_state(&mut app, GameState::Menu);
+        assert_eq!(frame(&mut app, 1), 1.0);
+        assert_eq!(frame(&mut app, 5), 1.0);
+
+        set_state(&mut app, GameState::Playing);
+        assert_eq!(frame(&mut app, 5), 1.0);
+        assert_eq!(frame(&mut app, 6), 2.0);
+    }
+
+    #[test]
+    fn boards_smaller_than_the_win_length_are_rejected() {
+        assert!(!Board::fits_win_length(3, 3, 4));
+        assert!(!Board::fits_win_length(3, 7, 4));
+        assert!(Board::fits_win_length(4, 4, 4));
+    }
+
+    #[test]
+    fn separate_winning_lines_are_all_found() {
+        let board = Board::from_ascii(
+            ".......
+             .......
+             .......
+             BBBB...
+             RRRR...",
+        )
+        .unwrap();
+        let lines = board.all_winning_lines();
+        assert!(lines == [(Turn(1), (3, 0), (3, 3)), (Turn(0), (4, 0), (4, 3))]);
+    }
+
+    #[test]
+    fn overlapping_winning_lines_are_found_once() {
+        // A line of five is one line, and lines sharing a disk are two
+        let board = Board::from_ascii(
+            ".......
+             ...R...
+             ...R...
+             ...R...
+             RRRRR..",
+        )
+        .unwrap();
+        let lines = board.all_winning_lines();
+        assert!(lines == [(Turn(0), (1, 3), (4, 3)), (Turn(0), (4, 0), (4, 4))]);
+    }
+
+    #[test]
+    fn disks_stack_on_top_of_rocks() {
+        let mut board = Board::from_ascii(
+            "....
+             ....
+             .#..
+             ....",
+        )
+        .unwrap();
+        assert_eq!(board.try_drop(1), Ok(1));
+        assert_eq!(board.drop_disk(1, Disk(0)), Some(1));
+        assert_eq!(board.try_drop(1), Ok(0));
+    }
+
+    #[test]
+    fn lines_through_rocks_dont_win() {
+        let board = Board::from_ascii(
+            ".....
+             RR#RR",
+        )
+        .unwrap();
+        assert!(board.check_for_wins().is_none());
+        assert!(board.all_winning_lines().is_empty());
+    }
+
+    #[test]
+    fn alternating_columns_fill_from_the_bottom_and_the_top() {
+        let mut board = Board::new(4, 4, 2);
+        board.drop_pattern = DropPattern::Alternating;
+        assert_eq!(board.drop_disk(0, Disk(0)), Some(3));
+        assert_eq!(board.drop_disk(0, Disk(1)), Some(2));
+        assert_eq!(board.drop_disk(1, Disk(0)), Some(0));
+        assert_eq!(board.drop_disk(1, Disk(1)), Some(1));
+    }
+
+    // The column, player and time of each move in the save, after saving it again and reloading
+    fn reloaded_moves(save: &str) -> Vec<(i32, usize, f32)> {
+        let data: GameData = serde_json::from_str(save).unwrap();
+        let data: GameData = serde_json::from_str(&serde_json::to_string(&data).unwrap()).unwrap();
+        data.history
+            .moves
+            .iter()
+            .map(|record| (record.col, record.turn.index(), record.time))
+            .collect()
+    }
+
+    #[test]
+    fn old_saves_without_move_times_round_trip() {
+        let save = r#"{
+            "board": {"rows": 2, "cols": 2, "disks": [[null, null], ["Blue", "Red"]]},
+            "turn": "Red",
+            "history": {"moves": [[1, "Red"], [1, "Blue"]]}
+        }"#;
+        assert_eq!(reloaded_moves(save), [(1, 0, 0.0), (1, 1, 0.0)]);
+    }
+
+    #[test]
+    fn saves_with_move_times_round_trip() {
+        let save = r#"{
+            "board": {"rows": 2, "cols": 2, "disks": [[null, null], ["Blue", "Red"]]},
+            "turn": "Red",
+            "history": {"moves": [
+                {"col": 1, "turn": "Red", "time": 1.5},
+                {"col": 1, "turn": "Blue", "time": 3.25}
+            ]}
+        }"#;
+        assert_eq!(reloaded_moves(save), [(1, 0, 1.5), (1, 1, 3.25)]);
+    }
+
+    #[test]
+    fn fast_win_check_agrees_with_the_full_scan() {
+        // Games of made up moves (from a simple generator, so they are the same every run),
+        // each checked after every move until someone wins or the board is full
+        for seed in 0..200_u64 {
+            let mut board = Board::new(6, 7, 2);
+            if seed % 2 == 1 {
+                board.win_condition = WinCondition::Square;
+            }
+            let mut state = seed;
+            let mut turn = Turn::FIRST;
+            loop {
+                let moves = board.legal_moves();
+                if moves.is_empty() {
+                    break;
+                }
+                state = state
+                    .wrapping_mul(6_364_136_223_846_793_005)
+                    .wrapping_add(1_442_695_040_888_963_407);
+                let col = moves[(state >> 33) as usize % moves.len()];
+                let row = board.drop_disk(col, turn.to_disk()).unwrap();
+                let won = board.check_for_wins().is_some();
+                assert_eq!(board.wins_through(row, col), won);
+                if won {
+                    break;
+                }
+                turn.next(board.players);
+            }
+        }
+    }
+
+    #[test]
+    fn two_by_two_square_wins() {
+        let mut board = Board::from_ascii(
+            "....
+             ....
+             .RR.
+             BRRB",
+        )
+        .unwrap();
+        board.win_condition = WinCondition::Square;
+        assert_eq!(board.check_square_win(2, 1, Disk(0)), Some((2, 1)));
+        assert_eq!(board.check_square_win(3, 2, Disk(0)), Some((2, 1)));
+        assert!(matches!(
+            board.check_for_wins(),
+            Some((Turn(0), (2, 1), (3, 2)))
+        ));
+    }
+
+    #[test]
+    fn near_miss_is_not_a_square() {
+        // Four in an L shape, and a square with a disk of the other player in it
+        let mut board = Board::from_ascii(
+            "....
+             .R..
+             .RB.
+             RRRB",
+        )
+        .unwrap();
+        board.win_condition = WinCondition::Square;
+        assert_eq!(board.check_square_win(3, 1, Disk(0)), None);
+        assert!(board.check_for_wins().is_none());
+    }
+
+    #[test]
+    fn two_wins_end_a_best_of_three() {
+        let mut game_match = Match::best_of(3, 2);
+        game_match.record_win(Turn::FIRST);
+        assert!(game_match.winner().is_none());
+        game_match.record_win(Turn::FIRST);
+        assert!(game_match.winner() == Some(Turn::FIRST));
+    }
+
+    #[test]
+    fn draws_dont_count_towards_the_match() {
+        let mut game_match = Match::best_of(3, 2);
+        game_match.record_win(Turn(1));
+        game_match.record_draw();
+        game_match.record_draw();
+        assert!(game_match.winner().is_none());
+    }
+
+    #[test]
+    fn horizontal_four_only_wins_with_straight_lines() {
+        let mut board = Board::from_ascii(
+            "....
+             ....
+             ....
+             RRRR",
+        )
+        .unwrap();
+        board.win_directions = WinDirections::DIAGONAL;
+        assert!(board.check_for_wins().is_none());
+        board.win_directions = WinDirections::STRAIGHT;
+        assert!(board.check_for_wins().is_some());
+    }
+
+    #[test]
+    fn diagonal_four_only_wins_with_diagonals() {
+        let mut board = Board::from_ascii(
+            "...R
+             ..RB
+             .RBB
+             RBBR",
+        )
+        .unwrap();
+        board.win_directions = WinDirections::STRAIGHT;
+        assert!(board.check_for_wins().is_none());
+        board.win_directions = WinDirections::DIAGONAL;
+        assert!(board.check_for_wins().is_some());
+    }
+
+    #[test]
+    fn ascii_boards_round_trip() {
+        let positions = [
+            // Empty
+            ".......\n.......\n.......\n.......\n.......\n.......\n",
+            // Columns of uneven heights
+            ".......\n...R...\n...B...\n..BR...\n.RBB...\nRBRR..B\n",
+            // More players and rocks
+            "....\n.G#.\nORBG\n",
+        ];
+        for text in positions {
+            assert_eq!(Board::from_ascii(text).unwrap().to_ascii(), text);
+        }
+    }
+
+    #[test]
+    fn broken_ascii_boards_are_refused() {
+        assert_eq!(Board::from_ascii("\n").err(), Some(ParseError::Empty));
+        assert_eq!(
+            Board::from_ascii("...\n..\n").err(),
+            Some(ParseError::UnevenRows)
+        );
+        assert_eq!(
+            Board::from_ascii("..X\n").err(),
+            Some(ParseError::UnknownCell('X'))
+        );
+    }
+
+    #[test]
+    fn input_is_locked_for_the_ai_and_falling_disks() {
+        // Clicks are ignored while the input is locked (see check_for_click)
+        let mut app = App::new();
+        app.insert_resource(Windows::default())
+            .insert_resource(GameMode::VsAi {
+                depth: 1,
+                ai: Turn(1),
+            })
+            .insert_resource(Turn::FIRST)
+            .insert_resource(InputLocked(false))
+            .add_system(update_input_lock);
+        let locked = |app: &mut App| {
+            app.update();
+            app.world.resource::<InputLocked>().0
+        };
+
+        assert!(!locked(&mut app));
+        *app.world.resource_mut::<Turn>() = Turn(1);
+        assert!(locked(&mut app));
+
+        *app.world.resource_mut::<Turn>() = Turn::FIRST;
+        let falling = app
+            .world
+            .spawn(FallingDisk {
+                target: Vec3::ZERO,
+                speed: 0.0,
+            })
+            .id();
+        assert!(locked(&mut app));
+        app.world.despawn(falling);
+        assert!(!locked(&mut app));
+    }
+
+    // A game on a standard board with the columns played in turn, and the player to move next
+    fn played(cols: &[i32]) -> (Board, MoveHistory, Turn) {
+        let mut board = Board::new(6, 7, 2);
+        let mut history = MoveHistory::new();
+        let mut turn = Turn::FIRST;
+        for &col in cols {
+            board.drop_disk(col, turn.to_disk()).unwrap();
+            let record = MoveRecord {
+                col,
+                turn,
+                time: 0.0,
+                row: None,
+            };
+            history.push(record, HistoryLimit(None));
+            turn.next(board.players);
+        }
+        (board, history, turn)
+    }
+
+    #[test]
+    fn restart_of_an_empty_board_is_immediate() {
+        let (board, history, _) = played(&[]);
+        assert!(RestartConfirmation::default().press(&board, &history));
+    }
+
+    #[test]
+    fn restart_of_a_game_in_progress_needs_a_second_press() {
+        let (board, history, _) = played(&[3, 3, 4]);
+        let mut confirmation = RestartConfirmation::default();
+        assert!(!confirmation.press(&board, &history));
+        assert!(confirmation.press(&board, &history));
+    }
+
+    #[test]
+    fn restart_of_a_won_game_is_immediate() {
+        let (board, history, _) = played(&[0, 0, 1, 1, 2, 2, 3]);
+        assert!(board.check_for_wins().is_some());
+        assert!(RestartConfirmation::default().press(&board, &history));
+    }
+
+    #[test]
+    fn fourth_undo_is_refused_with_a_limit_of_three() {
+        let (mut board, mut history, mut turn) = played(&[3, 3, 4, 4, 2]);
+        let mut undos = UndosTaken::default();
+        for _ in 0..3 {
+            assert!(undos.take(UndoLimit(Some(3))));
+            assert!(history.undo(&mut board, &mut turn));
+        }
+        assert!(!undos.take(UndoLimit(Some(3))));
+        assert_eq!(history.moves.len(), 2);
+    }
+
+    #[test]
+    fn growing_keeps_the_disks_where_they_are() {
+        let mut board = Board::from_ascii(
+            "....
+             .B..
+             RRB.",
+        )
+        .unwrap();
+        board.grow(4, 6).unwrap();
+        assert_eq!(board.to_ascii(), "......\n......\n.B....\nRRB...\n");
+        assert!(board.is_settled());
+    }
+
+    #[test]
+    fn growing_keeps_rising_stacks_at_the_top() {
+        // The first column fills from the bottom, the second one from the top
+        let mut board = Board::from_ascii(
+            ".B..
+             .B..
+             R...",
+        )
+        .unwrap();
+        board.drop_pattern = DropPattern::Alternating;
+        board.grow(5, 5).unwrap();
+        assert_eq!(board.to_ascii(), ".B...\n.B...\n.....\n.....\nR....\n");
+        assert!(board.is_settled());
+    }
+
+    #[test]
+    fn boards_dont_shrink() {
+        let mut board = Board::new(6, 7, 2);
+        assert!(board.grow(5, 7).is_err());
+        assert!(board.grow(6, 6).is_err());
+        assert_eq!((board.rows, board.cols), (6, 7));
+    }
+
+    #[test]
+    fn floating_first_disk_goes_to_the_chosen_row() {
+        let mut board = Board::new(6, 7, 2);
+        board.drop_pattern = DropPattern::Floating;
+        assert_eq!(board.drop_disk_at(2, Disk(0), Some(3)), Some(3));
+        assert_eq!(board.base(2), Some(3));
+        // Other columns still fall to the bottom without a chosen row
+        assert_eq!(board.drop_disk_at(4, Disk(1), None), Some(5));
+    }
+
+    #[test]
+    fn disks_stack_on_a_floating_first_disk() {
+        let mut board = Board::new(6, 7, 2);
+        board.drop_pattern = DropPattern::Floating;
+        board.drop_disk_at(2, Disk(0), Some(3));
+        // Once the column has its base, the chosen row no longer matters
+        assert_eq!(board.drop_disk_at(2, Disk(1), Some(5)), Some(2));
+        assert_eq!(board.drop_disk_at(2, Disk(0), None), Some(1));
+        assert_eq!(board.base(2), Some(3));
+        assert!(board.is_settled());
+    }
+
+    // Drops made by the frames of a held mouse button, pressed (true) or up, with each frame
+    // asking to drop while the button is down (like a column button does while it's clicked)
+    fn drops_while_held(frames: &[bool], require_release: bool) -> usize {
+        let mut drop_release = DropRelease::default();
+        let mut drops = 0;
+        for &pressed in frames {
+            drop_release.update(pressed);
+            if pressed && drop_release.press(require_release) {
+                drops += 1;
+            }
+        }
+        drops
+    }
+
+    #[test]
+    fn held_button_drops_once_when_release_is_required() {
+        assert_eq!(drops_while_held(&[true, true, true, true], true), 1);
+        assert_eq!(drops_while_held(&[true, true, false, true, true], true), 2);
+    }
+
+    #[test]
+    fn held_button_keeps_dropping_without_the_release_setting() {
+        assert_eq!(drops_while_held(&[true, true, true, true], false), 4);
+    }
+
+    // Winning lines for both players, made by flipping the board
+    fn lines_for_both() -> Vec<WinningLine> {
+        let mut board = Board::from_ascii(
+            "....
+             .R.R
+             RBRB
+             BRBR",
+        )
+        .unwrap();
+        assert!(board.all_winning_lines().is_empty());
+        board.apply_gravity_flip();
+        board.all_winning_lines()
+    }
+
+    #[test]
+    fn simultaneous_wins_go_to_the_mover() {
+        let lines = lines_for_both();
+        assert_eq!(lines.len(), 2);
+        let rule = SimultaneousWinRule::Mover;
+        assert!(resolve_winner(&lines, Turn(1), 2, rule) == Some(Turn(1)));
+        assert!(resolve_winner(&lines, Turn(0), 2, rule) == Some(Turn(0)));
+        // A mover without a line of their own leaves the win to the next player with one
+        assert!(resolve_winner(&lines, Turn(2), 3, rule) == Some(Turn(0)));
+    }
+
+    #[test]
+    fn simultaneous_wins_can_be_a_draw() {
+        let lines = lines_for_both();
+        let rule = SimultaneousWinRule::Draw;
+        assert!(resolve_winner(&lines, Turn(1), 2, rule).is_none());
+        assert!(resolve_winner(&lines[..1], Turn(1), 2, rule) == Some(lines[0].0));
+    }
+
+    #[test]
+    fn centered_disks_are_symmetric_about_the_window_center() {
+        let layout = BoardLayout {
+            centered: true,
+            margins: BoardMargins {
+                top: 60.0,
+                bottom: 60.0,
+                side: 30.0,
+            },
+        };
+        let board = Board::new(6, 7, 2);
+        let dims = get_dimensions(&board, DISK_PADDING, layout);
+        for row in 0..board.rows {
+            for col in 0..board.cols {
+                let disk = get_disk_transform(&dims, row, col).translation;
+                let mirrored = get_disk_transform(&dims, row, board.cols - 1 - col).translation;
+                assert!((disk.x + mirrored.x).abs() < 0.001);
+            }
+        }
+
+        // The board and the strip above it are centered from top to bottom
+        let bottom = dims.origin.y - dims.row_height - dims.height;
+        assert!((dims.origin.y + bottom).abs() < 0.001);
+    }
+
+    #[test]
+    fn ghost_materials_follow_the_ghost_alpha() {
+        let mut app = App::new();
+        app.add_plugin(bevy::asset::AssetPlugin::default())
+            .add_asset::<ColorMaterial>()
+            .insert_resource(GhostAlpha(0.3))
+            .add_system(update_ghost_alpha);
+
+        let mut materials = app.world.resource_mut::<Assets<ColorMaterial>>();
+        let mut add = |color| materials.add(ColorMaterial::from(color));
+        let handles = MaterialHandles {
+            background: add(Color::WHITE),
+            disks: vec![add(Color::RED), add(Color::BLUE)],
+            ghost_disks: vec![
+                add(ghost_color(Color::RED, GhostAlpha(0.3))),
+                add(ghost_color(Color::BLUE, GhostAlpha(0.3))),
+            ],
+            last_move: add(Color::WHITE),
+            rock: add(ROCK_COLOR),
+            disk_outline: add(Color::BLACK),
+            fog_disk: add(Color::GRAY),
+        };
+        app.insert_resource(handles);
+        app.update();
+
+        app.world.resource_mut::<GhostAlpha>().0 = 0.7;
+        app.update();
+        let handles = app.world.resource::<MaterialHandles>();
+        let materials = app.world.resource::<Assets<ColorMaterial>>();
+        for handle in &handles.ghost_disks {
+            assert_eq!(materials.get(handle).unwrap().color.a(), 0.7);
+        }
+        // Only the ghosts are see-through
+        for handle in &handles.disks {
+            assert_eq!(materials.get(handle).unwrap().color.a(), 1.0);
+        }
+    }
+
+    #[test]
+    fn drawing_ends_the_game_without_a_winner() {
+        let mut main_menu_info = MainMenuInfo {
+            allow_resume: true,
+            winner: Some(Turn(1)),
+            message: None,
+            won_match: false,
+        };
+        let mut game_match = Match::best_of(3, 2);
+        game_match.record_win(Turn(0));
+
+        end_in_draw(&mut main_menu_info, Some(&mut game_match));
+        assert!(main_menu_info.winner.is_none());
+        assert!(!main_menu_info.allow_resume);
+        assert_eq!(game_match.draws, 1);
+        assert_eq!(
+            main_menu_info.message.as_deref(),
+            Some("Game drawn, final score: 1-0 (1 drawn)")
+        );
+
+        end_in_draw(&mut main_menu_info, None);
+        assert_eq!(main_menu_info.message.as_deref(), Some("Game drawn"));
+    }
+
+    #[test]
+    fn flat_disks_keep_the_json_of_a_list_of_columns() {
+        let (red, blue) = (Some(Disk(0)), Some(Disk(1)));
+        // The cells as the board used to keep them, one list per column
+        let columns = vec![vec![None, red], vec![None, None], vec![red, blue]];
+        let mut disks = Disks::new(2, 3);
+        for (col, column) in columns.iter().enumerate() {
+            disks[col].copy_from_slice(column);
+        }
+
+        let json = serde_json::to_string(&disks).unwrap();
+        assert_eq!(json, serde_json::to_string(&columns).unwrap());
+        assert_eq!(json, r#"[[null,"Red"],[null,null],["Red","Blue"]]"#);
+        let loaded: Disks = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&loaded).unwrap(), json);
+        assert!(loaded.iter().eq(columns.iter().map(Vec::as_slice)));
+
+        assert!(serde_json::from_str::<Disks>(r#"[[null,"Red"],[null]]"#).is_err());
+    }
+
+    #[test]
+    fn set_column_fills_from_the_bottom() {
+        let (red, blue) = (Disk(0), Disk(1));
+        let mut board = Board::new(3, 2, 2);
+        board.set_column(0, &[red, blue, red]).unwrap();
+        board.set_column(1, &[blue]).unwrap();
+        assert_eq!(board.to_ascii(), "R.\nB.\nRB\n");
+    }
+
+    #[test]
+    fn set_column_rejects_too_many_disks() {
+        let (red, blue) = (Disk(0), Disk(1));
+        let mut board = Board::new(3, 2, 2);
+        board.set_column(0, &[blue]).unwrap();
+        assert!(board.set_column(0, &[red, blue, red, blue]).is_err());
+        assert!(board.set_column(2, &[red]).is_err());
+        // The column is left as it was
+        assert!(board.disks[0] == [None, None, Some(blue)]);
+    }
+
+    #[test]
+    fn queued_move_is_played_only_if_it_still_fits() {
+        let board = Board::from_ascii(
+            "R..
+             B..
+             RB.",
+        )
+        .unwrap();
+        let mut queued_move = QueuedMove(Some(1));
+        assert_eq!(queued_move.take_playable(&board), Some(1));
+        assert_eq!(queued_move.0, None);
+
+        // The column filled up while the AI was thinking
+        let mut queued_move = QueuedMove(Some(0));
+        assert_eq!(queued_move.take_playable(&board), None);
+        assert_eq!(queued_move.0, None);
+
+        assert_eq!(QueuedMove(None).take_playable(&board), None);
+    }
+
+    #[test]
+    fn random_moves_are_legal() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let board = Board::from_ascii(
+            "R.#.B
+             B.#.R
+             R.B.B",
+        )
+        .unwrap();
+        for _ in 0..100 {
+            let col = board.random_move(&mut rng).unwrap();
+            assert!(col == 1 || col == 3, "column {} is full", col);
+        }
+
+        let full = Board::from_ascii(
+            "RB
+             BR",
+        )
+        .unwrap();
+        assert_eq!(full.random_move(&mut rng), None);
+    }
+
+    #[test]
+    fn svg_has_a_circle_for_every_hole_and_disk() {
+        let count = |svg: &str, class: &str| svg.matches(&format!("class=\"{}\"", class)).count();
+        let board = Board::from_ascii(
+            "....
+             #...
+             RB..",
+        )
+        .unwrap();
+        let svg = board.to_svg(Theme::Classic.colors());
+        assert_eq!(count(&svg, "board"), 1);
+        assert_eq!(count(&svg, "hole"), 12);
+        assert_eq!(count(&svg, "disk"), 2);
+        assert_eq!(count(&svg, "rock"), 1);
+        assert_eq!(count(&svg, "win"), 0);
+
+        // Once won, the winning line is drawn over the disks
+        let board = Board::from_ascii(
+            "....
+             ....
+             BBB.
+             RRRR",
+        )
+        .unwrap();
+        let svg = board.to_svg(Theme::Classic.colors());
+        assert_eq!(count(&svg, "disk"), 7);
+        assert_eq!(count(&svg, "win"), 1);
+        assert!(svg.ends_with("</svg>\n"));
+    }
+
+    #[test]
+    fn input_is_locked_until_the_countdown_is_over() {
+        let mut app = App::new();
+        app.add_plugin(bevy::core::CorePlugin::default())
+            .add_plugin(bevy::asset::AssetPlugin::default())
+            .add_state(GameState::Countdown)
+            .insert_resource(Windows::default())
+            .insert_resource(Time::default())
+            .insert_resource(Input::<KeyCode>::default())
+            .insert_resource(InputLocked(false))
+            .insert_resource(Theme::Classic)
+            .insert_resource(MainMenuInfo {
+                allow_resume: false,
+                winner: None,
+                message: None,
+                won_match: false,
+            })
+            .add_system_set(SystemSet::on_enter(GameState::Countdown).with_system(start_countdown))
+            .add_system_set(
+                SystemSet::on_update(GameState::Countdown).with_system(update_countdown),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Countdown)
+                    .with_system(end_countdown)
+                    .with_system(unlock_input),
+            );
+
+        // Runs a frame that ends at the second since the start, telling if the input is locked
+        let start = bevy::utils::Instant::now();
+        let frame = |app: &mut App, second: u64| {
+            let instant = start + Duration::from_secs(second);
+            app.world
+                .resource_mut::<Time>()
+                .update_with_instant(instant);
+            app.update();
+            app.world.resource::<InputLocked>().0
+        };
+
+        assert!(frame(&mut app, 0));
+        assert!(frame(&mut app, 1));
+        assert!(frame(&mut app, 2));
+        assert_eq!(
+            app.world.resource::<State<GameState>>().current(),
+            &GameState::Countdown
+        );
+        assert!(!frame(&mut app, 3));
+        assert_eq!(
+            app.world.resource::<State<GameState>>().current(),
+            &GameState::Playing
+        );
+        assert!(app.world.get_resource::<CountdownTimer>().is_none());
+    }
+
+    #[test]
+    fn each_player_needs_their_own_win_length() {
+        let mut board = Board::from_ascii(
+            "....
+             B...
+             B...
+             BRRR",
+        )
+        .unwrap();
+        board.win_lengths = vec![3, 4];
+        let lines = board.all_winning_lines();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].0 == Turn(0));
+        assert_eq!(board.win_length_of(Disk(0)), 3);
+        assert_eq!(board.win_length_of(Disk(1)), 4);
+        assert!(board.wins_through(3, 1));
+        assert!(!board.wins_through(1, 0));
+
+        // With the usual length for both, neither has won yet
+        board.win_lengths = Vec::new();
+        assert!(board.check_for_wins().is_none());
+    }
+
+    #[test]
+    fn only_successful_saves_are_confirmed() {
+        let dir = std::env::temp_dir().join(format!("connect4_save_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("save.json");
+        let path = path.to_str().unwrap();
+        let data = game_data(Board::new(6, 7, 2), Turn::FIRST);
+
+        assert_eq!(
+            save_game(path, &data),
+            Some(format!("Game saved to {}", path))
+        );
+        assert!(fetch_save(path).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+        // The folder is gone now, so the save fails
+        assert_eq!(save_game(path, &data), None);
+    }
+
+    #[test]
+    fn hints_warn_about_lost_positions() {
+        // Blue can complete three in a row at either end of the bottom row
+        let mut board = Board::from_ascii(
+            "....
+             ....
+             .RR.
+             .BB.",
+        )
+        .unwrap();
+        board.win_length = 3;
+        let red = Turn::FIRST.to_disk();
+        assert_eq!(
+            hint_text(&board, red, LossWarning(true)).as_deref(),
+            Some("You're in a losing position, with perfect play")
+        );
+        let hint = hint_text(&board, red, LossWarning(false)).unwrap();
+        assert!(hint.starts_with("Hint: try column "), "{}", hint);
+
+        // Red isn't lost on an empty board, so it gets a move
+        let hint = hint_text(&Board::new(4, 4, 2), red, LossWarning(true)).unwrap();
+        assert!(hint.starts_with("Hint: try column "), "{}", hint);
+
+        let full = Board::from_ascii("RB\nBR").unwrap();
+        assert_eq!(hint_text(&full, red, LossWarning(false)), None);
+    }
+
+    #[test]
+    fn circle_is_rebuilt_with_the_new_vertex_count() {
+        let mut app = App::new();
+        app.add_plugin(bevy::asset::AssetPlugin::default())
+            .add_asset::<Mesh>()
+            .insert_resource(CircleVertices(32))
+            .add_system(update_circle_vertices);
+        let circle = app
+            .world
+            .resource_mut::<Assets<Mesh>>()
+            .add(circle_mesh(CircleVertices(32)));
+        app.insert_resource(MeshHandles {
+            circle: circle.clone(),
+        });
+        let vertices = |app: &App| {
+            let meshes = app.world.resource::<Assets<Mesh>>();
+            meshes.get(&circle).unwrap().count_vertices()
+        };
+
+        app.update();
+        assert_eq!(vertices(&app), 32);
+        app.world.resource_mut::<CircleVertices>().0 = 64;
+        app.update();
+        assert_eq!(vertices(&app), 64);
+    }
+
+    #[test]
+    fn saved_finished_game_keeps_its_result() {
+        // Red wins in the first column
+        let (board, history, turn) = played(&[0, 1, 0, 1, 0, 1, 0]);
+        let mut meta = GameMeta::new();
+        let lines = board.all_winning_lines();
+        let winner = resolve_winner(
+            &lines,
+            turn.previous(board.players),
+            board.players,
+            SimultaneousWinRule::Mover,
+        );
+        meta.finish(winner);
+
+        let data = GameData::new(
+            &board,
+            turn,
+            &history,
+            GameClock(12.0),
+            Theme::Classic,
+            &meta,
+        );
+        let json = serde_json::to_string(&data).unwrap();
+        let saved: GameData = serde_json::from_str(&json).unwrap();
+        assert!(saved.meta.result == Some(Turn::FIRST));
+        assert_eq!(saved.meta.created, meta.created);
+        assert!(saved.meta.finished.is_some());
+
+        // Dates look like 2023-01-31T12:34:56Z
+        let date = saved.meta.finished.unwrap();
+        assert_eq!(date.len(), 20, "{}", date);
+        assert!(
+            date.ends_with('Z') && date.as_bytes()[10] == b'T',
+            "{}",
+            date
+        );
+    }
+
+    #[test]
+    fn history_past_its_limit_drops_the_oldest_moves() {
+        let mut history = MoveHistory::new();
+        let mut turn = Turn::FIRST;
+        for col in 0..5 {
+            let record = MoveRecord {
+                col,
+                turn,
+                time: 0.0,
+                row: None,
+            };
+            history.push(record, HistoryLimit(Some(3)));
+            turn.next(2);
         }
-    }
-}
+        let cols: Vec<i32> = history.moves.iter().map(|record| record.col).collect();
+        assert_eq!(cols, [2, 3, 4]);
+        assert_eq!(history.moves_made(), 5);
 
-// Initial setup, to load all the materials and meshes
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-) {
-    commands.insert_resource(MaterialHandles {
-        background: materials.add(ColorMaterial::from(BACKGROUND_COLOR)),
-        red_disk: materials.add(ColorMaterial::from(RED_DISK_COLOR)),
-        blue_disk: materials.add(ColorMaterial::from(BLUE_DISK_COLOR)),
-        red_ghost_disk: materials.add(ColorMaterial::from({
-            let mut ghost_red = RED_DISK_COLOR;
-            ghost_red.set_a(0.3);
-            ghost_red
-        })),
-        blue_ghost_disk: materials.add(ColorMaterial::from({
-            let mut ghost_blue = BLUE_DISK_COLOR;
-            ghost_blue.set_a(0.3);
-            ghost_blue
-        })),
-    });
+        // Without a limit everything is kept
+        let (_, history, _) = played(&[0, 1, 2, 3, 4]);
+        assert_eq!(history.moves.len(), 5);
+        assert_eq!(history.moves_made(), 5);
+    }
 
-    commands.insert_resource(MeshHandles {
-        circle: meshes.add(shape::Circle::default().into()),
-    });
-}
+    #[test]
+    fn mirror_plays_the_recorded_columns_in_order() {
+        // Red is the player, Blue the AI
+        let (_, history, _) = played(&[3, 0, 4, 1, 5, 2]);
+        let mut mirror_moves = MirrorMoves::default();
+        mirror_moves.record(&history, Turn(1));
+        assert_eq!(mirror_moves.recorded, [3, 4, 5]);
+        // As if the last game was on a wider board
+        mirror_moves.recorded.push(9);
+        mirror_moves.start();
 
-// Removes all entities in the game
-fn cleanup(commands: &mut Commands, query: Query<Entity, With<InGame>>) {
-    for entity in &query {
-        commands.entity(entity).despawn_recursive();
+        let (red, blue) = (Disk(0), Disk(1));
+        let mut board = Board::new(6, 7, 2);
+        board
+            .set_column(4, &[red, blue, red, blue, red, blue])
+            .unwrap();
+        assert_eq!(mirror_moves.next_col(&board), Some(3));
+        // The full column is skipped, and a column past the edge is the last one
+        assert_eq!(mirror_moves.next_col(&board), Some(5));
+        assert_eq!(mirror_moves.next_col(&board), Some(6));
+        // Once they ran out the columns are random
+        let col = mirror_moves.next_col(&board).unwrap();
+        assert!(board.legal_moves().contains(&col));
     }
-}
-
-// Checks for the game change event
-fn check_for_game_change(
-    mut commands: Commands,
-    query: Query<Entity, With<InGame>>,
-    mesh_handles: Res<MeshHandles>,
-    material_handles: Res<MaterialHandles>,
-    mut board: ResMut<Board>,
-    mut turn: ResMut<Turn>,
-    mut move_history: ResMut<MoveHistory>,
-    mut game_change_events: EventReader<GameChange>,
-    asset_server: Res<AssetServer>,
-) {
-    if let Some(game_change) = game_change_events.iter().next() {
-        match game_change {
-            &GameChange::New { rows, cols } => {
-                // Remove all the entities in the game, then create a new one
-                cleanup(&mut commands, query);
-                new_game(
-                    &mut commands,
-                    &mesh_handles,
-                    &material_handles,
-                    &mut board,
-                    &mut turn,
-                    &mut move_history,
-                    rows,
-                    cols,
-                );
-            }
-            GameChange::Save => {
-                let data = GameData {
-                    board: board.clone(),
-                    turn: *turn,
-                    history: move_history.clone(),
-                };
 
-                let Ok(file) = std::fs::File::create("save.json") else {
-                    println!("Failed to create save file");
-                    return;
-                };
-                if let Err(err) = serde_json::to_writer(file, &data) {
-                    println!("Failed to write save file: {}", err);
-                }
-            }
-            GameChange::Load => {
-                let Ok(file) = std::fs::File::open("save.json") else {
-                    println!("Failed to open save file");
-                    return;
-                };
-                let Ok(data) = serde_json::from_reader::<_, GameData>(file) else {
-                    println!("Failed to read save file");
-                    return;
-                };
+    #[test]
+    fn only_a_single_legal_column_is_forced() {
+        let one_left = Board::from_ascii(
+            "RB.
+             BR.
+             RBR",
+        )
+        .unwrap();
+        assert_eq!(forced_move(&one_left), Some(2));
 
-                cleanup(&mut commands, query);
-                new_game(
-                    &mut commands,
-                    &mesh_handles,
-                    &material_handles,
-                    &mut board,
-                    &mut turn,
-                    &mut move_history,
-                    data.board.rows,
-                    data.board.cols,
-                );
-                *board = data.board;
-                *turn = data.turn;
-                *move_history = data.history;
+        let two_left = Board::from_ascii(
+            "R..
+             BR.
+             RBB",
+        )
+        .unwrap();
+        assert_eq!(forced_move(&two_left), None);
 
-                let dims = get_dimensions(&board, DISK_PADDING);
+        // Red has already won, so nothing is dropped
+        let mut won = one_left.clone();
+        won.win_length = 3;
+        assert!(won.check_for_wins().is_some());
+        assert_eq!(forced_move(&won), None);
+    }
 
-                // All the data is now loaded, but the visuals need to sync
-                // Add the disks and history
+    #[test]
+    fn falling_disks_land_with_any_gravity() {
+        for gravity in [0.0, 1500.0, 6000.0, 1.0e6] {
+            // Disks fall down into most columns, and up into rising ones
+            for (from, target) in [(300.0, -200.0), (-300.0, 100.0)] {
+                let mut app = App::new();
+                app.insert_resource(Time::default())
+                    .insert_resource(AnimationSpeed(1.0))
+                    .insert_resource(ReducedMotion(false))
+                    .insert_resource(FallGravity(gravity))
+                    .add_system(update_falling_disks);
+                let target = Vec3::new(0.0, target, 0.1);
+                let disk = app
+                    .world
+                    .spawn((
+                        Transform::from_xyz(0.0, from, 0.1),
+                        FallingDisk { target, speed: 0.0 },
+                    ))
+                    .id();
 
-                // Draw all the disks
-                for row in 0..board.rows {
-                    for col in 0..board.rows {
-                        if let Some(disk) = board.disks[col as usize][row as usize] {
-                            draw_disk(
-                                &mut commands,
-                                &mesh_handles,
-                                &material_handles,
-                                &dims,
-                                col,
-                                row,
-                                disk,
-                            );
-                        }
+                // Frames of 50 milliseconds, for a minute at most
+                let start = bevy::utils::Instant::now();
+                for frame in 0..1200 {
+                    let instant = start + Duration::from_millis(50 * frame);
+                    app.world
+                        .resource_mut::<Time>()
+                        .update_with_instant(instant);
+                    app.update();
+                    if app.world.get::<FallingDisk>(disk).is_none() {
+                        break;
                     }
                 }
-
-                // Add history
-                for (i, (col, disk)) in move_history.moves.iter().rev().enumerate() {
-                    commands
-                        .spawn((
-                            Move,
-                            NodeBundle {
-                                style: Style {
-                                    size: Size::new(
-                                        Val::Percent(10.0),
-                                        Val::Percent((1.0 - BOARD_SCALE.y) * 100.0),
-                                    ),
-                                    position_type: PositionType::Absolute,
-                                    position: UiRect {
-                                        left: Val::Percent(10.0 * i as f32),
-                                        bottom: Val::Percent(0.0),
-                                        ..default()
-                                    },
-                                    justify_content: JustifyContent::Center,
-                                    align_items: AlignItems::Center,
-                                    align_content: AlignContent::Center,
-                                    ..default()
-                                },
-                                ..default()
-                            },
-                            InGame,
-                        ))
-                        .with_children(|parent| {
-                            parent.spawn(TextBundle {
-                                text: Text::from_section(
-                                    format!("{}", col + 1),
-                                    TextStyle {
-                                        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                                        font_size: 40.0,
-                                        color: disk.to_color(),
-                                    },
-                                ),
-                                style: Style {
-                                    align_content: AlignContent::Center,
-                                    align_items: AlignItems::Center,
-                                    justify_content: JustifyContent::Center,
-                                    ..default()
-                                },
-                                ..default()
-                            });
-                        });
-                }
+                assert!(
+                    app.world.get::<FallingDisk>(disk).is_none(),
+                    "gravity {}",
+                    gravity
+                );
+                assert_eq!(
+                    app.world.get::<Transform>(disk).unwrap().translation,
+                    target
+                );
             }
         }
     }
 
-    game_change_events.clear();
-}
+    #[test]
+    fn live_three_glows_over_its_open_end() {
+        let red = Turn::FIRST.to_disk();
+        // Blue blocks the left end of Red's three, so only the right end is a threat
+        let board = Board::from_ascii(
+            "......
+             ......
+             B.....
+             BRRR..",
+        )
+        .unwrap();
+        assert_eq!(board.open_three_columns(red), [4]);
+        assert_eq!(board.open_three_columns(Disk(1)), Vec::<i32>::new());
 
-// Check for player pressing escape to go back to the main menu
-fn check_for_pause(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut game_state: ResMut<State<GameState>>,
-    mut main_menu_info: ResMut<MainMenuInfo>,
-) {
-    if keyboard_input.just_pressed(KeyCode::Escape) {
-        main_menu_info.allow_resume = true;
-        main_menu_info.winner = None;
-        game_state.set(GameState::Menu).unwrap();
+        // Open on both ends, it can be completed in either column
+        let board = Board::from_ascii(
+            "......
+             ......
+             B.....
+             B.RRR.",
+        )
+        .unwrap();
+        assert_eq!(board.open_three_columns(red), [1, 5]);
     }
-}
 
-// Convert the mouse position to the column in the board
-fn mouse_pos_to_col(mouse_pos: Vec2, board: &Board) -> Option<i32> {
-    if mouse_pos.x < WINDOW_WIDTH * BOARD_SCALE.x {
-        let col = mouse_pos.x / (WINDOW_WIDTH * BOARD_SCALE.x / board.cols as f32);
-        Some(col.floor() as i32)
-    } else {
-        None
+    #[test]
+    fn threat_glow_follows_the_board() {
+        let mut app = App::new();
+        app.insert_resource(Board::new(4, 6, 2))
+            .insert_resource(InputLocked(false))
+            .insert_resource(GameMode::HotSeat)
+            .insert_resource(Turn::FIRST)
+            .insert_resource(ThreatGlow::Own)
+            .insert_resource(ViewRotation::default())
+            .insert_resource(BoardLayout::default())
+            .add_system(update_column_buttons);
+        let buttons: Vec<Entity> = (0..6)
+            .map(|col| {
+                app.world
+                    .spawn((
+                        ColumnButton { col },
+                        Interaction::None,
+                        BackgroundColor(COLUMN_BUTTON_COLOR),
+                        Style::default(),
+                        Visibility::default(),
+                    ))
+                    .id()
+            })
+            .collect();
+        let colors = |app: &App| -> Vec<Color> {
+            buttons
+                .iter()
+                .map(|&button| app.world.get::<BackgroundColor>(button).unwrap().0)
+                .collect()
+        };
+        app.update();
+        assert_eq!(colors(&app), [COLUMN_BUTTON_COLOR; 6]);
+
+        *app.world.resource_mut::<Board>() = Board::from_ascii(
+            "......
+             ......
+             B.....
+             BRRR..",
+        )
+        .unwrap();
+        app.update();
+        assert_eq!(colors(&app)[4], COLUMN_BUTTON_OWN_THREAT_COLOR);
+
+        // Nothing changed, the glow stays where it was
+        app.update();
+        assert_eq!(colors(&app)[4], COLUMN_BUTTON_OWN_THREAT_COLOR);
+
+        // The threat is blocked, so nothing glows anymore
+        app.world.resource_mut::<Board>().drop_disk(4, Disk(1));
+        app.update();
+        assert_eq!(colors(&app), [COLUMN_BUTTON_COLOR; 6]);
     }
-}
 
-// Add a disk to the board and screen
-fn drop_disk(
-    mut commands: Commands,
-    mesh_handles: &MeshHandles,
-    material_handles: &MaterialHandles,
-    board: &mut Board,
-    turn: &mut Turn,
-    history: &mut MoveHistory,
-    mut query: Query<&mut Style, With<Move>>,
-    asset_server: Res<AssetServer>,
-    col: i32,
-) {
-    if let Some(row) = board.drop_disk(col, turn.to_disk()) {
-        draw_disk(
-            &mut commands,
-            mesh_handles,
-            material_handles,
-            &get_dimensions(board, DISK_PADDING),
-            col,
-            row,
-            turn.to_disk(),
-        );
+    #[test]
+    fn x_to_col_keeps_to_the_board() {
+        assert_eq!(x_to_col(0.0, 700.0, 7), Some(0));
+        assert_eq!(x_to_col(699.9, 700.0, 7), Some(6));
+        assert_eq!(x_to_col(700.0, 700.0, 7), None);
+        assert_eq!(x_to_col(-0.1, 700.0, 7), None);
+    }
 
-        // Add to history
-        history.moves.push((col, *turn));
+    #[test]
+    fn clicks_below_the_board_only_count_anywhere() {
+        let board = Board::new(6, 7, 2);
+        let layout = BoardLayout::default();
+        let dims = get_dimensions(&board, 0.0, layout);
+        let origin = dims.window_origin();
+        let below = Vec2::new(
+            origin.x + dims.col_width / 2.0,
+            origin.y - dims.row_height - dims.height - 10.0,
+        );
+        let col = |region| mouse_pos_to_col(below, &board, region, ViewRotation::Upright, layout);
+        assert_eq!(col(DropInputRegion::Anywhere), Some(0));
+        assert_eq!(col(DropInputRegion::Board), None);
+    }
 
-        // Shift all other history moves to the right
-        for mut style in &mut query {
-            style.position.left = style.position.left.try_add(Val::Percent(10.0)).unwrap();
-        }
+    #[test]
+    fn half_turned_view_inverts_the_columns() {
+        let board = Board::new(6, 7, 2);
+        let layout = BoardLayout::default();
+        let dims = get_dimensions(&board, 0.0, layout);
+        let origin = dims.window_origin();
+        // In the middle of the first column, a row down into the board
+        let mouse_pos = Vec2::new(
+            origin.x + dims.col_width / 2.0,
+            origin.y - dims.row_height * 1.5,
+        );
+        let col = |view_rotation| {
+            mouse_pos_to_col(
+                mouse_pos,
+                &board,
+                DropInputRegion::Board,
+                view_rotation,
+                layout,
+            )
+        };
+        assert_eq!(col(ViewRotation::Upright), Some(0));
+        assert_eq!(col(ViewRotation::HalfTurn), Some(6));
+    }
 
-        commands
-            .spawn((
-                Move,
-                NodeBundle {
-                    style: Style {
-                        size: Size::new(
-                            Val::Percent(10.0),
-                            Val::Percent((1.0 - BOARD_SCALE.y) * 100.0),
-                        ),
-                        position_type: PositionType::Absolute,
-                        position: UiRect {
-                            left: Val::Percent(0.0),
-                            bottom: Val::Percent(0.0),
-                            ..default()
-                        },
-                        justify_content: JustifyContent::Center,
-                        align_items: AlignItems::Center,
-                        align_content: AlignContent::Center,
-                        ..default()
-                    },
-                    ..default()
-                },
-                InGame,
-            ))
-            .with_children(|parent| {
-                parent.spawn(TextBundle {
-                    text: Text::from_section(
-                        format!("{}", col + 1),
-                        TextStyle {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            font_size: 40.0,
-                            color: turn.to_color(),
-                        },
-                    ),
-                    style: Style {
-                        align_content: AlignContent::Center,
-                        align_items: AlignItems::Center,
-                        justify_content: JustifyContent::Center,
-                        ..default()
-                    },
-                    ..default()
-                });
-            });
+    #[test]
+    fn board_size_of_a_new_game_is_valid() {
+        assert!(game_data(Board::new(6, 7, 2), Turn::FIRST).validate_board_size());
+    }
 
-        turn.next();
+    #[test]
+    fn board_size_without_rows_is_invalid() {
+        let mut board = Board::new(6, 7, 2);
+        board.rows = 0;
+        board.disks = Disks::new(0, 7);
+        assert!(!game_data(board, Turn::FIRST).validate_board_size());
     }
-}
 
-// Checking for placing a disk
-fn check_for_click(
-    commands: Commands,
-    windows: Res<Windows>,
-    buttons: Res<Input<MouseButton>>,
-    mut board: ResMut<Board>,
-    mut turn: ResMut<Turn>,
-    mut history: ResMut<MoveHistory>,
-    mut skip_click: ResMut<SkipClick>,
-    mesh_handles: Res<MeshHandles>,
-    material_handles: Res<MaterialHandles>,
-    query: Query<&mut Style, With<Move>>,
-    asset_server: Res<AssetServer>,
-) {
-    if buttons.just_pressed(MouseButton::Left) {
-        if skip_click.0 {
-            skip_click.0 = false;
-            return;
-        }
-        if let Some(window) = windows.get_primary() {
-            let position = window.cursor_position();
-            if let Some(position) = position {
-                if let Some(col) = mouse_pos_to_col(position, &board) {
-                    drop_disk(
-                        commands,
-                        &mesh_handles,
-                        &material_handles,
-                        &mut board,
-                        &mut turn,
-                        &mut history,
-                        query,
-                        asset_server,
-                        col,
-                    );
-                }
-            }
+    #[test]
+    fn negative_board_sizes_are_invalid() {
+        for (rows, cols) in [(-6, 7), (6, -7)] {
+            let mut board = Board::new(6, 7, 2);
+            board.rows = rows;
+            board.cols = cols;
+            assert!(!game_data(board, Turn::FIRST).validate_board_size());
         }
     }
-}
 
-// Used to display the ghost disks in the correct place
-fn check_for_mouse_movement(
-    windows: Res<Windows>,
-    board: Res<Board>,
-    turn: Res<Turn>,
-    mut ghost_disk_query: Query<(&mut Transform, &mut Visibility, &GhostDisk)>,
-) {
-    if let Some(mouse_pos) = windows.get_primary().unwrap().cursor_position() {
-        for (mut ghost_disk_transform, mut ghost_disk_visibility, &ghost_disk_type) in
-            &mut ghost_disk_query
-        {
-            if ghost_disk_type == turn.to_ghost_disk() {
-                if let Some(col) = mouse_pos_to_col(mouse_pos, &board) {
-                    let Dimensions {
-                        row_height,
-                        col_width,
-                        ..
-                    } = get_dimensions(&board, 0.0);
-
-                    // Set correct ghost disk to visible and the right loaction
-                    ghost_disk_visibility.is_visible = true;
-                    ghost_disk_transform.translation = Vec3::new(
-                        -WINDOW_WIDTH / 2.0 + (col_width * col as f32 + col_width / 2.0),
-                        WINDOW_HEIGHT / 2.0 - row_height / 2.0,
-                        0.0,
-                    );
-                    continue;
-                }
-            }
+    #[test]
+    fn players_the_game_cant_play_are_invalid() {
+        for players in [0, 1, MAX_PLAYERS + 1] {
+            let board = Board::new(6, 7, players);
+            assert!(!game_data(board, Turn::FIRST).validate_board_size());
+        }
 
-            // Set all other ghost disks to invisible
-            ghost_disk_visibility.is_visible = false;
+        let mut board = Board::new(6, 7, 3);
+        board.win_lengths = vec![4, 4];
+        assert!(!game_data(board, Turn::FIRST).validate_board_size());
+    }
+
+    // Number of disks of each player on the board
+    fn disk_counts(board: &Board) -> [usize; MAX_PLAYERS as usize] {
+        let mut counts = [0; MAX_PLAYERS as usize];
+        for disk in board.disks.iter().flatten().flatten() {
+            counts[disk.0 as usize] += 1;
         }
+        counts
     }
-}
 
-// Checking for a win every frame
-fn check_for_wins(
-    mut commands: Commands,
-    board: Res<Board>,
-    mut game_state: ResMut<State<GameState>>,
-    mut ghost_disks: Query<&mut Visibility, With<GhostDisk>>,
-    mut main_menu_info: ResMut<MainMenuInfo>,
-) {
-    if let Some((winner, from, to)) = board.check_for_wins() {
-        let dims = get_dimensions(&board, 0.0);
+    #[test]
+    fn gravity_flip_keeps_the_disks() {
+        let mut board = Board::from_ascii(
+            ".....
+             B....
+             R.#B.
+             RBRRB",
+        )
+        .unwrap();
+        let counts = disk_counts(&board);
+        board.apply_gravity_flip();
+        assert_eq!(disk_counts(&board), counts);
+    }
 
-        // Drawing the win line
-        let mut from = get_disk_transform(&dims, from.0, from.1).translation;
-        from.z = 0.4;
+    #[test]
+    fn gravity_flip_can_create_a_win() {
+        let mut board = Board::from_ascii(
+            "....
+             ....
+             R.R.
+             BRBR",
+        )
+        .unwrap();
+        assert!(board.check_for_wins().is_none());
+        board.apply_gravity_flip();
+        assert_eq!(board.to_ascii(), "....\n....\nB.B.\nRRRR\n");
+        assert!(matches!(board.check_for_wins(), Some((Turn(0), _, _))));
+    }
 
-        let mut to = get_disk_transform(&dims, to.0, to.1).translation;
-        to.z = 0.4;
+    #[test]
+    fn gravity_flip_rows_follow_the_flip() {
+        let board = Board::from_ascii(
+            ".....
+             B....
+             R.#B.
+             RBRRB",
+        )
+        .unwrap();
+        let mut flipped = board.clone();
+        flipped.apply_gravity_flip();
+        for (col, from, to) in board.gravity_flip_rows() {
+            let (col, from, to) = (col as usize, from as usize, to as usize);
+            assert!(board.disks[col][from] == flipped.disks[col][to]);
+        }
+    }
 
-        // Winning line
-        commands.spawn((
-            SpriteBundle {
-                transform: Transform {
-                    translation: from + (to - from) / 2.0,
-                    scale: Vec3::new(
-                        (to - from).length()
-                            + (dims.col_width.powf(2.0) + dims.row_height.powf(2.0)).sqrt() / 2.5,
-                        (dims.col_width * WINNER_LINE_HEIGHT)
-                            .min(dims.row_height * WINNER_LINE_HEIGHT),
-                        0.0,
-                    ),
-                    rotation: Quat::from_rotation_z(
-                        (from - to).angle_between(Vec3::new(1.0, 0.0, 0.0)),
-                    ),
-                },
-                sprite: Sprite {
-                    color: WINNER_COLOR,
-                    ..default()
-                },
-                ..default()
-            },
-            InGame,
-        ));
+    #[test]
+    fn turn_parity_of_a_consistent_save() {
+        let board = Board::from_ascii(
+            "....
+             ....
+             ....
+             RRB.",
+        )
+        .unwrap();
+        let data = game_data(board, Turn(1));
+        assert!(data.validate_turn_parity());
+        assert!(data.playable());
+    }
 
-        // Hide all ghost disks
-        for mut ghost_disk_visibility in &mut ghost_disks {
-            ghost_disk_visibility.is_visible = false;
-        }
+    #[test]
+    fn turn_parity_off_by_one() {
+        // Blue spent their turn flipping the board, so it's Blue's turn again and not Red's
+        let board = Board::from_ascii(
+            "....
+             ....
+             ....
+             RR..",
+        )
+        .unwrap();
+        let data = game_data(board, Turn::FIRST);
+        assert!(!data.validate_turn_parity());
+        assert!(data.playable());
+        assert!(data.fitting_turns().eq([Turn(1)]));
+    }
 
-        // Send to game menu with a winner
-        main_menu_info.allow_resume = false;
-        main_menu_info.winner = Some(winner);
-        game_state.set(GameState::Menu).unwrap();
+    #[test]
+    fn turn_parity_of_impossible_disk_counts() {
+        let board = Board::from_ascii(
+            "....
+             ....
+             ....
+             RRR.",
+        )
+        .unwrap();
+        for turn in [Turn::FIRST, Turn(1)] {
+            let data = game_data(board.clone(), turn);
+            assert!(!data.validate_turn_parity());
+            assert!(!data.playable());
+        }
     }
 }
@@ -0,0 +1,130 @@
+use crate::{
+    ai::best_move,
+    game::{Board, Disk, Turn},
+};
+
+// Default search depth of the built-in AI in headless matches
+const DEFAULT_DEPTH: u32 = 4;
+
+// Anything that can pick moves in a headless match (the built-in AI, or custom bots)
+pub trait Player {
+    // The column to drop the disk into
+    fn choose(&mut self, board: &Board, disk: Disk) -> i32;
+}
+
+// The built-in negamax AI, looking depth moves ahead
+pub struct AiPlayer {
+    pub depth: u32,
+}
+
+impl Player for AiPlayer {
+    fn choose(&mut self, board: &Board, disk: Disk) -> i32 {
+        // Only called while there are legal moves, so there is always a best one
+        best_move(board, disk, self.depth).unwrap_or(0)
+    }
+}
+
+// How a headless match ended
+pub struct MatchResult {
+    // None for a draw
+    pub winner: Option<Turn>,
+    // Columns played, in order
+    pub moves: Vec<i32>,
+    pub move_count: usize,
+}
+
+// Plays a full two player game without a window, red moves first.
+// A player choosing a column that is full (or not on the board) loses right away.
+pub fn run_match(
+    mut red: impl Player,
+    mut blue: impl Player,
+    (rows, cols): (i32, i32),
+) -> MatchResult {
+    let mut board = Board::new(rows, cols, 2);
+    let mut turn = Turn::FIRST;
+    let mut moves = Vec::new();
+
+    let winner = loop {
+        if board.legal_moves().is_empty() {
+            break None;
+        }
+
+        let player: &mut dyn Player = if turn == Turn::FIRST {
+            &mut red
+        } else {
+            &mut blue
+        };
+        let col = player.choose(&board, turn.to_disk());
+
+        if !board.legal_moves().contains(&col) {
+            turn.next(board.players);
+            break Some(turn);
+        }
+        board.drop_disk(col, turn.to_disk());
+        moves.push(col);
+
+        if let Some((winner, _, _)) = board.check_for_wins() {
+            break Some(winner);
+        }
+        turn.next(board.players);
+    };
+
+    MatchResult {
+        winner,
+        move_count: moves.len(),
+        moves,
+    }
+}
+
+// Runs a match between two built-in AIs from the command line and prints the result,
+// the arguments are the depths of the red and blue AI
+pub fn run_from_args(args: &[String]) {
+    let depth = |index: usize| {
+        args.get(index)
+            .and_then(|arg| arg.parse().ok())
+            .unwrap_or(DEFAULT_DEPTH)
+    };
+
+    let result = run_match(
+        AiPlayer { depth: depth(0) },
+        AiPlayer { depth: depth(1) },
+        (6, 7),
+    );
+
+    match result.winner {
+        Some(winner) => println!("{} wins in {} moves", winner, result.move_count),
+        None => println!("Draw after {} moves", result.move_count),
+    }
+    let moves: Vec<String> = result
+        .moves
+        .iter()
+        .map(|col| (col + 1).to_string())
+        .collect();
+    println!("Moves: {}", moves.join(" "));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Always drops into the leftmost column that isn't full
+    struct LeftmostPlayer;
+
+    impl Player for LeftmostPlayer {
+        fn choose(&mut self, board: &Board, _disk: Disk) -> i32 {
+            board.legal_moves().into_iter().min().unwrap()
+        }
+    }
+
+    #[test]
+    fn leftmost_players_play_to_the_end() {
+        // The columns fill up one after the other with rows of the same color,
+        // so red completes the bottom row when it starts the fourth column
+        let result = run_match(LeftmostPlayer, LeftmostPlayer, (6, 7));
+        assert!(result.winner == Some(Turn::FIRST));
+        assert_eq!(result.move_count, 19);
+        let mut moves = [[0; 6], [1; 6], [2; 6]].concat();
+        moves.push(3);
+        assert_eq!(result.moves, moves);
+    }
+}
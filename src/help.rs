@@ -0,0 +1,162 @@
+use bevy::prelude::*;
+
+use crate::{theme::Theme, FONT_PATH};
+
+const TINT: Color = Color::rgba(0.0, 0.0, 0.0, 0.85);
+
+// Keys that show or hide the help overlay (Slash is the ? key)
+pub const HELP_KEYS: [KeyCode; 2] = [KeyCode::F1, KeyCode::Slash];
+
+// Flips the board over, once per game for each player
+pub const GRAVITY_FLIP_KEY: KeyCode = KeyCode::G;
+
+// Prints the board as text to the console, for bug reports
+pub const PRINT_BOARD_KEY: KeyCode = KeyCode::P;
+
+// Takes back the last move, as often as the undo limit in the settings allows
+pub const UNDO_KEY: KeyCode = KeyCode::U;
+
+// Starts the game over (asking again first if it is in progress)
+pub const RESTART_KEY: KeyCode = KeyCode::R;
+
+// Turns the view of the board around, for a player sitting on the other side of the screen
+pub const VIEW_ROTATION_KEY: KeyCode = KeyCode::V;
+
+// Shows or hides the analysis, the threats each move would create above its column
+pub const ANALYSIS_KEY: KeyCode = KeyCode::A;
+
+// Grows the board by a row and a column in the middle of the game (Equals is the + key)
+pub const GROW_BOARD_KEY: KeyCode = KeyCode::Equals;
+
+// Drops into a random column for the player whose turn it is
+pub const RANDOM_MOVE_KEY: KeyCode = KeyCode::D;
+
+// Saves a picture of the board as an SVG file
+pub const EXPORT_SVG_KEY: KeyCode = KeyCode::S;
+
+// Suggests a move for the player whose turn it is (or warns that the position is lost)
+pub const HINT_KEY: KeyCode = KeyCode::H;
+
+// Keys answering the offer to use the appearance a loaded save was made with
+pub const APPLY_APPEARANCE_KEY: KeyCode = KeyCode::Y;
+pub const KEEP_APPEARANCE_KEY: KeyCode = KeyCode::N;
+
+// Keys answering whether a save dropped onto the window during a game in progress replaces it
+pub const LOAD_DROPPED_KEY: KeyCode = KeyCode::L;
+pub const KEEP_GAME_KEY: KeyCode = KeyCode::K;
+
+// Step through the positions of a game under review, or jump to its first or last one
+pub const REVIEW_BACK_KEY: KeyCode = KeyCode::Left;
+pub const REVIEW_FORWARD_KEY: KeyCode = KeyCode::Right;
+pub const REVIEW_FIRST_KEY: KeyCode = KeyCode::Home;
+pub const REVIEW_LAST_KEY: KeyCode = KeyCode::End;
+
+// All the hotkeys and what they do, listed in the help overlay
+const HOTKEYS: &[(KeyCode, &str)] = &[
+    (KeyCode::F1, "Show or hide this help"),
+    (KeyCode::Escape, "Pause or resume the game"),
+    (GRAVITY_FLIP_KEY, "Flip the board over (once per game)"),
+    (PRINT_BOARD_KEY, "Print the board as text (for bug reports)"),
+    (EXPORT_SVG_KEY, "Save a picture of the board (board.svg)"),
+    (VIEW_ROTATION_KEY, "Turn the board around"),
+    (UNDO_KEY, "Take back the last move"),
+    (RESTART_KEY, "Restart the game"),
+    (GROW_BOARD_KEY, "Grow the board by a row and a column"),
+    (ANALYSIS_KEY, "Show or hide the threats of each move"),
+    (RANDOM_MOVE_KEY, "Drop in a random column"),
+    (HINT_KEY, "Suggest a move"),
+    (APPLY_APPEARANCE_KEY, "Use the look a loaded save was made with"),
+    (KEEP_APPEARANCE_KEY, "Keep the current look for a loaded save"),
+    (LOAD_DROPPED_KEY, "Load a save dropped onto the window"),
+    (KEEP_GAME_KEY, "Keep playing instead of loading a dropped save"),
+    (REVIEW_BACK_KEY, "Review: step back a move"),
+    (REVIEW_FORWARD_KEY, "Review: step forward a move"),
+    (REVIEW_FIRST_KEY, "Review: jump to the start"),
+    (REVIEW_LAST_KEY, "Review: jump to the end"),
+];
+
+// Used to identify the help overlay, so it can be removed again
+#[derive(Component)]
+struct HelpOverlay;
+
+pub struct HelpPlugin;
+
+// The overlay can be toggled at any time, in the game and in the menu
+impl Plugin for HelpPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(toggle_help);
+    }
+}
+
+// Name of a key as shown in the help overlay
+fn key_name(key: KeyCode) -> String {
+    match key {
+        KeyCode::Slash => "?".to_string(),
+        KeyCode::Escape => "Esc".to_string(),
+        _ => format!("{:?}", key),
+    }
+}
+
+// Shows the help overlay when one of the help keys is pressed, or hides it if it was already shown
+fn toggle_help(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    theme: Res<Theme>,
+    query: Query<Entity, With<HelpOverlay>>,
+) {
+    if !keyboard_input.any_just_pressed(HELP_KEYS) {
+        return;
+    }
+
+    if !query.is_empty() {
+        for entity in &query {
+            commands.entity(entity).despawn_recursive();
+        }
+        return;
+    }
+
+    let font = asset_server.load(FONT_PATH);
+    let text_style = TextStyle {
+        font: font.clone(),
+        // Small enough for the whole list to fit in the window
+        font_size: 24.0,
+        color: theme.colors().text,
+    };
+
+    // Tinted background over everything else (the board and the menu), with the list centered
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    position_type: PositionType::Absolute,
+                    align_items: AlignItems::Center,
+                    justify_content: JustifyContent::Center,
+                    flex_direction: FlexDirection::Column,
+                    ..default()
+                },
+                background_color: TINT.into(),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            HelpOverlay,
+        ))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "Hotkeys",
+                TextStyle {
+                    font,
+                    font_size: 50.0,
+                    color: theme.colors().title,
+                },
+            ));
+
+            for &(key, description) in HOTKEYS {
+                parent.spawn(TextBundle::from_section(
+                    format!("{}: {}", key_name(key), description),
+                    text_style.clone(),
+                ));
+            }
+        });
+}
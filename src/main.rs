@@ -1,33 +1,110 @@
-use bevy::prelude::*;
+use bevy::{asset::LoadState, prelude::*};
 
+// Saves only have somewhere else to go than files in the browser
+#[cfg(all(not(feature = "native-save"), not(target_arch = "wasm32")))]
+compile_error!("native builds need the native-save feature");
+
+mod accessibility;
+mod ai;
+mod book;
+mod event_log;
 mod game;
+mod headless;
+mod help;
 mod menu;
+mod puzzles;
+mod replay;
+mod settings;
+mod stats;
+mod theme;
 
-use game::Turn;
+use game::{DropPattern, Turn, WinCondition, WinDirections};
 
-const BACKGROUND_COLOR: Color = Color::rgb(0.0, 0.0, 0.0);
 const FPS: f32 = 60.0;
 pub const TIME_STEP: f32 = 1.0 / FPS;
 
 pub const WINDOW_WIDTH: f32 = 800.0;
 pub const WINDOW_HEIGHT: f32 = 800.0;
 
+// Font all the text is written in, from the assets folder
+const FONT_PATH: &str = "fonts/FiraSans-Bold.ttf";
+// The same font built into the app, for when the assets folder can't be found
+const EMBEDDED_FONT: &[u8] = include_bytes!("../assets/fonts/FiraSans-Bold.ttf");
+// How long the loading screen waits for the assets before showing the menu anyway (in seconds)
+const LOADING_TIMEOUT: f32 = 5.0;
+
 // Used by main menu and game to determine if the game can be resumed or saved, and if there is a winner
+// (with an optional message shown under it, e.g. for puzzles), and if they won a whole match
 #[derive(Resource)]
 struct MainMenuInfo {
     pub allow_resume: bool,
     pub winner: Option<Turn>,
+    pub message: Option<String>,
+    pub won_match: bool,
 }
 
 // Event type used to communicate between the main menu and game
 pub enum GameChange {
-    New { rows: i32, cols: i32 },
+    // With a seed, some cells are blocked by rocks picked by it
+    New {
+        rows: i32,
+        cols: i32,
+        players: u8,
+        rocks: Option<u64>,
+        drop_pattern: DropPattern,
+        win_condition: WinCondition,
+        win_directions: WinDirections,
+        // Disks in a line each player needs to win, in turn order (empty if all need the usual)
+        win_lengths: Vec<i32>,
+        // Play a match of this many games instead of a single one
+        best_of: Option<u32>,
+    },
     Save,
     Load,
+    // Load a save file from anywhere, like one dropped onto the window
+    LoadFile {
+        path: String,
+    },
+    // Load the game saved automatically when the app was last closed
+    Continue,
+    Resign,
+    // End the game without a winner
+    Draw,
+    Puzzle,
+    Demo {
+        rows: i32,
+        cols: i32,
+        depth: u32,
+    },
+    VsAi {
+        rows: i32,
+        cols: i32,
+        depth: u32,
+    },
+    // Play against the moves the player made in their last game against the AI
+    MirrorHuman {
+        rows: i32,
+        cols: i32,
+    },
+    // Write the current game as PNG frames into the directory, one per move
+    ExportReplay {
+        dir: String,
+    },
+    // Add a row and a column to the board of the current game
+    GrowBoard,
 }
 
 // Setup the bevy app, adding the main menu and game plugins
+// (or with `--match [red depth] [blue depth]`, just play the AIs against each other and print the result).
+// With `--announce`, what happens in the game is also printed for assistive tools (see accessibility).
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("--match") {
+        headless::run_from_args(&args[2..]);
+        return;
+    }
+    let announce = args.iter().any(|arg| arg == "--announce");
+
     App::new()
         .add_event::<GameChange>()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
@@ -36,30 +113,140 @@ fn main() {
                 height: WINDOW_HEIGHT,
                 title: "Connect 4".to_string(),
                 resizable: false,
+                position: settings::saved_window_position(),
+                monitor: MonitorSelection::Primary,
                 ..default()
             },
             ..default()
         }))
-        .insert_resource(ClearColor(BACKGROUND_COLOR))
         .insert_resource(MainMenuInfo {
             allow_resume: false,
             winner: None,
+            message: None,
+            won_match: false,
         })
+        .insert_resource(accessibility::AccessibilityLog::new(announce))
         .add_startup_system(setup)
-        .add_state(GameState::Menu)
+        .add_system(check_font)
+        .add_state(GameState::Loading)
+        .add_system_set(SystemSet::on_enter(GameState::Loading).with_system(setup_loading))
+        .add_system_set(SystemSet::on_update(GameState::Loading).with_system(update_loading))
+        .add_system_set(SystemSet::on_exit(GameState::Loading).with_system(cleanup_loading))
+        .add_system(event_log::log_state_changes)
+        .add_plugin(settings::SettingsPlugin)
         .add_plugin(menu::MenuPlugin)
         .add_plugin(game::GamePlugin)
+        .add_plugin(help::HelpPlugin)
         .run();
 }
 
 // Used to determine which plugin to run (game or main menu)
 #[derive(Clone, Copy, Eq, PartialEq, Debug, Hash)]
 pub enum GameState {
+    // Waiting for the assets when the app starts, so the menu isn't drawn without its text
+    Loading,
     Playing,
+    // Quick pause, with a small panel over the game instead of the whole menu
+    Paused,
+    // Counting down before a game against or between AIs starts, the board shown but locked
+    Countdown,
+    // Stepping through the positions of a finished game
+    Review,
+    // Setting up a position by hand, cell by cell
+    Editor,
     Menu,
 }
 
+// Used to identify the message shown while loading
+#[derive(Component)]
+struct LoadingText;
+
 // Setup the camera
 fn setup(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
 }
+
+// Shows the loading message in the built-in font, since the other one is what is being waited for
+fn setup_loading(mut commands: Commands, mut fonts: ResMut<Assets<Font>>) {
+    let font = match Font::try_from_bytes(EMBEDDED_FONT.to_vec()) {
+        Ok(font) => fonts.add(font),
+        Err(err) => {
+            warn!("Failed to read the built-in font: {:?}", err);
+            return;
+        }
+    };
+    commands.spawn((
+        TextBundle::from_section(
+            "Loading...",
+            TextStyle {
+                font,
+                font_size: 50.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            margin: UiRect::all(Val::Auto),
+            ..default()
+        }),
+        LoadingText,
+    ));
+}
+
+// Goes to the menu once the font is loaded. If it failed (check_font puts the built-in one in its
+// place) or takes too long, the menu is shown anyway.
+fn update_loading(
+    asset_server: Res<AssetServer>,
+    time: Res<Time>,
+    mut game_state: ResMut<State<GameState>>,
+    mut waited: Local<f32>,
+) {
+    let font: Handle<Font> = asset_server.load(FONT_PATH);
+    *waited += time.delta_seconds();
+    let done = match asset_server.get_load_state(&font) {
+        LoadState::Loaded | LoadState::Failed => true,
+        _ if *waited >= LOADING_TIMEOUT => {
+            warn!(
+                "{} didn't load within {} seconds, showing the menu without waiting",
+                FONT_PATH, LOADING_TIMEOUT
+            );
+            true
+        }
+        _ => false,
+    };
+    if done {
+        game_state.set(GameState::Menu).unwrap();
+    }
+}
+
+// Removes the loading message
+fn cleanup_loading(mut commands: Commands, query: Query<Entity, With<LoadingText>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Puts the built-in font in place of the one in the assets folder if that one fails to load
+// (like when the app was moved without its assets), so all the text can still be read
+fn check_font(
+    asset_server: Res<AssetServer>,
+    mut fonts: ResMut<Assets<Font>>,
+    mut font: Local<Option<Handle<Font>>>,
+    mut checked: Local<bool>,
+) {
+    if *checked {
+        return;
+    }
+    let font = font.get_or_insert_with(|| asset_server.load(FONT_PATH));
+    match asset_server.get_load_state(&*font) {
+        LoadState::Loaded => *checked = true,
+        LoadState::Failed => {
+            warn!("Failed to load {}, using the built-in font instead", FONT_PATH);
+            match Font::try_from_bytes(EMBEDDED_FONT.to_vec()) {
+                Ok(embedded) => fonts.set_untracked(font.id(), embedded),
+                Err(err) => warn!("Failed to read the built-in font: {:?}", err),
+            }
+            *checked = true;
+        }
+        _ => {}
+    }
+}
@@ -1,28 +1,184 @@
-use bevy::{app::AppExit, prelude::*};
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+use std::path::Path;
 
-use crate::{game::WINNER_COLOR, GameChange, GameState, MainMenuInfo, WINDOW_HEIGHT, WINDOW_WIDTH};
+use bevy::{app::AppExit, prelude::*, sprite::MaterialMesh2dBundle};
+
+use crate::{
+    game::{
+        autosave_available, board_preview, circle_mesh, column_label, Board, DropPattern,
+        WinCondition, WinDirections, DEFAULT_WIN_LENGTH, MAX_BOARD_SIZE, MAX_PLAYERS, PLAYER_NAMES,
+    },
+    settings::{
+        tick_animation, AiDelay, AiSearchTime, AnimationSpeed, AutoForced, Autosave, BoardLayout,
+        CircleVertices, ConfirmExit, Countdown, DemoDelay, DropInputRegion, FallGravity, FogOfWar,
+        GhostAlpha, HistoryDisplayMode, HistoryLimit, LossWarning, PassScreen, ReducedMotion,
+        RequireRelease, ShowAiIntent, ShowGhost, ShowMoveNumbers, ShowParticles, ShowTrajectory,
+        SimultaneousWinRule, SwapColors, ThreatGlow, UndoLimit, WinLineStyle, WinLineThickness,
+    },
+    stats::aggregate,
+    theme::Theme,
+    GameChange, GameState, MainMenuInfo, FONT_PATH, WINDOW_HEIGHT, WINDOW_WIDTH,
+};
 
-const NORMAL_BUTTON: Color = Color::rgb(0.15, 0.15, 0.15);
-const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
-const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
-const TITLE_COLOR: Color = Color::WHITE;
-const FONT_COLOR: Color = Color::WHITE;
 const TINT: Color = Color::rgba(0.0, 0.0, 0.0, 0.7);
+// Background of the quick pause panel, which leaves the game visible around it
+const PAUSE_PANEL_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.5);
+// How long the tint takes to fade in and out when entering and leaving the menu (in seconds)
+const FADE_TIME: f32 = 0.2;
+
+// How many moves ahead the AIs in the demo look
+const DEMO_AI_DEPTH: u32 = 4;
+// How many moves ahead the AI looks when playing against it
+const AI_DEPTH: u32 = 4;
+// Board size preview on the new game page, to the right of the buttons
+const PREVIEW_POSITION: Vec2 = Vec2::new(290.0, 0.0);
+const PREVIEW_WIDTH: f32 = 160.0;
+// Bigger boards show fewer holes than they have, so there are never too many entities
+const PREVIEW_MAX_CELLS: i32 = 12;
+
+// Choices for what wins a game, by the name shown for them:
+// lines in any direction, only diagonal or only horizontal and vertical ones, or squares
+const WIN_RULES: [(&str, WinCondition, WinDirections); 4] = [
+    ("Line", WinCondition::Line, WinDirections::ALL),
+    ("Diagonal", WinCondition::Line, WinDirections::DIAGONAL),
+    ("Straight", WinCondition::Line, WinDirections::STRAIGHT),
+    ("Square", WinCondition::Square, WinDirections::ALL),
+];
+
+// Typed board sizes that aren't allowed flash the size text in this color for a moment (in seconds)
+const SIZE_ERROR_COLOR: Color = Color::rgb(1.0, 0.2, 0.2);
+const SIZE_ERROR_FLASH: f32 = 0.5;
+
+// Choices for the number of disks in a line each player needs to win, for a handicap
+const WIN_LENGTHS: [i32; 3] = [3, 4, 5];
+// The win length buttons on the new game page, one per player, above the board size preview
+// (their top left corner, in pixels from the top left of the window)
+const WIN_LENGTHS_POSITION: Vec2 = Vec2::new(610.0, 100.0);
+
+// Choices for the number of games in a match, after a single game
+const MATCH_LENGTHS: [u32; 3] = [3, 5, 7];
+
+// Choices for the delay of the AI moves, in the demo and against a player (in seconds)
+const AI_DELAYS: [f32; 4] = [0.25, 0.5, 1.0, 2.0];
+
+// Choices for how long the AI searches for its reply (in seconds), after a fixed depth
+const AI_SEARCH_TIMES: [f32; 3] = [0.1, 0.25, 0.5];
+
+// Choices for the animation speed, followed by reduced motion (no animations) before wrapping around
+const ANIMATION_SPEEDS: [f32; 3] = [0.5, 1.0, 2.0];
+
+// Choices for how strongly dropped disks fall (in pixels per second squared), named,
+// from a slow floaty drop to one that is as good as instant
+const FALL_GRAVITIES: [(&str, f32); 4] = [
+    ("Floaty", 1500.0),
+    ("Normal", 6000.0),
+    ("Heavy", 24000.0),
+    ("Instant", 1.0e6),
+];
+
+// Choices for the number of undos in each game, from none to no limit at all
+const UNDO_LIMITS: [Option<u32>; 4] = [Some(0), Some(1), Some(3), None];
+
+// Choices for how many moves the history keeps, from all of them to only the latest few
+const HISTORY_LIMITS: [Option<usize>; 4] = [None, Some(500), Some(100), Some(20)];
+
+// Choices for how opaque the ghost disks are, from barely there to almost solid
+const GHOST_ALPHAS: [f32; 4] = [0.15, 0.3, 0.5, 0.7];
+
+// Choices for how many corners the circles of the disks have
+const CIRCLE_VERTICES: [usize; 4] = [16, 32, 64, 128];
+
+// Choices for how thick the winning line is, as a part of a cell
+const WIN_LINE_THICKNESSES: [f32; 4] = [0.2, 0.35, 0.5, 0.7];
+
+// Choices for the margins at the sides of the centered board (in pixels), after the classic layout
+const BOARD_SIDE_MARGINS: [f32; 2] = [40.0, 120.0];
+
+// Folder the replay frames are exported to
+const REPLAY_DIR: &str = "replay";
+
+// Folder of saved games the stats page adds up
+const SAVES_DIR: &str = "saves";
 
 // Used to label each button with a unique component
 #[derive(Component)]
 enum ButtonType {
+    Continue,
     Resume,
+    // Leaves the quick pause panel for the whole menu
+    Menu,
+    Resign,
+    Draw,
     NewGame,
+    Start,
+    Rocks,
     IncreaseRows,
     DecreaseRows,
     IncreaseCols,
     DecreaseCols,
+    Players,
+    // Cycles the number of disks in a line the player needs to win
+    WinLength(u8),
+    // Trades the colors of the first two players
+    SwapColors,
+    DropPattern,
+    WinCondition,
+    MatchLength,
+    Puzzle,
+    Demo,
+    VsAi,
+    MirrorHuman,
     Save,
+    ExportReplay,
     Load,
+    Editor,
+    Stats,
+    Review,
+    GrowBoard,
+    Settings,
+    ToggleGhost,
+    GhostAlpha,
+    CircleVertices,
+    ToggleParticles,
+    ToggleConfirmExit,
+    ToggleRequireRelease,
+    SimultaneousWinRule,
+    WinLineStyle,
+    WinLineThickness,
+    BoardLayout,
+    Motion,
+    FallGravity,
+    ToggleAutosave,
+    UndoLimit,
+    DropInputRegion,
+    HistoryDisplayMode,
+    HistoryLimit,
+    DemoDelay,
+    AiDelay,
+    AiSearchTime,
+    ToggleAiIntent,
+    ToggleMoveNumbers,
+    TogglePassScreen,
+    ToggleCountdown,
+    ToggleLossWarning,
+    ThreatGlow,
+    ToggleFogOfWar,
+    ToggleAutoForced,
+    Theme,
+    Back,
     Exit,
 }
 
+// Which page of the menu is shown, starting a game and the settings have their own pages
+#[derive(Resource, Clone, Copy, PartialEq, Eq)]
+enum MenuPage {
+    Main,
+    NewGame,
+    Settings,
+    // Totals over the games in the saves folder
+    Stats,
+}
+
 // Used to store the current board size that is displayed in the main menu
 #[derive(Resource)]
 struct BoardSize {
@@ -34,22 +190,148 @@ struct BoardSize {
 #[derive(Component)]
 struct BoardSizeText;
 
+// The board size being typed after clicking the size text, like "10x10" (None when not typing),
+// and how long the text still flashes after a size that isn't allowed was entered
+#[derive(Resource, Default)]
+struct SizeEntry {
+    typed: Option<String>,
+    error_flash: f32,
+}
+
+// Used to store the number of players for the next game, displayed in the main menu
+#[derive(Resource)]
+struct PlayerCount(u8);
+
+// To identify the text that displays the number of players
+#[derive(Component)]
+struct PlayerCountText;
+
+// Used to store which way the disks fall in the next game, displayed in the main menu
+#[derive(Resource)]
+struct ChosenDropPattern(DropPattern);
+
+// To identify the text that displays the drop pattern
+#[derive(Component)]
+struct DropPatternText;
+
+// Used to store what has to be formed to win the next game, displayed in the main menu
+#[derive(Resource)]
+struct ChosenWinCondition(WinCondition);
+
+// Used to store which directions winning lines can go in for the next game, shown with the win condition
+#[derive(Resource)]
+struct ChosenWinDirections(WinDirections);
+
+// To identify the text that displays the win condition
+#[derive(Component)]
+struct WinConditionText;
+
+// Used to store how many games the next match has (None for a single game), displayed in the main menu
+#[derive(Resource)]
+struct ChosenMatchLength(Option<u32>);
+
+// To identify the text that displays the match length
+#[derive(Component)]
+struct MatchLengthText;
+
+// Used to store how many disks in a line each player needs to win the next game, in turn order
+#[derive(Resource)]
+struct ChosenWinLengths([i32; MAX_PLAYERS as usize]);
+
+// To identify the text that displays the win length of the player
+#[derive(Component)]
+struct WinLengthText(u8);
+
+// Whether the game autosaved when the app was last closed can be continued,
+// only offered until the first game is started
+#[derive(Resource)]
+struct ContinueOffer(bool);
+
+// Set by the first click on exit when exiting needs to be confirmed, the next click on it exits.
+// Clicking anywhere else takes it back.
+#[derive(Resource)]
+struct ExitPending(bool);
+
+// To identify the text of the exit button, which asks for confirmation when an exit is pending
+#[derive(Component)]
+struct ExitText;
+
+// To identify the parts of the board size preview
+#[derive(Component)]
+struct SizePreview;
+
+// Explains why a menu option was blocked (like a board too small to win), shown under the board size
+#[derive(Resource, Default)]
+struct Notice(Option<String>);
+
+// To identify the text that displays the notice
+#[derive(Component)]
+struct NoticeText;
+
 // To identify all entities inside the menu, so they can be easily fetched and removed
 #[derive(Component)]
 struct InMenu;
 
+// To identify the tint behind the menu, which fades instead of being removed right away
+#[derive(Component)]
+struct MenuTint;
+
+// Fades the tint in (when entering the menu) or out (when leaving it, removing it at the end)
+#[derive(Component)]
+struct MenuFade {
+    timer: Timer,
+    fade_in: bool,
+}
+
 pub struct MenuPlugin;
 
 // Setup the main menu plugin, adding all the systems and resources (all only running when the state is GameState::Menu)
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(BoardSize { rows: 6, cols: 7 })
-            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(setup))
-            .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(cleanup))
+            .insert_resource(PlayerCount(2))
+            .insert_resource(ChosenDropPattern(DropPattern::Normal))
+            .insert_resource(ChosenWinCondition(WinCondition::Line))
+            .insert_resource(ChosenWinDirections(WinDirections::ALL))
+            .insert_resource(ChosenMatchLength(None))
+            .insert_resource(ChosenWinLengths([DEFAULT_WIN_LENGTH; MAX_PLAYERS as usize]))
+            .insert_resource(Notice::default())
+            .insert_resource(SizeEntry::default())
+            .insert_resource(MenuPage::Main)
+            .insert_resource(ContinueOffer(autosave_available()))
+            .insert_resource(ExitPending(false))
+            .add_system_set(
+                SystemSet::on_enter(GameState::Menu)
+                    .with_system(setup)
+                    .with_system(fade_in_tint),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Menu)
+                    .with_system(cleanup)
+                    .with_system(fade_out_tint),
+            )
+            .add_system(update_fade)
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(clear_continue_offer),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(setup_pause_panel))
+            .add_system_set(SystemSet::on_exit(GameState::Paused).with_system(cleanup))
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused)
+                    .with_system(button_system)
+                    .with_system(cancel_exit)
+                    .with_system(update_exit_text)
+                    .with_system(resume_on_escape),
+            )
             .add_system_set(
                 SystemSet::on_update(GameState::Menu)
                     .with_system(button_system)
-                    .with_system(update_text),
+                    .with_system(cancel_exit)
+                    .with_system(update_exit_text)
+                    .with_system(type_board_size)
+                    .with_system(update_text.after(type_board_size))
+                    .with_system(update_win_length_buttons)
+                    .with_system(update_size_preview),
             );
     }
 }
@@ -59,15 +341,68 @@ fn setup(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     main_menu_info: Res<MainMenuInfo>,
+    menu_page: Res<MenuPage>,
+    (show_ghost, show_trajectory, ghost_alpha, circle_vertices): (
+        Res<ShowGhost>,
+        Res<ShowTrajectory>,
+        Res<GhostAlpha>,
+        Res<CircleVertices>,
+    ),
+    (show_particles, pass_screen, loss_warning, swap_colors, threat_glow): (
+        Res<ShowParticles>,
+        Res<PassScreen>,
+        Res<LossWarning>,
+        Res<SwapColors>,
+        Res<ThreatGlow>,
+    ),
+    (demo_delay, ai_delay, show_ai_intent, countdown, ai_search_time): (
+        Res<DemoDelay>,
+        Res<AiDelay>,
+        Res<ShowAiIntent>,
+        Res<Countdown>,
+        Res<AiSearchTime>,
+    ),
+    (undo_limit, simultaneous_win_rule, win_line_style, win_line_thickness): (
+        Res<UndoLimit>,
+        Res<SimultaneousWinRule>,
+        Res<WinLineStyle>,
+        Res<WinLineThickness>,
+    ),
+    theme: Res<Theme>,
+    autosave: Res<Autosave>,
+    (drop_input_region, require_release, auto_forced, fog_of_war): (
+        Res<DropInputRegion>,
+        Res<RequireRelease>,
+        Res<AutoForced>,
+        Res<FogOfWar>,
+    ),
+    (history_display_mode, history_limit, board_layout, show_move_numbers): (
+        Res<HistoryDisplayMode>,
+        Res<HistoryLimit>,
+        Res<BoardLayout>,
+        Res<ShowMoveNumbers>,
+    ),
+    confirm_exit: Res<ConfirmExit>,
+    (reduced_motion, animation_speed, fall_gravity): (
+        Res<ReducedMotion>,
+        Res<AnimationSpeed>,
+        Res<FallGravity>,
+    ),
+    continue_offer: Res<ContinueOffer>,
 ) {
+    // A pending exit is forgotten when the menu is shown again
+    commands.insert_resource(ExitPending(false));
+
+    let colors = theme.colors();
+
     // Reused data for the buttons -------------------
-    let box_size = Size::new(Val::Px(200.0), Val::Px(65.0));
+    let box_size = Size::new(Val::Px(200.0), Val::Px(55.0));
 
-    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let font = asset_server.load(FONT_PATH);
     let text_style = TextStyle {
         font: font.clone(),
         font_size: 40.0,
-        color: FONT_COLOR,
+        color: colors.text,
     };
 
     let button_style = Style {
@@ -75,35 +410,44 @@ fn setup(
         justify_content: JustifyContent::Center,
         align_items: AlignItems::Center,
         margin: UiRect {
-            top: Val::Px(10.0),
-            bottom: Val::Px(10.0),
+            top: Val::Px(5.0),
+            bottom: Val::Px(5.0),
             ..default()
         },
         ..default()
     };
     let button_bundle = ButtonBundle {
         style: button_style,
-        background_color: NORMAL_BUTTON.into(),
+        background_color: colors.button.into(),
         ..default()
     };
-    // ----------------------------------------------
 
-    // Tint, so the game is not too visible behind the menu
-    commands.spawn((
-        SpriteBundle {
-            transform: Transform {
-                translation: Vec3::new(0.0, 0.0, 0.5),
-                scale: Vec3::new(WINDOW_WIDTH, WINDOW_HEIGHT, 0.0),
-                ..default()
-            },
-            sprite: Sprite {
-                color: TINT,
-                ..default()
-            },
+    // Pages with many buttons use shorter ones, so they all fit
+    let mut compact_button_bundle = button_bundle.clone();
+    compact_button_bundle.style.size.height = Val::Px(50.0);
+    compact_button_bundle.style.margin.top = Val::Px(3.0);
+    compact_button_bundle.style.margin.bottom = Val::Px(3.0);
+
+    // Settings have longer labels, so their buttons are wider, with two columns of
+    // them side by side and smaller text so they all fit in the window
+    let mut setting_button_bundle = compact_button_bundle.clone();
+    setting_button_bundle.style.size.width = Val::Px(380.0);
+    setting_button_bundle.style.size.height = Val::Px(36.0);
+    setting_button_bundle.style.margin = UiRect::all(Val::Px(2.0));
+    let setting_text_style = TextStyle {
+        font_size: 28.0,
+        ..text_style.clone()
+    };
+    let setting_column_bundle = NodeBundle {
+        style: Style {
+            align_items: AlignItems::Center,
+            flex_direction: FlexDirection::Column,
+            margin: UiRect::horizontal(Val::Px(3.0)),
             ..default()
         },
-        InMenu,
-    ));
+        ..default()
+    };
+    // ----------------------------------------------
 
     // Main menu entity, used to center all the buttons
     commands
@@ -121,297 +465,2064 @@ fn setup(
             InMenu,
         ))
         .with_children(|parent| {
-            // Title
-            parent.spawn(TextBundle::from_section(
-                "Connect 4",
-                TextStyle {
-                    font: font.clone(),
-                    font_size: 50.0,
-                    color: TITLE_COLOR,
-                },
-            ));
-
-            // Winner (if there is one)
-            if let Some(winner) = &main_menu_info.winner {
+            // Settings page, shown instead of the main page
+            if *menu_page == MenuPage::Settings {
                 parent.spawn(TextBundle::from_section(
-                    format!("{} wins!", winner),
+                    "Settings",
                     TextStyle {
-                        font,
-                        font_size: 40.0,
-                        color: WINNER_COLOR,
+                        font: font.clone(),
+                        font_size: 50.0,
+                        color: colors.title,
                     },
                 ));
-            }
 
-            // Resume button
-            if main_menu_info.allow_resume {
+                // The settings, in two columns
                 parent
-                    .spawn((button_bundle.clone(), ButtonType::Resume))
+                    .spawn(NodeBundle {
+                        style: Style {
+                            align_items: AlignItems::FlexStart,
+                            flex_direction: FlexDirection::Row,
+                            ..default()
+                        },
+                        ..default()
+                    })
                     .with_children(|parent| {
-                        parent.spawn(TextBundle::from_section("Resume", text_style.clone()));
+                        parent
+                            .spawn(setting_column_bundle.clone())
+                            .with_children(|parent| {
+                                // Ghost disk toggle, which can also show where the disk lands
+                                // ("Path")
+                                parent
+                                    .spawn((setting_button_bundle.clone(), ButtonType::ToggleGhost))
+                                    .with_children(|parent| {
+                                        let ghost = match (show_ghost.0, show_trajectory.0) {
+                                            (false, _) => "Off",
+                                            (true, false) => "On",
+                                            (true, true) => "Path",
+                                        };
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Ghost Disks: {}", ghost),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // How see-through the ghost disks are
+                                parent
+                                    .spawn((setting_button_bundle.clone(), ButtonType::GhostAlpha))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!(
+                                                "Ghost Opacity: {}%",
+                                                (ghost_alpha.0 * 100.0).round()
+                                            ),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // How smooth the disks and holes are drawn
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::CircleVertices,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Circle Detail: {}", circle_vertices.0),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Winner celebration toggle
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::ToggleParticles,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Particles: {}", on_off(show_particles.0)),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Exit confirmation toggle
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::ToggleConfirmExit,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Confirm Exit: {}", on_off(confirm_exit.0)),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Animation speed, or no animations at all
+                                parent
+                                    .spawn((setting_button_bundle.clone(), ButtonType::Motion))
+                                    .with_children(|parent| {
+                                        let motion = if reduced_motion.0 {
+                                            "Reduced".to_string()
+                                        } else {
+                                            format!("{}x", animation_speed.0)
+                                        };
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Motion: {}", motion),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // How fast dropped disks fall
+                                parent
+                                    .spawn((setting_button_bundle.clone(), ButtonType::FallGravity))
+                                    .with_children(|parent| {
+                                        let gravity = FALL_GRAVITIES
+                                            .iter()
+                                            .find(|&&(_, preset)| preset == fall_gravity.0)
+                                            .map_or_else(
+                                                || fall_gravity.0.to_string(),
+                                                |(name, _)| name.to_string(),
+                                            );
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Disk Fall: {}", gravity),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Autosave toggle
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::ToggleAutosave,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Autosave: {}", on_off(autosave.0)),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Moves that can be taken back in each game
+                                parent
+                                    .spawn((setting_button_bundle.clone(), ButtonType::UndoLimit))
+                                    .with_children(|parent| {
+                                        let undos = match undo_limit.0 {
+                                            Some(0) => "Off".to_string(),
+                                            Some(limit) => format!("{} per game", limit),
+                                            None => "Unlimited".to_string(),
+                                        };
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Undos: {}", undos),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Who wins when an action connects lines for more than one player
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::SimultaneousWinRule,
+                                    ))
+                                    .with_children(|parent| {
+                                        let rule = match *simultaneous_win_rule {
+                                            SimultaneousWinRule::Mover => "Mover",
+                                            SimultaneousWinRule::Draw => "Draw",
+                                        };
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Double Wins: {}", rule),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // How the winning line is marked, and how thick
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::WinLineStyle,
+                                    ))
+                                    .with_children(|parent| {
+                                        let style = match *win_line_style {
+                                            WinLineStyle::Bar => "Bar",
+                                            WinLineStyle::Rings => "Rings",
+                                            WinLineStyle::Both => "Both",
+                                        };
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Win Line: {}", style),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::WinLineThickness,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!(
+                                                "Win Line Width: {}%",
+                                                (win_line_thickness.0 * 100.0).round()
+                                            ),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Where clicks drop disks
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::DropInputRegion,
+                                    ))
+                                    .with_children(|parent| {
+                                        let region = match *drop_input_region {
+                                            DropInputRegion::Anywhere => "Anywhere",
+                                            DropInputRegion::Board => "On Board",
+                                        };
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Drop Clicks: {}", region),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Whether the mouse has to be let go between drops
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::ToggleRequireRelease,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Must Release: {}", on_off(require_release.0)),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+                            });
+                        parent
+                            .spawn(setting_column_bundle.clone())
+                            .with_children(|parent| {
+                                // Covering the board between turns in hot seat play
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::TogglePassScreen,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Pass Screen: {}", on_off(pass_screen.0)),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // How the move history is shown under the board
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::HistoryDisplayMode,
+                                    ))
+                                    .with_children(|parent| {
+                                        let mode = match *history_display_mode {
+                                            HistoryDisplayMode::Nodes => "Spread",
+                                            HistoryDisplayMode::Text => "Compact",
+                                            HistoryDisplayMode::Hidden => "Hidden",
+                                        };
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("History: {}", mode),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // How many moves the history keeps (undo can't go back further)
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::HistoryLimit,
+                                    ))
+                                    .with_children(|parent| {
+                                        let kept = match history_limit.0 {
+                                            Some(limit) => format!("{} Moves", limit),
+                                            None => "All Moves".to_string(),
+                                        };
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("History Keeps: {}", kept),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Move numbers on the disks themselves
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::ToggleMoveNumbers,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!(
+                                                "Move Numbers: {}",
+                                                on_off(show_move_numbers.0)
+                                            ),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Where the board is placed in the window
+                                parent
+                                    .spawn((setting_button_bundle.clone(), ButtonType::BoardLayout))
+                                    .with_children(|parent| {
+                                        let layout = if board_layout.centered {
+                                            format!("Centered, {}px", board_layout.margins.side)
+                                        } else {
+                                            "Top".to_string()
+                                        };
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Board: {}", layout),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Delay between moves in the AI demo
+                                parent
+                                    .spawn((setting_button_bundle.clone(), ButtonType::DemoDelay))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Demo Speed: {}s", demo_delay.0),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Minimum time the AI takes for a move when playing against it
+                                parent
+                                    .spawn((setting_button_bundle.clone(), ButtonType::AiDelay))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("AI Think Time: {}s", ai_delay.0),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // How far the AI looks ahead, as deep as it gets in the time or a
+                                // fixed depth
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::AiSearchTime,
+                                    ))
+                                    .with_children(|parent| {
+                                        let search = match ai_search_time.0 {
+                                            Some(time) => format!("{}s", time),
+                                            None => format!("{} Moves", AI_DEPTH),
+                                        };
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("AI Search: {}", search),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Preview of the AI's reply while it thinks
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::ToggleAiIntent,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("AI Intent: {}", on_off(show_ai_intent.0)),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Countdown before games against or between AIs
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::ToggleCountdown,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Countdown: {}", on_off(countdown.0)),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Hints in lost positions warn about the loss instead
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::ToggleLossWarning,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Loss Warning: {}", on_off(loss_warning.0)),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Columns where open lines of three end glow
+                                parent
+                                    .spawn((setting_button_bundle.clone(), ButtonType::ThreatGlow))
+                                    .with_children(|parent| {
+                                        let glow = match *threat_glow {
+                                            ThreatGlow::Off => "Off",
+                                            ThreatGlow::Own => "Own",
+                                            ThreatGlow::Both => "Both",
+                                        };
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Threat Glow: {}", glow),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // The other players' disks are gray, to be remembered
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::ToggleFogOfWar,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Fog of War: {}", on_off(fog_of_war.0)),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // The only column left is dropped into without a click
+                                parent
+                                    .spawn((
+                                        setting_button_bundle.clone(),
+                                        ButtonType::ToggleAutoForced,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Auto Forced Moves: {}", on_off(auto_forced.0)),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+
+                                // Color theme of the board, disks and text
+                                parent
+                                    .spawn((setting_button_bundle.clone(), ButtonType::Theme))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            format!("Theme: {}", theme.name()),
+                                            setting_text_style.clone(),
+                                        ));
+                                    });
+                            });
+                    });
+
+                // Back to the main page
+                parent
+                    .spawn((button_bundle.clone(), ButtonType::Back))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Back", text_style.clone()));
                     });
+                return;
             }
 
-            // New Game button
-            parent
-                .spawn((button_bundle.clone(), ButtonType::NewGame))
-                .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section("New Game", text_style.clone()));
+            // Stats page, read from the saves folder each time it is opened
+            if *menu_page == MenuPage::Stats {
+                parent.spawn(TextBundle::from_section(
+                    "Stats",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 50.0,
+                        color: colors.title,
+                    },
+                ));
+
+                let stats = aggregate(Path::new(SAVES_DIR));
+                let mut lines = vec![format!("Games: {}", stats.games)];
+                for (name, wins) in PLAYER_NAMES.iter().zip(stats.wins) {
+                    lines.push(format!("{} Wins: {}", name, wins));
+                }
+                lines.push(match stats.average_length() {
+                    Some(moves) => format!("Average Length: {:.1} moves", moves),
+                    None => "Average Length: -".to_string(),
+                });
+                lines.push(match stats.most_common_winning_col() {
+                    Some(col) => format!("Top Winning Column: {}", column_label(col)),
+                    None => "Top Winning Column: -".to_string(),
                 });
+                for line in lines {
+                    parent.spawn(TextBundle::from_section(line, text_style.clone()));
+                }
 
-            // Board size label
-            parent.spawn(TextBundle::from_section("Board Size:", text_style.clone()));
+                // Back to the main page
+                parent
+                    .spawn((button_bundle.clone(), ButtonType::Back))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Back", text_style.clone()));
+                    });
+                return;
+            }
 
-            // Board size buttons and text, inside new entities for easy formatting
-            parent
-                .spawn(NodeBundle {
-                    style: Style {
-                        size: box_size,
-                        align_items: AlignItems::Center,
-                        justify_content: JustifyContent::SpaceBetween,
-                        flex_direction: FlexDirection::Row,
-                        ..default()
+            // New game page, to pick the board and how the game is played
+            if *menu_page == MenuPage::NewGame {
+                parent.spawn(TextBundle::from_section(
+                    "New Game",
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 50.0,
+                        color: colors.title,
                     },
-                    ..default()
-                })
-                .with_children(|parent| {
-                    // Rows increase/decrease
-                    parent
-                        .spawn(NodeBundle {
-                            style: Style {
-                                size: Size::new(Val::Percent(20.0), Val::Percent(100.0)),
-                                align_items: AlignItems::Center,
-                                justify_content: JustifyContent::Center,
-                                flex_direction: FlexDirection::Column,
-                                ..default()
-                            },
+                ));
+
+                // Board size label
+                parent.spawn(TextBundle::from_section("Board Size:", text_style.clone()));
+
+                // Board size buttons and text, inside new entities for easy formatting
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            size: box_size,
+                            align_items: AlignItems::Center,
+                            justify_content: JustifyContent::SpaceBetween,
+                            flex_direction: FlexDirection::Row,
                             ..default()
-                        })
-                        .with_children(|parent| {
-                            // Increase row button
-                            parent
-                                .spawn((
-                                    ButtonBundle {
-                                        style: Style {
-                                            size: Size::new(
-                                                Val::Percent(100.0),
-                                                Val::Percent(50.0),
-                                            ),
-                                            justify_content: JustifyContent::Center,
-                                            align_items: AlignItems::Center,
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        // Rows increase/decrease
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Percent(20.0), Val::Percent(100.0)),
+                                    align_items: AlignItems::Center,
+                                    justify_content: JustifyContent::Center,
+                                    flex_direction: FlexDirection::Column,
+                                    ..default()
+                                },
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                // Increase row button
+                                parent
+                                    .spawn((
+                                        ButtonBundle {
+                                            style: Style {
+                                                size: Size::new(
+                                                    Val::Percent(100.0),
+                                                    Val::Percent(50.0),
+                                                ),
+                                                justify_content: JustifyContent::Center,
+                                                align_items: AlignItems::Center,
+                                                ..default()
+                                            },
+                                            background_color: colors.button.into(),
                                             ..default()
                                         },
-                                        background_color: NORMAL_BUTTON.into(),
-                                        ..default()
-                                    },
-                                    ButtonType::IncreaseRows,
-                                ))
-                                .with_children(|parent| {
-                                    parent.spawn(TextBundle::from_section("+", text_style.clone()));
-                                });
+                                        ButtonType::IncreaseRows,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            "+",
+                                            text_style.clone(),
+                                        ));
+                                    });
 
-                            // Decrease row button
-                            parent
-                                .spawn((
-                                    ButtonBundle {
-                                        style: Style {
-                                            size: Size::new(
-                                                Val::Percent(100.0),
-                                                Val::Percent(50.0),
-                                            ),
-                                            justify_content: JustifyContent::Center,
-                                            align_items: AlignItems::Center,
+                                // Decrease row button
+                                parent
+                                    .spawn((
+                                        ButtonBundle {
+                                            style: Style {
+                                                size: Size::new(
+                                                    Val::Percent(100.0),
+                                                    Val::Percent(50.0),
+                                                ),
+                                                justify_content: JustifyContent::Center,
+                                                align_items: AlignItems::Center,
+                                                ..default()
+                                            },
+                                            background_color: colors.button.into(),
                                             ..default()
                                         },
-                                        background_color: NORMAL_BUTTON.into(),
-                                        ..default()
-                                    },
-                                    ButtonType::DecreaseRows,
-                                ))
-                                .with_children(|parent| {
-                                    parent.spawn(TextBundle::from_section("-", text_style.clone()));
-                                });
-                        });
+                                        ButtonType::DecreaseRows,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            "-",
+                                            text_style.clone(),
+                                        ));
+                                    });
+                            });
 
-                    // Size text
-                    parent.spawn((
-                        TextBundle::from_section("6x7", text_style.clone()),
-                        BoardSizeText,
-                    ));
+                        // Size text, which can be clicked to type the size instead
+                        parent.spawn((
+                            TextBundle::from_section("6x7", text_style.clone()),
+                            Interaction::default(),
+                            BoardSizeText,
+                        ));
 
-                    // Cols increase/decrease
-                    parent
-                        .spawn(NodeBundle {
-                            style: Style {
-                                size: Size::new(Val::Percent(20.0), Val::Percent(100.0)),
-                                align_items: AlignItems::Center,
-                                justify_content: JustifyContent::Center,
-                                flex_direction: FlexDirection::Column,
+                        // Cols increase/decrease
+                        parent
+                            .spawn(NodeBundle {
+                                style: Style {
+                                    size: Size::new(Val::Percent(20.0), Val::Percent(100.0)),
+                                    align_items: AlignItems::Center,
+                                    justify_content: JustifyContent::Center,
+                                    flex_direction: FlexDirection::Column,
+                                    ..default()
+                                },
                                 ..default()
-                            },
-                            ..default()
-                        })
-                        .with_children(|parent| {
-                            // Increase col button
-                            parent
-                                .spawn((
-                                    ButtonBundle {
-                                        style: Style {
-                                            size: Size::new(
-                                                Val::Percent(100.0),
-                                                Val::Percent(50.0),
-                                            ),
-                                            justify_content: JustifyContent::Center,
-                                            align_items: AlignItems::Center,
+                            })
+                            .with_children(|parent| {
+                                // Increase col button
+                                parent
+                                    .spawn((
+                                        ButtonBundle {
+                                            style: Style {
+                                                size: Size::new(
+                                                    Val::Percent(100.0),
+                                                    Val::Percent(50.0),
+                                                ),
+                                                justify_content: JustifyContent::Center,
+                                                align_items: AlignItems::Center,
+                                                ..default()
+                                            },
+                                            background_color: colors.button.into(),
                                             ..default()
                                         },
-                                        background_color: NORMAL_BUTTON.into(),
-                                        ..default()
-                                    },
-                                    ButtonType::IncreaseCols,
-                                ))
-                                .with_children(|parent| {
-                                    parent.spawn(TextBundle::from_section("+", text_style.clone()));
-                                });
+                                        ButtonType::IncreaseCols,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            "+",
+                                            text_style.clone(),
+                                        ));
+                                    });
 
-                            // Decrease col button
-                            parent
-                                .spawn((
-                                    ButtonBundle {
-                                        style: Style {
-                                            size: Size::new(
-                                                Val::Percent(100.0),
-                                                Val::Percent(50.0),
-                                            ),
-                                            justify_content: JustifyContent::Center,
-                                            align_items: AlignItems::Center,
+                                // Decrease col button
+                                parent
+                                    .spawn((
+                                        ButtonBundle {
+                                            style: Style {
+                                                size: Size::new(
+                                                    Val::Percent(100.0),
+                                                    Val::Percent(50.0),
+                                                ),
+                                                justify_content: JustifyContent::Center,
+                                                align_items: AlignItems::Center,
+                                                ..default()
+                                            },
+                                            background_color: colors.button.into(),
                                             ..default()
                                         },
-                                        background_color: NORMAL_BUTTON.into(),
-                                        ..default()
-                                    },
-                                    ButtonType::DecreaseCols,
-                                ))
-                                .with_children(|parent| {
-                                    parent.spawn(TextBundle::from_section("-", text_style.clone()));
-                                });
-                        });
-                });
+                                        ButtonType::DecreaseCols,
+                                    ))
+                                    .with_children(|parent| {
+                                        parent.spawn(TextBundle::from_section(
+                                            "-",
+                                            text_style.clone(),
+                                        ));
+                                    });
+                            });
+                    });
 
-            // Save button
-            if main_menu_info.allow_resume {
+                // Notice, empty unless a size was blocked
+                parent.spawn((
+                    TextBundle::from_section(
+                        "",
+                        TextStyle {
+                            font: font.clone(),
+                            font_size: 25.0,
+                            color: colors.winner,
+                        },
+                    ),
+                    NoticeText,
+                ));
+
+                // Number of players button, cycles through 2 to MAX_PLAYERS
                 parent
-                    .spawn((button_bundle.clone(), ButtonType::Save))
+                    .spawn((compact_button_bundle.clone(), ButtonType::Players))
                     .with_children(|parent| {
-                        parent.spawn(TextBundle::from_section("Save Game", text_style.clone()));
+                        parent.spawn((
+                            TextBundle::from_section("2 Players", text_style.clone()),
+                            PlayerCountText,
+                        ));
                     });
-            }
-
-            // Load button
-            parent
-                .spawn((button_bundle.clone(), ButtonType::Load))
-                .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section("Load Game", text_style.clone()));
-                });
 
-            // Exit button
-            parent
-                .spawn((button_bundle.clone(), ButtonType::Exit))
-                .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section("Exit", text_style.clone()));
-                });
-        });
-}
+                // Drop pattern button, cycles through the ways the disks can fall
+                parent
+                    .spawn((compact_button_bundle.clone(), ButtonType::DropPattern))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextBundle::from_section("Normal", text_style.clone()),
+                            DropPatternText,
+                        ));
+                    });
 
-// Remove all entities that are in the menu
-fn cleanup(mut commands: Commands, query: Query<Entity, With<InMenu>>) {
-    for entity in &query {
-        commands.entity(entity).despawn_recursive();
-    }
-}
+                // Win condition button, cycles through the lines that win and squares
+                parent
+                    .spawn((compact_button_bundle.clone(), ButtonType::WinCondition))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextBundle::from_section("Win: Line", text_style.clone()),
+                            WinConditionText,
+                        ));
+                    });
 
-type OnButtonChanged = (Changed<Interaction>, With<Button>);
+                // Match length button, cycles through a single game and best of N matches
+                parent
+                    .spawn((compact_button_bundle.clone(), ButtonType::MatchLength))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextBundle::from_section("Single Game", text_style.clone()),
+                            MatchLengthText,
+                        ));
+                    });
 
-// Button system, handles all button interactions
-fn button_system(
-    mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor, &ButtonType),
-        OnButtonChanged,
-    >,
-    mut game_state: ResMut<State<GameState>>,
-    mut exit: EventWriter<AppExit>,
-    mut game_change: EventWriter<GameChange>,
-    mut board_size: ResMut<BoardSize>,
-) {
-    for (interaction, mut background_color, button_type) in &mut interaction_query {
-        // Check each interaction, and color the button accordingly
-        match *interaction {
-            Interaction::Clicked => {
-                *background_color = PRESSED_BUTTON.into();
+                // Start a normal game with the chosen options
+                parent
+                    .spawn((compact_button_bundle.clone(), ButtonType::Start))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Start", text_style.clone()));
+                    });
+
+                // Start a game with some cells blocked by rocks
+                parent
+                    .spawn((compact_button_bundle.clone(), ButtonType::Rocks))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("With Rocks", text_style.clone()));
+                    });
+
+                // Play against the AI (the player moves first)
+                parent
+                    .spawn((compact_button_bundle.clone(), ButtonType::VsAi))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Play vs AI", text_style.clone()));
+                    });
+
+                // Play against your own moves from the last game against the AI
+                parent
+                    .spawn((compact_button_bundle.clone(), ButtonType::MirrorHuman))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            "Play vs Mirror",
+                            text_style.clone(),
+                        ));
+                    });
+
+                // Daily puzzle button
+                parent
+                    .spawn((compact_button_bundle.clone(), ButtonType::Puzzle))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Puzzle", text_style.clone()));
+                    });
+
+                // AI vs AI demo button
+                parent
+                    .spawn((compact_button_bundle.clone(), ButtonType::Demo))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("AI Demo", text_style.clone()));
+                    });
+
+                // Back to the main page
+                parent
+                    .spawn((compact_button_bundle.clone(), ButtonType::Back))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Back", text_style.clone()));
+                    });
+
+                // Win length of each player in their color, out of the way on the right
+                // (only the players in the game are shown, see update_win_length_buttons)
+                let mut win_length_button_bundle = setting_button_bundle.clone();
+                win_length_button_bundle.style.size.width = Val::Px(PREVIEW_WIDTH);
+                let player_colors = colors.swapped(*swap_colors);
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                left: Val::Px(WIN_LENGTHS_POSITION.x),
+                                top: Val::Px(WIN_LENGTHS_POSITION.y),
+                                ..default()
+                            },
+                            flex_direction: FlexDirection::Column,
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        for player in 0..MAX_PLAYERS {
+                            parent
+                                .spawn((
+                                    win_length_button_bundle.clone(),
+                                    ButtonType::WinLength(player),
+                                ))
+                                .with_children(|parent| {
+                                    parent.spawn((
+                                        TextBundle::from_section(
+                                            "",
+                                            TextStyle {
+                                                color: player_colors.disks[player as usize],
+                                                ..setting_text_style.clone()
+                                            },
+                                        ),
+                                        WinLengthText(player),
+                                    ));
+                                });
+                        }
+
+                        // Below the players, so the swap shows right away in their colors
+                        parent
+                            .spawn((win_length_button_bundle.clone(), ButtonType::SwapColors))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    format!("Swap Colors: {}", on_off(swap_colors.0)),
+                                    setting_text_style.clone(),
+                                ));
+                            });
+                    });
+                return;
+            }
+
+            // Title
+            parent.spawn(TextBundle::from_section(
+                "Connect 4",
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 50.0,
+                    color: colors.title,
+                },
+            ));
+
+            // Winner (if there is one), of the game or the whole match
+            if let Some(winner) = &main_menu_info.winner {
+                let heading = if main_menu_info.won_match {
+                    format!("{} wins the match!", winner)
+                } else {
+                    format!("{} wins!", winner)
+                };
+                parent.spawn(TextBundle::from_section(
+                    heading,
+                    TextStyle {
+                        font: font.clone(),
+                        font_size: 40.0,
+                        color: colors.winner,
+                    },
+                ));
+            }
+
+            // Extra message about how the game ended (if there is one)
+            if let Some(message) = &main_menu_info.message {
+                parent.spawn(TextBundle::from_section(
+                    message,
+                    TextStyle {
+                        font,
+                        font_size: 40.0,
+                        color: colors.winner,
+                    },
+                ));
+            }
+
+            // Continue the autosaved game (only when the app was just started)
+            if continue_offer.0 && autosave.0 && !main_menu_info.allow_resume {
+                parent
+                    .spawn((button_bundle.clone(), ButtonType::Continue))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Continue", text_style.clone()));
+                    });
+            }
+
+            // Resume button
+            if main_menu_info.allow_resume {
+                parent
+                    .spawn((button_bundle.clone(), ButtonType::Resume))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Resume", text_style.clone()));
+                    });
+
+                // Resign button
+                parent
+                    .spawn((button_bundle.clone(), ButtonType::Resign))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Resign", text_style.clone()));
+                    });
+
+                // Draw button
+                parent
+                    .spawn((button_bundle.clone(), ButtonType::Draw))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Draw", text_style.clone()));
+                    });
+            }
+
+            // New Game button
+            parent
+                .spawn((button_bundle.clone(), ButtonType::NewGame))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("New Game", text_style.clone()));
+                });
+
+            // Save button
+            if main_menu_info.allow_resume {
+                parent
+                    .spawn((button_bundle.clone(), ButtonType::Save))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Save Game", text_style.clone()));
+                    });
+            }
+
+            // Export the game as frames, also once it's over
+            if main_menu_info.allow_resume || main_menu_info.winner.is_some() {
+                parent
+                    .spawn((button_bundle.clone(), ButtonType::ExportReplay))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            "Export Replay",
+                            text_style.clone(),
+                        ));
+                    });
+            }
+
+            // Step through the moves of a finished game
+            if !main_menu_info.allow_resume && main_menu_info.winner.is_some() {
+                parent
+                    .spawn((button_bundle.clone(), ButtonType::Review))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section("Review", text_style.clone()));
+                    });
+            }
+
+            // Load button
+            parent
+                .spawn((button_bundle.clone(), ButtonType::Load))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Load Game", text_style.clone()));
+                });
+
+            // Board editor, to set up a position by hand
+            parent
+                .spawn((button_bundle.clone(), ButtonType::Editor))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Edit Board", text_style.clone()));
+                });
+
+            // Stats over the saved games
+            parent
+                .spawn((button_bundle.clone(), ButtonType::Stats))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Stats", text_style.clone()));
+                });
+
+            // Settings button
+            parent
+                .spawn((button_bundle.clone(), ButtonType::Settings))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section("Settings", text_style.clone()));
+                });
+
+            // Exit button
+            parent
+                .spawn((button_bundle.clone(), ButtonType::Exit))
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle::from_section("Exit", text_style.clone()),
+                        ExitText,
+                    ));
+                });
+        });
+}
+
+// Small panel in the top right corner for the quick pause, the game stays visible around it
+fn setup_pause_panel(mut commands: Commands, asset_server: Res<AssetServer>, theme: Res<Theme>) {
+    // A pending exit is forgotten when the panel is shown again
+    commands.insert_resource(ExitPending(false));
+
+    let colors = theme.colors();
+    let text_style = TextStyle {
+        font: asset_server.load(FONT_PATH),
+        font_size: 30.0,
+        color: colors.text,
+    };
+    let button_bundle = ButtonBundle {
+        style: Style {
+            size: Size::new(Val::Px(160.0), Val::Px(40.0)),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::all(Val::Px(4.0)),
+            ..default()
+        },
+        background_color: colors.button.into(),
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    // Under the clock
+                    position: UiRect {
+                        right: Val::Px(10.0),
+                        top: Val::Px(50.0),
+                        ..default()
+                    },
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    padding: UiRect::all(Val::Px(6.0)),
+                    ..default()
+                },
+                background_color: PAUSE_PANEL_COLOR.into(),
+                ..default()
+            },
+            InMenu,
+        ))
+        .with_children(|parent| {
+            for (button_type, label) in [
+                (ButtonType::Resume, "Resume"),
+                (ButtonType::Save, "Save"),
+                (ButtonType::GrowBoard, "Grow Board"),
+                (ButtonType::Menu, "Menu"),
+            ] {
+                parent
+                    .spawn((button_bundle.clone(), button_type))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(label, text_style.clone()));
+                    });
+            }
+
+            // Quits the whole app, like the exit button of the menu
+            parent
+                .spawn((button_bundle.clone(), ButtonType::Exit))
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle::from_section("Exit", text_style.clone()),
+                        ExitText,
+                    ));
+                });
+        });
+}
+
+// Escape goes straight back to the game from the quick pause
+fn resume_on_escape(keyboard_input: Res<Input<KeyCode>>, mut game_state: ResMut<State<GameState>>) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        game_state.set(GameState::Playing).unwrap();
+    }
+}
+
+// Text for the state of a toggle in the settings
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "On"
+    } else {
+        "Off"
+    }
+}
+
+// Shown when trying to make the board smaller than the win length
+fn too_small_notice(win_length: i32) -> String {
+    format!("Boards need at least {} rows and columns", win_length)
+}
+
+// The longest line any of the players needs to win, the board has to fit it
+fn longest_win_length(win_lengths: &ChosenWinLengths, players: u8) -> i32 {
+    win_lengths.0[..players as usize]
+        .iter()
+        .copied()
+        .max()
+        .unwrap_or(DEFAULT_WIN_LENGTH)
+}
+
+// The win lengths of the players for a new game, none if they all need the usual number
+fn handicap(win_lengths: &ChosenWinLengths, players: u8) -> Vec<i32> {
+    let win_lengths = &win_lengths.0[..players as usize];
+    if win_lengths.iter().all(|&win_length| win_length == DEFAULT_WIN_LENGTH) {
+        Vec::new()
+    } else {
+        win_lengths.to_vec()
+    }
+}
+
+// The win length after the given one, going back to the shortest after the longest
+fn next_win_length(win_length: i32) -> i32 {
+    let index = WIN_LENGTHS
+        .iter()
+        .position(|&choice| choice == win_length)
+        .map_or(0, |index| (index + 1) % WIN_LENGTHS.len());
+    WIN_LENGTHS[index]
+}
+
+// Boards can't be much wider than they are tall (or the other way around),
+// nor have more rows or columns than the game allows
+fn in_size_limits(rows: i32, cols: i32) -> bool {
+    (cols - rows).abs() <= 2 && rows <= MAX_BOARD_SIZE && cols <= MAX_BOARD_SIZE
+}
+
+// Reads a typed board size as rows and columns, like "10x10" (None if it isn't one)
+fn parse_board_size(text: &str) -> Option<(i32, i32)> {
+    let text = text.to_lowercase();
+    let (rows, cols) = text.split_once('x')?;
+    Some((rows.trim().parse().ok()?, cols.trim().parse().ok()?))
+}
+
+// Where the win condition and directions are in WIN_RULES (the first rule if they aren't there)
+fn win_rule_index(condition: WinCondition, directions: WinDirections) -> usize {
+    WIN_RULES
+        .iter()
+        .position(|&(_, rule_condition, rule_directions)| {
+            rule_condition == condition && rule_directions == directions
+        })
+        .unwrap_or(0)
+}
+
+// The match length after the given one, going back to a single game after the longest match
+fn next_match_length(games: Option<u32>) -> Option<u32> {
+    match games {
+        None => Some(MATCH_LENGTHS[0]),
+        Some(games) => MATCH_LENGTHS
+            .iter()
+            .position(|&preset| preset == games)
+            .and_then(|index| MATCH_LENGTHS.get(index + 1))
+            .copied(),
+    }
+}
+
+// The preset AI delay after the given one, wrapping around
+fn next_delay(delay: f32) -> f32 {
+    let next = AI_DELAYS
+        .iter()
+        .position(|&preset| preset == delay)
+        .map_or(0, |index| (index + 1) % AI_DELAYS.len());
+    AI_DELAYS[next]
+}
+
+// The preset AI search time after the given one, going back to a fixed depth after the longest
+fn next_ai_search_time(time: Option<f32>) -> Option<f32> {
+    match time {
+        None => Some(AI_SEARCH_TIMES[0]),
+        Some(time) => AI_SEARCH_TIMES
+            .iter()
+            .position(|&preset| preset == time)
+            .and_then(|index| AI_SEARCH_TIMES.get(index + 1))
+            .copied(),
+    }
+}
+
+// The preset ghost disk opacity after the given one, wrapping around
+fn next_ghost_alpha(alpha: f32) -> f32 {
+    let next = GHOST_ALPHAS
+        .iter()
+        .position(|&preset| preset == alpha)
+        .map_or(0, |index| (index + 1) % GHOST_ALPHAS.len());
+    GHOST_ALPHAS[next]
+}
+
+// The preset circle corner count after the given one, wrapping around
+fn next_circle_vertices(vertices: usize) -> usize {
+    let next = CIRCLE_VERTICES
+        .iter()
+        .position(|&preset| preset == vertices)
+        .map_or(0, |index| (index + 1) % CIRCLE_VERTICES.len());
+    CIRCLE_VERTICES[next]
+}
+
+// The preset winning line thickness after the given one, wrapping around
+fn next_win_line_thickness(thickness: f32) -> f32 {
+    let next = WIN_LINE_THICKNESSES
+        .iter()
+        .position(|&preset| preset == thickness)
+        .map_or(0, |index| (index + 1) % WIN_LINE_THICKNESSES.len());
+    WIN_LINE_THICKNESSES[next]
+}
+
+// The preset fall gravity after the given one, wrapping around
+fn next_fall_gravity(gravity: f32) -> f32 {
+    let next = FALL_GRAVITIES
+        .iter()
+        .position(|&(_, preset)| preset == gravity)
+        .map_or(0, |index| (index + 1) % FALL_GRAVITIES.len());
+    FALL_GRAVITIES[next].1
+}
+
+// The preset undo limit after the given one, wrapping around
+fn next_undo_limit(limit: Option<u32>) -> Option<u32> {
+    let next = UNDO_LIMITS
+        .iter()
+        .position(|&preset| preset == limit)
+        .map_or(0, |index| (index + 1) % UNDO_LIMITS.len());
+    UNDO_LIMITS[next]
+}
+
+// The preset history limit after the given one, wrapping around
+fn next_history_limit(limit: Option<usize>) -> Option<usize> {
+    let next = HISTORY_LIMITS
+        .iter()
+        .position(|&preset| preset == limit)
+        .map_or(0, |index| (index + 1) % HISTORY_LIMITS.len());
+    HISTORY_LIMITS[next]
+}
+
+// The layout after the given one: the classic one, then centered with each preset side margin
+// (keeping the top and bottom margins), wrapping around
+fn next_board_layout(layout: BoardLayout) -> BoardLayout {
+    let next = if layout.centered {
+        BOARD_SIDE_MARGINS
+            .iter()
+            .position(|&preset| preset == layout.margins.side)
+            .and_then(|index| BOARD_SIDE_MARGINS.get(index + 1))
+    } else {
+        BOARD_SIDE_MARGINS.first()
+    };
+    let mut margins = layout.margins;
+    if let Some(&side) = next {
+        margins.side = side;
+    }
+    BoardLayout {
+        centered: next.is_some(),
+        margins,
+    }
+}
+
+// The autosave is only offered before the first game, called when a game starts
+fn clear_continue_offer(mut continue_offer: ResMut<ContinueOffer>) {
+    continue_offer.0 = false;
+}
+
+// Remove all entities that are in the menu
+// (except the tint, which fades out first)
+fn cleanup(mut commands: Commands, query: Query<Entity, (With<InMenu>, Without<MenuTint>)>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Tint, so the game is not too visible behind the menu, fading in
+fn fade_in_tint(mut commands: Commands, mut query: Query<(Entity, &mut Sprite), With<MenuTint>>) {
+    // Switching between pages leaves and enters the menu again, the tint just stays
+    if !query.is_empty() {
+        for (entity, mut sprite) in &mut query {
+            commands.entity(entity).remove::<MenuFade>();
+            sprite.color = TINT;
+        }
+        return;
+    }
+
+    let mut color = TINT;
+    color.set_a(0.0);
+    commands.spawn((
+        SpriteBundle {
+            transform: Transform {
+                translation: Vec3::new(0.0, 0.0, 0.5),
+                scale: Vec3::new(WINDOW_WIDTH, WINDOW_HEIGHT, 0.0),
+                ..default()
+            },
+            sprite: Sprite { color, ..default() },
+            ..default()
+        },
+        MenuFade {
+            timer: Timer::from_seconds(FADE_TIME, TimerMode::Once),
+            fade_in: true,
+        },
+        MenuTint,
+        InMenu,
+    ));
+}
+
+// Starts fading out the tint when leaving the menu
+fn fade_out_tint(mut commands: Commands, query: Query<Entity, With<MenuTint>>) {
+    for entity in &query {
+        commands.entity(entity).insert(MenuFade {
+            timer: Timer::from_seconds(FADE_TIME, TimerMode::Once),
+            fade_in: false,
+        });
+    }
+}
+
+// Animates the tint's alpha, removing it once it has faded out
+fn update_fade(
+    mut commands: Commands,
+    time: Res<Time>,
+    animation_speed: Res<AnimationSpeed>,
+    reduced_motion: Res<ReducedMotion>,
+    mut query: Query<(Entity, &mut Sprite, &mut MenuFade)>,
+) {
+    for (entity, mut sprite, mut fade) in &mut query {
+        let finished = tick_animation(
+            &mut fade.timer,
+            time.delta(),
+            *animation_speed,
+            *reduced_motion,
+        )
+        .finished();
+        let percent = if fade.fade_in {
+            fade.timer.percent()
+        } else {
+            fade.timer.percent_left()
+        };
+        sprite.color.set_a(TINT.a() * percent);
+
+        if finished {
+            if fade.fade_in {
+                commands.entity(entity).remove::<MenuFade>();
+            } else {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+type OnButtonChanged = (Changed<Interaction>, With<Button>);
+
+// Button system, handles all button interactions
+fn button_system(
+    mut interaction_query: Query<
+        (&Interaction, &mut BackgroundColor, &ButtonType),
+        OnButtonChanged,
+    >,
+    mut game_state: ResMut<State<GameState>>,
+    mut exit: EventWriter<AppExit>,
+    mut game_change: EventWriter<GameChange>,
+    (
+        mut board_size,
+        mut player_count,
+        mut drop_pattern,
+        mut win_condition,
+        mut win_directions,
+        mut match_length,
+        mut win_lengths,
+    ): NewGameOptions,
+    mut notice: ResMut<Notice>,
+    mut menu_page: ResMut<MenuPage>,
+    (mut show_ghost, mut show_trajectory, mut ghost_alpha, mut circle_vertices): (
+        ResMut<ShowGhost>,
+        ResMut<ShowTrajectory>,
+        ResMut<GhostAlpha>,
+        ResMut<CircleVertices>,
+    ),
+    (mut show_particles, mut pass_screen, mut loss_warning, mut swap_colors, mut threat_glow): (
+        ResMut<ShowParticles>,
+        ResMut<PassScreen>,
+        ResMut<LossWarning>,
+        ResMut<SwapColors>,
+        ResMut<ThreatGlow>,
+    ),
+    (
+        mut autosave,
+        mut undo_limit,
+        mut simultaneous_win_rule,
+        mut win_line_style,
+        mut win_line_thickness,
+    ): (
+        ResMut<Autosave>,
+        ResMut<UndoLimit>,
+        ResMut<SimultaneousWinRule>,
+        ResMut<WinLineStyle>,
+        ResMut<WinLineThickness>,
+    ),
+    (mut drop_input_region, mut require_release, mut auto_forced, mut fog_of_war): (
+        ResMut<DropInputRegion>,
+        ResMut<RequireRelease>,
+        ResMut<AutoForced>,
+        ResMut<FogOfWar>,
+    ),
+    (mut history_display_mode, mut history_limit, mut board_layout, mut show_move_numbers): (
+        ResMut<HistoryDisplayMode>,
+        ResMut<HistoryLimit>,
+        ResMut<BoardLayout>,
+        ResMut<ShowMoveNumbers>,
+    ),
+    (mut demo_delay, mut ai_delay, mut show_ai_intent, mut countdown, mut ai_search_time): (
+        ResMut<DemoDelay>,
+        ResMut<AiDelay>,
+        ResMut<ShowAiIntent>,
+        ResMut<Countdown>,
+        ResMut<AiSearchTime>,
+    ),
+    (mut reduced_motion, mut animation_speed, mut fall_gravity): (
+        ResMut<ReducedMotion>,
+        ResMut<AnimationSpeed>,
+        ResMut<FallGravity>,
+    ),
+    mut theme: ResMut<Theme>,
+    (mut confirm_exit, mut exit_pending): (ResMut<ConfirmExit>, ResMut<ExitPending>),
+) {
+    for (interaction, mut background_color, button_type) in &mut interaction_query {
+        // Check each interaction, and color the button accordingly
+        match *interaction {
+            Interaction::Clicked => {
+                *background_color = theme.colors().button_pressed.into();
 
                 // Handle button presses
                 match button_type {
+                    // Tell the game to load the autosave, and then return to the game
+                    ButtonType::Continue => {
+                        game_change.send(GameChange::Continue);
+                        game_state.set(GameState::Playing).unwrap();
+                    }
                     // Just return to the game
                     ButtonType::Resume => game_state.set(GameState::Playing).unwrap(),
-                    // Send the event to create a new game, and then return to the game
+                    ButtonType::Menu => game_state.set(GameState::Menu).unwrap(),
+                    // The game shows the finished game's positions, coming back here after
+                    ButtonType::Review => game_state.set(GameState::Review).unwrap(),
+                    ButtonType::GrowBoard => {
+                        game_change.send(GameChange::GrowBoard);
+                        game_state.set(GameState::Playing).unwrap();
+                    }
+                    // Tell the game the current player gave up, it will come back with a winner
+                    ButtonType::Resign => {
+                        game_change.send(GameChange::Resign);
+                        game_state.set(GameState::Playing).unwrap();
+                    }
+                    // Tell the game to end without a winner, it will come back with a draw
+                    ButtonType::Draw => {
+                        game_change.send(GameChange::Draw);
+                        game_state.set(GameState::Playing).unwrap();
+                    }
                     ButtonType::NewGame => {
+                        notice.0 = None;
+                        *menu_page = MenuPage::NewGame;
+                        game_state.restart().unwrap();
+                    }
+                    // Send the event to create a new game, and then return to the game
+                    ButtonType::Start => {
+                        *menu_page = MenuPage::Main;
+                        game_change.send(GameChange::New {
+                            rows: board_size.rows,
+                            cols: board_size.cols,
+                            players: player_count.0,
+                            rocks: None,
+                            drop_pattern: drop_pattern.0,
+                            win_condition: win_condition.0,
+                            win_directions: win_directions.0,
+                            win_lengths: handicap(&win_lengths, player_count.0),
+                            best_of: match_length.0,
+                        });
+                        game_state.set(GameState::Playing).unwrap();
+                    }
+                    // The same, but with rocks in random places
+                    ButtonType::Rocks => {
+                        *menu_page = MenuPage::Main;
                         game_change.send(GameChange::New {
                             rows: board_size.rows,
                             cols: board_size.cols,
+                            players: player_count.0,
+                            rocks: Some(rand::random()),
+                            drop_pattern: drop_pattern.0,
+                            win_condition: win_condition.0,
+                            win_directions: win_directions.0,
+                            win_lengths: handicap(&win_lengths, player_count.0),
+                            best_of: match_length.0,
                         });
                         game_state.set(GameState::Playing).unwrap();
                     }
                     ButtonType::IncreaseRows => {
-                        if (board_size.cols - (board_size.rows + 1)).abs() <= 2 {
+                        notice.0 = None;
+                        if in_size_limits(board_size.rows + 1, board_size.cols) {
                             board_size.rows += 1;
                         }
                     }
+                    // Boards can't be smaller than the win length, the notice says why
                     ButtonType::DecreaseRows => {
-                        if board_size.rows > 6
-                            && (board_size.cols - (board_size.rows - 1)).abs() <= 2
-                        {
+                        let win_length = longest_win_length(&win_lengths, player_count.0);
+                        if !Board::fits_win_length(
+                            board_size.rows - 1,
+                            board_size.cols,
+                            win_length,
+                        ) {
+                            notice.0 = Some(too_small_notice(win_length));
+                        } else if in_size_limits(board_size.rows - 1, board_size.cols) {
                             board_size.rows -= 1;
                         }
                     }
                     ButtonType::IncreaseCols => {
-                        if ((board_size.cols + 1) - board_size.rows).abs() <= 2 {
+                        notice.0 = None;
+                        if in_size_limits(board_size.rows, board_size.cols + 1) {
                             board_size.cols += 1;
                         }
                     }
                     ButtonType::DecreaseCols => {
-                        if board_size.cols > 7
-                            && ((board_size.cols - 1) - board_size.rows).abs() <= 2
-                        {
+                        let win_length = longest_win_length(&win_lengths, player_count.0);
+                        if !Board::fits_win_length(
+                            board_size.rows,
+                            board_size.cols - 1,
+                            win_length,
+                        ) {
+                            notice.0 = Some(too_small_notice(win_length));
+                        } else if in_size_limits(board_size.rows, board_size.cols - 1) {
                             board_size.cols -= 1;
                         }
                     }
+                    ButtonType::Players => {
+                        player_count.0 = if player_count.0 < MAX_PLAYERS {
+                            player_count.0 + 1
+                        } else {
+                            2
+                        };
+                    }
+                    // Lines longer than the board fits are skipped, the notice says why
+                    ButtonType::WinLength(player) => {
+                        let win_length = &mut win_lengths.0[*player as usize];
+                        *win_length = next_win_length(*win_length);
+                        if !Board::fits_win_length(board_size.rows, board_size.cols, *win_length) {
+                            notice.0 = Some(too_small_notice(*win_length));
+                            *win_length = WIN_LENGTHS[0];
+                        } else {
+                            notice.0 = None;
+                        }
+                    }
+                    // The game is recolored by it, and the menu rebuilt in the new colors
+                    ButtonType::SwapColors => {
+                        swap_colors.0 = !swap_colors.0;
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::DropPattern => {
+                        drop_pattern.0 = drop_pattern.0.next();
+                    }
+                    ButtonType::WinCondition => {
+                        let next = (win_rule_index(win_condition.0, win_directions.0) + 1)
+                            % WIN_RULES.len();
+                        (_, win_condition.0, win_directions.0) = WIN_RULES[next];
+                    }
+                    ButtonType::MatchLength => {
+                        match_length.0 = next_match_length(match_length.0);
+                    }
+                    // Tell the game to load today's puzzle, and then return to the game
+                    ButtonType::Puzzle => {
+                        *menu_page = MenuPage::Main;
+                        game_change.send(GameChange::Puzzle);
+                        game_state.set(GameState::Playing).unwrap();
+                    }
+                    // Start the AI vs AI demo on the chosen board size
+                    ButtonType::Demo => {
+                        *menu_page = MenuPage::Main;
+                        game_change.send(GameChange::Demo {
+                            rows: board_size.rows,
+                            cols: board_size.cols,
+                            depth: DEMO_AI_DEPTH,
+                        });
+                        game_state.set(GameState::Playing).unwrap();
+                    }
+                    // Start a two player game against the AI on the chosen board size
+                    ButtonType::VsAi => {
+                        *menu_page = MenuPage::Main;
+                        game_change.send(GameChange::VsAi {
+                            rows: board_size.rows,
+                            cols: board_size.cols,
+                            depth: AI_DEPTH,
+                        });
+                        game_state.set(GameState::Playing).unwrap();
+                    }
+                    // Start a two player game against the mirror on the chosen board size
+                    ButtonType::MirrorHuman => {
+                        *menu_page = MenuPage::Main;
+                        game_change.send(GameChange::MirrorHuman {
+                            rows: board_size.rows,
+                            cols: board_size.cols,
+                        });
+                        game_state.set(GameState::Playing).unwrap();
+                    }
                     // Tell the game to save, and then return to the game
                     ButtonType::Save => {
                         game_change.send(GameChange::Save);
                         game_state.set(GameState::Playing).unwrap();
                     }
+                    // The game writes the frames and shows the result here, staying in the menu
+                    ButtonType::ExportReplay => {
+                        game_change.send(GameChange::ExportReplay {
+                            dir: REPLAY_DIR.to_string(),
+                        });
+                    }
                     // Tell the game to load, and then return to the game
                     ButtonType::Load => {
                         game_change.send(GameChange::Load);
                         game_state.set(GameState::Playing).unwrap();
                     }
-                    // Exit the whole app
-                    ButtonType::Exit => exit.send_default(),
+                    // Switch between the pages, restarting the menu state to rebuild it
+                    ButtonType::Settings => {
+                        *menu_page = MenuPage::Settings;
+                        game_state.restart().unwrap();
+                    }
+                    // The game sets up an empty board to edit, replacing the current game
+                    ButtonType::Editor => game_state.set(GameState::Editor).unwrap(),
+                    ButtonType::Stats => {
+                        *menu_page = MenuPage::Stats;
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::Back => {
+                        *menu_page = MenuPage::Main;
+                        game_state.restart().unwrap();
+                    }
+                    // Flip the setting, and rebuild the page so the label is up to date
+                    // (the ghost disks go from off to on, to on with their path, and back to off)
+                    ButtonType::ToggleGhost => {
+                        if !show_ghost.0 {
+                            show_ghost.0 = true;
+                            show_trajectory.0 = false;
+                        } else if !show_trajectory.0 {
+                            show_trajectory.0 = true;
+                        } else {
+                            show_ghost.0 = false;
+                        }
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::ToggleConfirmExit => {
+                        confirm_exit.0 = !confirm_exit.0;
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::ToggleParticles => {
+                        show_particles.0 = !show_particles.0;
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::TogglePassScreen => {
+                        pass_screen.0 = !pass_screen.0;
+                        game_state.restart().unwrap();
+                    }
+                    // Faster speeds, then reduced motion, then back to the slowest speed
+                    ButtonType::Motion => {
+                        if reduced_motion.0 {
+                            reduced_motion.0 = false;
+                            animation_speed.0 = ANIMATION_SPEEDS[0];
+                        } else {
+                            match ANIMATION_SPEEDS
+                                .iter()
+                                .position(|&s| s == animation_speed.0)
+                            {
+                                Some(index) if index + 1 < ANIMATION_SPEEDS.len() => {
+                                    animation_speed.0 = ANIMATION_SPEEDS[index + 1];
+                                }
+                                _ => reduced_motion.0 = true,
+                            }
+                        }
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::FallGravity => {
+                        fall_gravity.0 = next_fall_gravity(fall_gravity.0);
+                        game_state.restart().unwrap();
+                    }
+                    // The ghost disks in the game are updated by it
+                    ButtonType::GhostAlpha => {
+                        ghost_alpha.0 = next_ghost_alpha(ghost_alpha.0);
+                        game_state.restart().unwrap();
+                    }
+                    // The disks and holes in the game are redrawn by it
+                    ButtonType::CircleVertices => {
+                        circle_vertices.0 = next_circle_vertices(circle_vertices.0);
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::ToggleRequireRelease => {
+                        require_release.0 = !require_release.0;
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::ToggleAutosave => {
+                        autosave.0 = !autosave.0;
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::UndoLimit => {
+                        undo_limit.0 = next_undo_limit(undo_limit.0);
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::SimultaneousWinRule => {
+                        *simultaneous_win_rule = match *simultaneous_win_rule {
+                            SimultaneousWinRule::Mover => SimultaneousWinRule::Draw,
+                            SimultaneousWinRule::Draw => SimultaneousWinRule::Mover,
+                        };
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::WinLineStyle => {
+                        *win_line_style = match *win_line_style {
+                            WinLineStyle::Bar => WinLineStyle::Rings,
+                            WinLineStyle::Rings => WinLineStyle::Both,
+                            WinLineStyle::Both => WinLineStyle::Bar,
+                        };
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::WinLineThickness => {
+                        win_line_thickness.0 = next_win_line_thickness(win_line_thickness.0);
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::DropInputRegion => {
+                        *drop_input_region = match *drop_input_region {
+                            DropInputRegion::Anywhere => DropInputRegion::Board,
+                            DropInputRegion::Board => DropInputRegion::Anywhere,
+                        };
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::HistoryDisplayMode => {
+                        *history_display_mode = match *history_display_mode {
+                            HistoryDisplayMode::Nodes => HistoryDisplayMode::Text,
+                            HistoryDisplayMode::Text => HistoryDisplayMode::Hidden,
+                            HistoryDisplayMode::Hidden => HistoryDisplayMode::Nodes,
+                        };
+                        game_state.restart().unwrap();
+                    }
+                    // The game's history is trimmed by it with the next move
+                    ButtonType::HistoryLimit => {
+                        history_limit.0 = next_history_limit(history_limit.0);
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::ToggleMoveNumbers => {
+                        show_move_numbers.0 = !show_move_numbers.0;
+                        game_state.restart().unwrap();
+                    }
+                    // The game lays itself out again behind the menu
+                    ButtonType::BoardLayout => {
+                        *board_layout = next_board_layout(*board_layout);
+                        game_state.restart().unwrap();
+                    }
+                    // Cycle through the preset delays
+                    ButtonType::DemoDelay => {
+                        demo_delay.0 = next_delay(demo_delay.0);
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::AiDelay => {
+                        ai_delay.0 = next_delay(ai_delay.0);
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::AiSearchTime => {
+                        ai_search_time.0 = next_ai_search_time(ai_search_time.0);
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::ToggleAiIntent => {
+                        show_ai_intent.0 = !show_ai_intent.0;
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::ToggleCountdown => {
+                        countdown.0 = !countdown.0;
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::ToggleLossWarning => {
+                        loss_warning.0 = !loss_warning.0;
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::ThreatGlow => {
+                        *threat_glow = match *threat_glow {
+                            ThreatGlow::Off => ThreatGlow::Own,
+                            ThreatGlow::Own => ThreatGlow::Both,
+                            ThreatGlow::Both => ThreatGlow::Off,
+                        };
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::ToggleFogOfWar => {
+                        fog_of_war.0 = !fog_of_war.0;
+                        game_state.restart().unwrap();
+                    }
+                    ButtonType::ToggleAutoForced => {
+                        auto_forced.0 = !auto_forced.0;
+                        game_state.restart().unwrap();
+                    }
+                    // The game recolors itself, the menu is rebuilt with the new colors
+                    ButtonType::Theme => {
+                        *theme = theme.next();
+                        game_state.restart().unwrap();
+                    }
+                    // Exit the whole app (on the second click, if it needs to be confirmed)
+                    ButtonType::Exit => {
+                        if exit_pending.0 || !confirm_exit.0 {
+                            exit.send_default();
+                        } else {
+                            exit_pending.0 = true;
+                        }
+                    }
                 }
             }
             Interaction::Hovered => {
-                *background_color = HOVERED_BUTTON.into();
+                *background_color = theme.colors().button_hovered.into();
             }
             Interaction::None => {
-                *background_color = NORMAL_BUTTON.into();
+                *background_color = theme.colors().button.into();
             }
         }
     }
 }
 
-// Keeps the BoardSize struct and displayed text in sync
-fn update_text(mut query: Query<&mut Text, With<BoardSizeText>>, board_size: Res<BoardSize>) {
+// Rebuilds the board size preview whenever the size changes (or the new game page was just opened)
+fn update_size_preview(
+    mut commands: Commands,
+    board_size: Res<BoardSize>,
+    menu_page: Res<MenuPage>,
+    theme: Res<Theme>,
+    layout: Res<BoardLayout>,
+    circle_vertices: Res<CircleVertices>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    query: Query<Entity, With<SizePreview>>,
+) {
+    if *menu_page != MenuPage::NewGame || (!board_size.is_changed() && !query.is_empty()) {
+        return;
+    }
+
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+
+    let colors = theme.colors();
+    let preview = board_preview(
+        board_size.rows,
+        board_size.cols,
+        PREVIEW_WIDTH,
+        PREVIEW_MAX_CELLS,
+        *layout,
+    );
+
+    // Board, above the tint
+    commands.spawn((
+        SpriteBundle {
+            transform: Transform {
+                translation: PREVIEW_POSITION.extend(0.6),
+                scale: preview.size.extend(0.0),
+                ..default()
+            },
+            sprite: Sprite {
+                color: colors.board,
+                ..default()
+            },
+            ..default()
+        },
+        SizePreview,
+        InMenu,
+    ));
+
+    // Holes
+    let circle = meshes.add(circle_mesh(*circle_vertices));
+    let hole = materials.add(ColorMaterial::from(colors.background));
+    for position in preview.holes {
+        commands.spawn((
+            MaterialMesh2dBundle {
+                mesh: circle.clone().into(),
+                material: hole.clone(),
+                transform: Transform {
+                    translation: (PREVIEW_POSITION + position).extend(0.7),
+                    scale: Vec3::new(preview.hole_size, preview.hole_size, 0.0),
+                    ..default()
+                },
+                ..default()
+            },
+            SizePreview,
+            InMenu,
+        ));
+    }
+}
+
+// The options for the next game chosen on the new game page
+type NewGameOptions<'w> = (
+    ResMut<'w, BoardSize>,
+    ResMut<'w, PlayerCount>,
+    ResMut<'w, ChosenDropPattern>,
+    ResMut<'w, ChosenWinCondition>,
+    ResMut<'w, ChosenWinDirections>,
+    ResMut<'w, ChosenMatchLength>,
+    ResMut<'w, ChosenWinLengths>,
+);
+type NoticeTextOnly = (
+    With<NoticeText>,
+    Without<BoardSizeText>,
+    Without<PlayerCountText>,
+);
+type DropPatternTextOnly = (
+    With<DropPatternText>,
+    Without<BoardSizeText>,
+    Without<PlayerCountText>,
+    Without<NoticeText>,
+);
+type WinConditionTextOnly = (
+    With<WinConditionText>,
+    Without<BoardSizeText>,
+    Without<PlayerCountText>,
+    Without<NoticeText>,
+    Without<DropPatternText>,
+);
+type MatchLengthTextOnly = (
+    With<MatchLengthText>,
+    Without<BoardSizeText>,
+    Without<PlayerCountText>,
+    Without<NoticeText>,
+    Without<DropPatternText>,
+    Without<WinConditionText>,
+);
+
+// Takes back a pending exit when the player clicks anywhere but the exit button
+fn cancel_exit(
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut exit_pending: ResMut<ExitPending>,
+    button_query: Query<(&Interaction, &ButtonType)>,
+) {
+    if !exit_pending.0 || !mouse_buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let on_exit = button_query.iter().any(|(interaction, button_type)| {
+        matches!(button_type, ButtonType::Exit) && *interaction == Interaction::Clicked
+    });
+    if !on_exit {
+        exit_pending.0 = false;
+    }
+}
+
+// Asks for confirmation on the exit button while an exit is pending
+fn update_exit_text(exit_pending: Res<ExitPending>, mut query: Query<&mut Text, With<ExitText>>) {
+    if !exit_pending.is_changed() {
+        return;
+    }
     for mut text in &mut query {
-        text.sections[0].value = format!("{}x{}", board_size.rows, board_size.cols);
+        text.sections[0].value = if exit_pending.0 {
+            "Confirm?".to_string()
+        } else {
+            "Exit".to_string()
+        };
+    }
+}
+
+// Clicking the size text starts typing a size like "10x10", which is used on enter if the buttons
+// could make it too. Clicking the text again stops typing, and sizes that aren't allowed flash it.
+fn type_board_size(
+    mut size_entry: ResMut<SizeEntry>,
+    mut board_size: ResMut<BoardSize>,
+    mut notice: ResMut<Notice>,
+    mut characters: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    (win_lengths, player_count): (Res<ChosenWinLengths>, Res<PlayerCount>),
+    query: Query<&Interaction, (Changed<Interaction>, With<BoardSizeText>)>,
+    size_text_query: Query<(), With<BoardSizeText>>,
+) {
+    size_entry.error_flash = (size_entry.error_flash - time.delta_seconds()).max(0.0);
+    // Read every frame, so nothing typed before the click ends up in the size
+    let typed_chars: Vec<char> = characters.iter().map(|event| event.char).collect();
+
+    // The size text is only on the new game page, leaving it stops typing
+    if size_text_query.is_empty() {
+        size_entry.typed = None;
+        return;
+    }
+    if query.iter().any(|&interaction| interaction == Interaction::Clicked) {
+        size_entry.typed = match size_entry.typed {
+            Some(_) => None,
+            None => Some(String::new()),
+        };
+    }
+    let Some(typed) = &mut size_entry.typed else {
+        return;
+    };
+
+    typed.extend(
+        typed_chars
+            .into_iter()
+            .filter(|character| character.is_ascii_digit() || character.eq_ignore_ascii_case(&'x')),
+    );
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        typed.pop();
+    }
+    if !keyboard_input.just_pressed(KeyCode::Return) {
+        return;
+    }
+
+    match parse_board_size(typed) {
+        Some((rows, cols))
+            if Board::fits_win_length(
+                rows,
+                cols,
+                longest_win_length(&win_lengths, player_count.0),
+            ) && in_size_limits(rows, cols) =>
+        {
+            notice.0 = None;
+            board_size.rows = rows;
+            board_size.cols = cols;
+        }
+        _ => size_entry.error_flash = SIZE_ERROR_FLASH,
+    }
+    size_entry.typed = None;
+}
+
+// Shows a win length button for each player in the next game, with the number they need
+fn update_win_length_buttons(
+    player_count: Res<PlayerCount>,
+    win_lengths: Res<ChosenWinLengths>,
+    mut button_query: Query<(&mut Style, &ButtonType)>,
+    mut text_query: Query<(&mut Text, &WinLengthText)>,
+) {
+    for (mut style, button_type) in &mut button_query {
+        if let ButtonType::WinLength(player) = *button_type {
+            style.display = if player < player_count.0 {
+                Display::Flex
+            } else {
+                Display::None
+            };
+        }
+    }
+    for (mut text, &WinLengthText(player)) in &mut text_query {
+        text.sections[0].value = format!(
+            "{} needs {}",
+            PLAYER_NAMES[player as usize],
+            win_lengths.0[player as usize]
+        );
+    }
+}
+
+// Keeps the BoardSize (with the SizeEntry), PlayerCount, ChosenDropPattern, ChosenWinCondition
+// (with ChosenWinDirections), ChosenMatchLength and Notice structs and displayed text in sync
+fn update_text(
+    mut board_size_query: Query<&mut Text, (With<BoardSizeText>, Without<PlayerCountText>)>,
+    mut player_count_query: Query<&mut Text, With<PlayerCountText>>,
+    mut notice_query: Query<&mut Text, NoticeTextOnly>,
+    mut drop_pattern_query: Query<&mut Text, DropPatternTextOnly>,
+    mut win_condition_query: Query<&mut Text, WinConditionTextOnly>,
+    mut match_length_query: Query<&mut Text, MatchLengthTextOnly>,
+    board_size: Res<BoardSize>,
+    player_count: Res<PlayerCount>,
+    drop_pattern: Res<ChosenDropPattern>,
+    win_condition: Res<ChosenWinCondition>,
+    win_directions: Res<ChosenWinDirections>,
+    match_length: Res<ChosenMatchLength>,
+    notice: Res<Notice>,
+    size_entry: Res<SizeEntry>,
+    theme: Res<Theme>,
+) {
+    for mut text in &mut drop_pattern_query {
+        text.sections[0].value = format!("{:?}", drop_pattern.0);
+    }
+    for mut text in &mut win_condition_query {
+        let (name, _, _) = WIN_RULES[win_rule_index(win_condition.0, win_directions.0)];
+        text.sections[0].value = format!("Win: {}", name);
+    }
+    for mut text in &mut match_length_query {
+        text.sections[0].value = match match_length.0 {
+            Some(games) => format!("Best of {}", games),
+            None => "Single Game".to_string(),
+        };
+    }
+    // While typing, the size text shows what was typed so far
+    for mut text in &mut board_size_query {
+        text.sections[0].value = match &size_entry.typed {
+            Some(typed) => format!("{}_", typed),
+            None => format!("{}x{}", board_size.rows, board_size.cols),
+        };
+        text.sections[0].style.color = if size_entry.error_flash > 0.0 {
+            SIZE_ERROR_COLOR
+        } else {
+            theme.colors().text
+        };
+    }
+    for mut text in &mut player_count_query {
+        text.sections[0].value = format!("{} Players", player_count.0);
+    }
+    for mut text in &mut notice_query {
+        text.sections[0].value = notice.0.clone().unwrap_or_default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_typed_board_sizes() {
+        assert_eq!(parse_board_size("10x10"), Some((10, 10)));
+        assert_eq!(parse_board_size("6X7"), Some((6, 7)));
+        assert_eq!(parse_board_size(" 5 x 6 "), Some((5, 6)));
+        assert_eq!(parse_board_size(""), None);
+        assert_eq!(parse_board_size("10"), None);
+        assert_eq!(parse_board_size("x7"), None);
+        assert_eq!(parse_board_size("6x"), None);
+        assert_eq!(parse_board_size("6x7x8"), None);
+        assert_eq!(parse_board_size("axb"), None);
+    }
+
+    #[test]
+    fn typed_sizes_keep_to_the_limits() {
+        assert!(in_size_limits(6, 7));
+        assert!(in_size_limits(10, 8));
+        assert!(!in_size_limits(6, 9));
+        assert!(!in_size_limits(MAX_BOARD_SIZE + 1, MAX_BOARD_SIZE));
     }
 }
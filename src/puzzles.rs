@@ -0,0 +1,74 @@
+use bevy::prelude::*;
+use instant::SystemTime;
+use serde::Deserialize;
+
+use crate::game::{GameData, Turn};
+
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+// Puzzle positions bundled with the game, a different one is picked each day
+const PUZZLES: [&str; 7] = [
+    include_str!("../assets/puzzles/puzzle_1.json"),
+    include_str!("../assets/puzzles/puzzle_2.json"),
+    include_str!("../assets/puzzles/puzzle_3.json"),
+    include_str!("../assets/puzzles/puzzle_4.json"),
+    include_str!("../assets/puzzles/puzzle_5.json"),
+    include_str!("../assets/puzzles/puzzle_6.json"),
+    include_str!("../assets/puzzles/puzzle_7.json"),
+];
+
+// A scenario to load, with the player to move having to win within a number of their own moves
+#[derive(Deserialize)]
+pub struct Puzzle {
+    pub objective: String,
+    pub moves: usize,
+    #[serde(flatten)]
+    pub scenario: GameData,
+}
+
+// Inserted by the game while a puzzle is being played
+#[derive(Resource)]
+pub struct ActivePuzzle {
+    // The player that has to win
+    pub player: Turn,
+    // How many moves they have to do it
+    pub moves: usize,
+    // Length of the move history when the puzzle was loaded
+    pub start: usize,
+}
+
+// Picks the puzzle for a day (counted from the unix epoch), so everyone gets the same one
+fn puzzle_index(day: u64) -> usize {
+    (day % PUZZLES.len() as u64) as usize
+}
+
+// Reads the puzzle for the current day
+pub fn todays_puzzle() -> serde_json::Result<Puzzle> {
+    let day = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|time| time.as_secs() / SECONDS_PER_DAY)
+        .unwrap_or(0);
+    serde_json::from_str(PUZZLES[puzzle_index(day)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn puzzle_of_a_fixed_date_is_stable() {
+        // 2023-01-31
+        let day = 19_388;
+        assert_eq!(puzzle_index(day), 5);
+        assert_eq!(puzzle_index(day + 1), 6);
+        assert_eq!(puzzle_index(day + PUZZLES.len() as u64), 5);
+    }
+
+    #[test]
+    fn bundled_puzzles_can_be_played() {
+        for puzzle in PUZZLES {
+            let puzzle: Puzzle = serde_json::from_str(puzzle).unwrap();
+            assert!(puzzle.scenario.playable());
+        }
+    }
+}
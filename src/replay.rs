@@ -0,0 +1,74 @@
+use bevy::prelude::Color;
+use image::{Rgba, RgbaImage};
+
+use crate::{
+    game::{Board, ROCK_COLOR},
+    theme::ThemeColors,
+};
+
+// Size of a cell of the board in the exported frames (in pixels), and the disks in it
+const CELL_SIZE: u32 = 60;
+const DISK_RADIUS: f32 = 0.45 * CELL_SIZE as f32;
+
+// Writes every board state as a PNG frame into the directory (created if it doesn't exist yet),
+// named in order: frame_000.png, frame_001.png, ...
+// The frames of an earlier export are removed first, so a longer game can't leave any behind.
+// Returns how many frames were written.
+pub fn export_frames(dir: &str, boards: &[Board], colors: &ThemeColors) -> Result<usize, String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|err| format!("Failed to create replay directory {}: {}", dir, err))?;
+    remove_frames(dir)?;
+
+    for (i, board) in boards.iter().enumerate() {
+        let path = std::path::Path::new(dir).join(format!("frame_{:03}.png", i));
+        render_board(board, colors)
+            .save(&path)
+            .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+    }
+    Ok(boards.len())
+}
+
+// Removes the frames in the directory, leaving any other files in it alone
+fn remove_frames(dir: &str) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|err| format!("Failed to read replay directory {}: {}", dir, err))?;
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let is_frame = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("frame_") && name.ends_with(".png"));
+        if is_frame {
+            std::fs::remove_file(&path)
+                .map_err(|err| format!("Failed to remove {}: {}", path.display(), err))?;
+        }
+    }
+    Ok(())
+}
+
+// Draws the board like in the game: disks, rocks or empty holes (in the background color) on the board
+fn render_board(board: &Board, colors: &ThemeColors) -> RgbaImage {
+    let to_pixel = |color: Color| Rgba(color.as_rgba_f32().map(|c| (c * 255.0).round() as u8));
+    let board_color = to_pixel(colors.board);
+    let hole_color = to_pixel(colors.background);
+    let rock_color = to_pixel(ROCK_COLOR);
+
+    RgbaImage::from_fn(
+        board.cols as u32 * CELL_SIZE,
+        board.rows as u32 * CELL_SIZE,
+        |x, y| {
+            let (col, row) = (x / CELL_SIZE, y / CELL_SIZE);
+            let center = (CELL_SIZE / 2) as f32;
+            let dx = (x % CELL_SIZE) as f32 + 0.5 - center;
+            let dy = (y % CELL_SIZE) as f32 + 0.5 - center;
+            if dx * dx + dy * dy > DISK_RADIUS * DISK_RADIUS {
+                return board_color;
+            }
+
+            match board.disks[col as usize][row as usize] {
+                Some(disk) => to_pixel(disk.to_turn().to_color(colors)),
+                None if board.is_rock(row as i32, col as i32) => rock_color,
+                None => hole_color,
+            }
+        },
+    )
+}
@@ -0,0 +1,620 @@
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+use std::{collections::BTreeMap, time::Duration};
+
+use bevy::{app::AppExit, prelude::*, winit::WinitWindows};
+use serde::{Deserialize, Serialize};
+
+use crate::theme::Theme;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+// Whether the ghost disk is shown above the column under the mouse
+#[derive(Resource, Clone, Copy)]
+pub struct ShowGhost(pub bool);
+
+// Whether the ghost disk also shows where the disk will land, with a faint trail down to it
+#[derive(Resource, Clone, Copy)]
+pub struct ShowTrajectory(pub bool);
+
+// How opaque the ghost disks are, from 0.0 (invisible) to 1.0 (like a dropped disk)
+#[derive(Resource, Clone, Copy)]
+pub struct GhostAlpha(pub f32);
+
+// How long the AI searches for its reply to a player (in seconds), looking further ahead as far as
+// it gets in that time, or None to always look the same number of moves ahead
+#[derive(Resource, Clone, Copy)]
+pub struct AiSearchTime(pub Option<f32>);
+
+// Whether a hint in a position that is lost with perfect play says so instead of suggesting a move
+// (only known on boards small enough for the solver)
+#[derive(Resource, Clone, Copy)]
+pub struct LossWarning(pub bool);
+
+// Whether the first two players trade disk colors, for players who prefer the other one.
+// Only how the disks look changes, the players keep their turn order and names.
+#[derive(Resource, Clone, Copy)]
+pub struct SwapColors(pub bool);
+
+// Number of corners the circles of the disks and holes are drawn with,
+// more look smoother on big disks but take more to draw
+#[derive(Resource, Clone, Copy)]
+pub struct CircleVertices(pub usize);
+
+// Whether a faint ghost disk previews the AI's reply while it thinks, when playing against it
+#[derive(Resource, Clone, Copy)]
+pub struct ShowAiIntent(pub bool);
+
+// Whether games against or between AIs start after a 3-2-1 countdown, instead of right away
+#[derive(Resource, Clone, Copy)]
+pub struct Countdown(pub bool);
+
+// Whether the board is covered after each move in hot seat play, until the next player clicks,
+// so the device can be passed on without them seeing each other's plans
+#[derive(Resource, Clone, Copy)]
+pub struct PassScreen(pub bool);
+
+// Whether each disk shows the number of the move it was dropped in
+#[derive(Resource, Clone, Copy)]
+pub struct ShowMoveNumbers(pub bool);
+
+// Whether a burst of particles celebrates the winner
+#[derive(Resource, Clone, Copy)]
+pub struct ShowParticles(pub bool);
+
+// Whether exiting takes a second click on the exit button, so it isn't hit by accident
+#[derive(Resource, Clone, Copy)]
+pub struct ConfirmExit(pub bool);
+
+// Whether animations (menu fades, particles, disk drops, the pulsing last move marker) are skipped
+#[derive(Resource, Clone, Copy)]
+pub struct ReducedMotion(pub bool);
+
+// How fast animations play, 1.0 being the normal speed
+#[derive(Resource, Clone, Copy)]
+pub struct AnimationSpeed(pub f32);
+
+// How strongly dropped disks are pulled into their places (in pixels per second squared),
+// only changing how fast they are seen falling, not the game
+#[derive(Resource, Clone, Copy)]
+pub struct FallGravity(pub f32);
+
+// The fastest wins against the AI (in seconds on the game clock), by board size and difficulty,
+// kept for the player to beat
+#[derive(Resource, Clone, Default)]
+pub struct BestTimes(pub BTreeMap<String, f32>);
+
+impl BestTimes {
+    // What the best time of a board size and difficulty is kept under, like "6x7 depth 4"
+    // (or "6x7 0.25s search" when the AI searches for a time instead of to a depth)
+    pub fn key(rows: i32, cols: i32, depth: u32, search_time: Option<f32>) -> String {
+        match search_time {
+            Some(seconds) => format!("{}x{} {}s search", rows, cols, seconds),
+            None => format!("{}x{} depth {}", rows, cols, depth),
+        }
+    }
+
+    // Records the time of a win, returns whether it beat the best one (the first win always does)
+    pub fn record(&mut self, key: &str, seconds: f32) -> bool {
+        let beaten = self.0.get(key).is_none_or(|&best| seconds < best);
+        if beaten {
+            self.0.insert(key.to_string(), seconds);
+        }
+        beaten
+    }
+}
+
+// Where the window was last moved to (its top left corner, in logical pixels from the top left of
+// the primary monitor), so it opens there again. None leaves it to the window manager.
+// The window can't be resized, so there is no size to keep.
+#[derive(Resource, Clone, Copy)]
+pub struct WindowPlacement(pub Option<(i32, i32)>);
+
+// Advances an animation timer by a frame, scaled by the animation speed,
+// or straight to its end when motion is reduced
+pub fn tick_animation(
+    timer: &mut Timer,
+    delta: Duration,
+    speed: AnimationSpeed,
+    reduced_motion: ReducedMotion,
+) -> &Timer {
+    let delta = if reduced_motion.0 {
+        timer.duration()
+    } else {
+        delta.mul_f32(speed.0)
+    };
+    timer.tick(delta)
+}
+
+// Seconds between moves in the AI vs AI demo
+#[derive(Resource, Clone, Copy)]
+pub struct DemoDelay(pub f32);
+
+// Minimum seconds the AI takes for a move when playing against it, so it doesn't move instantly
+#[derive(Resource, Clone, Copy)]
+pub struct AiDelay(pub f32);
+
+// Whether the game is saved automatically when quitting (and pausing), to continue it later
+#[derive(Resource, Clone, Copy)]
+pub struct Autosave(pub bool);
+
+// How many moves can be taken back in each game, None for no limit
+#[derive(Resource, Clone, Copy)]
+pub struct UndoLimit(pub Option<u32>);
+
+impl UndoLimit {
+    // Whether another undo is allowed after the given number of them
+    pub fn allows(self, taken: u32) -> bool {
+        self.0.is_none_or(|limit| taken < limit)
+    }
+}
+
+// How many moves the history keeps at most, the oldest ones are dropped beyond it (None keeps all).
+// The game is played on the board alone, the history is only shown and used to take moves back,
+// so undo reaches back as far as the kept moves. A game that dropped moves can't be replayed from
+// the start, so reviewing it or exporting its replay only shows its final position.
+#[derive(Resource, Clone, Copy)]
+pub struct HistoryLimit(pub Option<usize>);
+
+// Whether the disk is dropped by itself when only one column is left to drop into,
+// since there is nothing to decide then
+#[derive(Resource, Clone, Copy)]
+pub struct AutoForced(pub bool);
+
+// Where clicks drop a disk into the column under the mouse
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DropInputRegion {
+    // Anywhere above, on or below the board
+    #[default]
+    Anywhere,
+    // Only on the board, or the strip above it
+    Board,
+}
+
+// Whether the mouse button has to be let go before it can drop another disk,
+// so a button held down (or bouncing) never drops twice
+#[derive(Resource, Clone, Copy)]
+pub struct RequireRelease(pub bool);
+
+// Who wins when a single action (like flipping the board) connects lines for several players at once
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SimultaneousWinRule {
+    // The player who made the action
+    #[default]
+    Mover,
+    // Nobody, the game is a draw
+    Draw,
+}
+
+// How the winning line is marked on the board
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WinLineStyle {
+    // A bar from one end of the line to the other
+    #[default]
+    Bar,
+    // A ring around each of the winning disks
+    Rings,
+    // The bar over the rings
+    Both,
+}
+
+// Whether the disks of the other players are shown in gray during play (fog of war), only
+// showing their colors for a moment after they are dropped, so the players have to remember them
+#[derive(Resource, Clone, Copy)]
+pub struct FogOfWar(pub bool);
+
+// Which columns glow during play because an open line of three ends there (a live threat)
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ThreatGlow {
+    #[default]
+    Off,
+    // Only the threats of the player to move, their own chances
+    Own,
+    // Also the threats of the other players, in another color
+    Both,
+}
+
+// How thick the winning bar (and the rings) are, as a part of the size of a cell
+#[derive(Resource, Clone, Copy)]
+pub struct WinLineThickness(pub f32);
+
+// How the moves played so far are shown under the board
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HistoryDisplayMode {
+    // A column letter per move, spread along the bottom
+    #[default]
+    Nodes,
+    // One compact line of text with the moves (e.g. "d c d")
+    Text,
+    // Not shown at all, for a cleaner look (the moves are still recorded).
+    // Nodes already shown stay until the game ends.
+    Hidden,
+}
+
+// Where the board is placed in the window
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct BoardLayout {
+    // Centered in the window within the margins, instead of filling the top of it
+    // with the move history under it
+    pub centered: bool,
+    pub margins: BoardMargins,
+}
+
+// Space kept free around the centered board, in pixels (the move history goes in the bottom one)
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct BoardMargins {
+    pub top: f32,
+    pub bottom: f32,
+    pub side: f32,
+}
+
+impl Default for BoardMargins {
+    fn default() -> Self {
+        Self {
+            top: 80.0,
+            bottom: 80.0,
+            side: 40.0,
+        }
+    }
+}
+
+// What is written to the settings file, fields missing from older files keep their default
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    show_ghost: bool,
+    demo_delay: f32,
+    ai_delay: f32,
+    theme: Theme,
+    autosave: bool,
+    drop_input_region: DropInputRegion,
+    history_display_mode: HistoryDisplayMode,
+    show_particles: bool,
+    confirm_exit: bool,
+    reduced_motion: bool,
+    animation_speed: f32,
+    show_trajectory: bool,
+    undo_limit: Option<u32>,
+    require_release: bool,
+    simultaneous_win_rule: SimultaneousWinRule,
+    board_layout: BoardLayout,
+    ghost_alpha: f32,
+    show_ai_intent: bool,
+    pass_screen: bool,
+    show_move_numbers: bool,
+    win_line_style: WinLineStyle,
+    win_line_thickness: f32,
+    countdown: bool,
+    ai_search_time: Option<f32>,
+    loss_warning: bool,
+    circle_vertices: usize,
+    swap_colors: bool,
+    history_limit: Option<usize>,
+    auto_forced: bool,
+    fall_gravity: f32,
+    window_position: Option<(i32, i32)>,
+    best_times: BTreeMap<String, f32>,
+    threat_glow: ThreatGlow,
+    fog_of_war: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            show_ghost: true,
+            demo_delay: 0.5,
+            ai_delay: 1.0,
+            theme: Theme::Classic,
+            autosave: true,
+            drop_input_region: DropInputRegion::Anywhere,
+            history_display_mode: HistoryDisplayMode::Nodes,
+            show_particles: true,
+            confirm_exit: true,
+            reduced_motion: false,
+            animation_speed: 1.0,
+            show_trajectory: false,
+            undo_limit: Some(3),
+            require_release: false,
+            simultaneous_win_rule: SimultaneousWinRule::Mover,
+            board_layout: BoardLayout::default(),
+            ghost_alpha: 0.3,
+            show_ai_intent: false,
+            pass_screen: false,
+            show_move_numbers: false,
+            win_line_style: WinLineStyle::Bar,
+            win_line_thickness: 0.5,
+            countdown: false,
+            ai_search_time: None,
+            loss_warning: false,
+            circle_vertices: 64,
+            swap_colors: false,
+            history_limit: None,
+            auto_forced: false,
+            fall_gravity: 6000.0,
+            window_position: None,
+            best_times: BTreeMap::new(),
+            threat_glow: ThreatGlow::Off,
+            fog_of_war: false,
+        }
+    }
+}
+
+pub struct SettingsPlugin;
+
+// Load the settings when the app starts, and save them again whenever they change
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        let settings = load_settings();
+        app.insert_resource(ShowGhost(settings.show_ghost))
+            .insert_resource(DemoDelay(settings.demo_delay))
+            .insert_resource(AiDelay(settings.ai_delay))
+            .insert_resource(settings.theme)
+            .insert_resource(Autosave(settings.autosave))
+            .insert_resource(settings.drop_input_region)
+            .insert_resource(settings.history_display_mode)
+            .insert_resource(ShowParticles(settings.show_particles))
+            .insert_resource(ConfirmExit(settings.confirm_exit))
+            .insert_resource(ReducedMotion(settings.reduced_motion))
+            .insert_resource(AnimationSpeed(settings.animation_speed))
+            .insert_resource(ShowTrajectory(settings.show_trajectory))
+            .insert_resource(UndoLimit(settings.undo_limit))
+            .insert_resource(RequireRelease(settings.require_release))
+            .insert_resource(settings.simultaneous_win_rule)
+            .insert_resource(settings.board_layout)
+            .insert_resource(GhostAlpha(settings.ghost_alpha))
+            .insert_resource(ShowAiIntent(settings.show_ai_intent))
+            .insert_resource(PassScreen(settings.pass_screen))
+            .insert_resource(ShowMoveNumbers(settings.show_move_numbers))
+            .insert_resource(settings.win_line_style)
+            .insert_resource(WinLineThickness(settings.win_line_thickness))
+            .insert_resource(Countdown(settings.countdown))
+            .insert_resource(AiSearchTime(settings.ai_search_time))
+            .insert_resource(LossWarning(settings.loss_warning))
+            .insert_resource(CircleVertices(settings.circle_vertices))
+            .insert_resource(SwapColors(settings.swap_colors))
+            .insert_resource(HistoryLimit(settings.history_limit))
+            .insert_resource(AutoForced(settings.auto_forced))
+            .insert_resource(FallGravity(settings.fall_gravity))
+            .insert_resource(WindowPlacement(settings.window_position))
+            .insert_resource(BestTimes(settings.best_times))
+            .insert_resource(settings.threat_glow)
+            .insert_resource(FogOfWar(settings.fog_of_war))
+            .add_startup_system(check_window_on_screen)
+            // Last, to catch the app closing wherever that happened in the frame
+            .add_system_to_stage(CoreStage::Last, track_window_position)
+            .add_system_to_stage(CoreStage::Last, save_settings.after(track_window_position));
+    }
+}
+
+// Reads the settings file, falling back to the defaults if it is missing or invalid
+fn load_settings() -> Settings {
+    let Ok(file) = std::fs::File::open(SETTINGS_FILE) else {
+        return Settings::default();
+    };
+    serde_json::from_reader(file).unwrap_or_else(|err| {
+        warn!("Failed to read settings file: {}", err);
+        Settings::default()
+    })
+}
+
+// Where the window opens, read from the settings file before the app (and its window) is created
+pub fn saved_window_position() -> WindowPosition {
+    match load_settings().window_position {
+        Some((x, y)) => WindowPosition::At(Vec2::new(x as f32, y as f32)),
+        None => WindowPosition::Automatic,
+    }
+}
+
+// Centers the window if it opened where no monitor shows it, like at a position saved on a monitor
+// that isn't connected anymore, so it can't get lost off screen
+fn check_window_on_screen(mut windows: ResMut<Windows>, winit_windows: NonSend<WinitWindows>) {
+    let Some(window) = windows.get_primary_mut() else {
+        return;
+    };
+    let Some(winit_window) = winit_windows.get_window(window.id()) else {
+        return;
+    };
+    let Ok(corner) = winit_window.outer_position() else {
+        return;
+    };
+    let on_screen = winit_window.available_monitors().any(|monitor| {
+        let (position, size) = (monitor.position(), monitor.size());
+        (position.x..position.x + size.width as i32).contains(&corner.x)
+            && (position.y..position.y + size.height as i32).contains(&corner.y)
+    });
+    if !on_screen {
+        window.center_window(MonitorSelection::Primary);
+    }
+}
+
+// Remembers where the window was moved to (the events are in physical pixels). Dragging the
+// window moves it many times, so the position is only kept in memory until the app is closing
+// (which closing the window also does), and then saved with the other settings.
+fn track_window_position(
+    windows: Res<Windows>,
+    mut moved_events: EventReader<WindowMoved>,
+    mut exit_events: EventReader<AppExit>,
+    mut window_placement: ResMut<WindowPlacement>,
+    mut moved: Local<bool>,
+) {
+    if let Some(moved_event) = moved_events.iter().last() {
+        let scale = windows
+            .get(moved_event.id)
+            .map_or(1.0, |window| window.scale_factor());
+        let position = (moved_event.position.as_dvec2() / scale).as_ivec2();
+        if window_placement.0 != Some((position.x, position.y)) {
+            window_placement.bypass_change_detection().0 = Some((position.x, position.y));
+            *moved = true;
+        }
+    }
+
+    if exit_events.iter().next().is_some() && *moved {
+        window_placement.set_changed();
+    }
+}
+
+// Writes the settings file when any setting was changed (but not when they were first inserted)
+fn save_settings(
+    show_ghost: Res<ShowGhost>,
+    demo_delay: Res<DemoDelay>,
+    ai_delay: Res<AiDelay>,
+    theme: Res<Theme>,
+    autosave: Res<Autosave>,
+    drop_input_region: Res<DropInputRegion>,
+    history_display_mode: Res<HistoryDisplayMode>,
+    show_particles: Res<ShowParticles>,
+    confirm_exit: Res<ConfirmExit>,
+    reduced_motion: Res<ReducedMotion>,
+    animation_speed: Res<AnimationSpeed>,
+    show_trajectory: Res<ShowTrajectory>,
+    undo_limit: Res<UndoLimit>,
+    require_release: Res<RequireRelease>,
+    (simultaneous_win_rule, best_times, threat_glow, fog_of_war): (
+        Res<SimultaneousWinRule>,
+        Res<BestTimes>,
+        Res<ThreatGlow>,
+        Res<FogOfWar>,
+    ),
+    (
+        board_layout,
+        ghost_alpha,
+        show_ai_intent,
+        pass_screen,
+        show_move_numbers,
+        win_line_style,
+        win_line_thickness,
+        countdown,
+        ai_search_time,
+        loss_warning,
+        circle_vertices,
+        swap_colors,
+        history_limit,
+        auto_forced,
+        fall_gravity,
+        window_placement,
+    ): (
+        Res<BoardLayout>,
+        Res<GhostAlpha>,
+        Res<ShowAiIntent>,
+        Res<PassScreen>,
+        Res<ShowMoveNumbers>,
+        Res<WinLineStyle>,
+        Res<WinLineThickness>,
+        Res<Countdown>,
+        Res<AiSearchTime>,
+        Res<LossWarning>,
+        Res<CircleVertices>,
+        Res<SwapColors>,
+        Res<HistoryLimit>,
+        Res<AutoForced>,
+        Res<FallGravity>,
+        Res<WindowPlacement>,
+    ),
+) {
+    let changed = show_ghost.is_changed()
+        || demo_delay.is_changed()
+        || ai_delay.is_changed()
+        || theme.is_changed()
+        || autosave.is_changed()
+        || drop_input_region.is_changed()
+        || history_display_mode.is_changed()
+        || show_particles.is_changed()
+        || confirm_exit.is_changed()
+        || reduced_motion.is_changed()
+        || animation_speed.is_changed()
+        || show_trajectory.is_changed()
+        || undo_limit.is_changed()
+        || require_release.is_changed()
+        || simultaneous_win_rule.is_changed()
+        || board_layout.is_changed()
+        || ghost_alpha.is_changed()
+        || show_ai_intent.is_changed()
+        || pass_screen.is_changed()
+        || show_move_numbers.is_changed()
+        || win_line_style.is_changed()
+        || win_line_thickness.is_changed()
+        || countdown.is_changed()
+        || ai_search_time.is_changed()
+        || loss_warning.is_changed()
+        || circle_vertices.is_changed()
+        || swap_colors.is_changed()
+        || history_limit.is_changed()
+        || auto_forced.is_changed()
+        || fall_gravity.is_changed()
+        || window_placement.is_changed()
+        || best_times.is_changed()
+        || threat_glow.is_changed()
+        || fog_of_war.is_changed();
+    if !changed || show_ghost.is_added() {
+        return;
+    }
+
+    let settings = Settings {
+        show_ghost: show_ghost.0,
+        demo_delay: demo_delay.0,
+        ai_delay: ai_delay.0,
+        theme: *theme,
+        autosave: autosave.0,
+        drop_input_region: *drop_input_region,
+        history_display_mode: *history_display_mode,
+        show_particles: show_particles.0,
+        confirm_exit: confirm_exit.0,
+        reduced_motion: reduced_motion.0,
+        animation_speed: animation_speed.0,
+        show_trajectory: show_trajectory.0,
+        undo_limit: undo_limit.0,
+        require_release: require_release.0,
+        simultaneous_win_rule: *simultaneous_win_rule,
+        board_layout: *board_layout,
+        ghost_alpha: ghost_alpha.0,
+        show_ai_intent: show_ai_intent.0,
+        pass_screen: pass_screen.0,
+        show_move_numbers: show_move_numbers.0,
+        win_line_style: *win_line_style,
+        win_line_thickness: win_line_thickness.0,
+        countdown: countdown.0,
+        ai_search_time: ai_search_time.0,
+        loss_warning: loss_warning.0,
+        circle_vertices: circle_vertices.0,
+        swap_colors: swap_colors.0,
+        history_limit: history_limit.0,
+        auto_forced: auto_forced.0,
+        fall_gravity: fall_gravity.0,
+        window_position: window_placement.0,
+        best_times: best_times.0.clone(),
+        threat_glow: *threat_glow,
+        fog_of_war: fog_of_war.0,
+    };
+
+    let Ok(file) = std::fs::File::create(SETTINGS_FILE) else {
+        warn!("Failed to create settings file");
+        return;
+    };
+    if let Err(err) = serde_json::to_writer(file, &settings) {
+        warn!("Failed to write settings file: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_faster_wins_replace_the_best_time() {
+        let mut best_times = BestTimes::default();
+        let key = BestTimes::key(6, 7, 4, None);
+        assert_eq!(key, "6x7 depth 4");
+
+        assert!(best_times.record(&key, 90.0));
+        assert!(best_times.record(&key, 60.0));
+        assert_eq!(best_times.0[&key], 60.0);
+        assert!(!best_times.record(&key, 75.0));
+        assert!(!best_times.record(&key, 60.0));
+        assert_eq!(best_times.0[&key], 60.0);
+
+        // Other boards and difficulties keep their own best times
+        let timed = BestTimes::key(6, 7, 4, Some(0.5));
+        assert_eq!(timed, "6x7 0.5s search");
+        assert!(best_times.record(&timed, 120.0));
+        assert_eq!(best_times.0[&key], 60.0);
+    }
+}
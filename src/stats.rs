@@ -0,0 +1,143 @@
+use std::path::Path;
+
+use bevy::prelude::*;
+
+use crate::game::{GameData, MAX_PLAYERS};
+
+// Totals over all the games saved in a folder
+#[derive(Default)]
+pub struct Stats {
+    pub games: usize,
+    // Games won by each player, by their number
+    pub wins: [usize; MAX_PLAYERS as usize],
+    // Disks dropped in all the games together
+    pub moves: usize,
+    // How often the winning disk was dropped into each column
+    winning_cols: Vec<usize>,
+}
+
+impl Stats {
+    // Counts a saved game in the totals
+    fn add(&mut self, data: &GameData) {
+        self.games += 1;
+        self.moves += data.moves_played();
+
+        let Some(winner) = data.winner() else {
+            return;
+        };
+        self.wins[winner.index()] += 1;
+        if let Some(col) = data.last_move_col() {
+            let col = col as usize;
+            if self.winning_cols.len() <= col {
+                self.winning_cols.resize(col + 1, 0);
+            }
+            self.winning_cols[col] += 1;
+        }
+    }
+
+    // Disks dropped per game on average, None without any games
+    pub fn average_length(&self) -> Option<f32> {
+        (self.games > 0).then(|| self.moves as f32 / self.games as f32)
+    }
+
+    // The column most winning disks were dropped into (the leftmost one of a tie),
+    // None if no game was won by a drop
+    pub fn most_common_winning_col(&self) -> Option<i32> {
+        let most = *self.winning_cols.iter().max()?;
+        self.winning_cols
+            .iter()
+            .position(|&count| count == most)
+            .map(|col| col as i32)
+    }
+}
+
+// Reads every save file in the folder and adds them up. Files that can't be read,
+// aren't saves or can't be from a real game are left out.
+pub fn aggregate(dir: &Path) -> Stats {
+    let mut stats = Stats::default();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            warn!("Failed to read saves directory {}: {}", dir.display(), err);
+            return stats;
+        }
+    };
+
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.extension().is_none_or(|extension| extension != "json") {
+            continue;
+        }
+        let data = std::fs::File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, GameData>(file).ok());
+        match data {
+            Some(data) if data.playable() => stats.add(&data),
+            _ => warn!("Skipped {}, it is not a valid save", path.display()),
+        }
+    }
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_a_folder_of_saves() {
+        let dir = std::env::temp_dir().join(format!("connect4_stats_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = [
+            // Red won along the bottom row with a drop into the last column
+            (
+                "won.json",
+                r#"{
+                    "board": {"rows": 4, "cols": 4, "disks": [
+                        [null, null, "Blue", "Red"],
+                        [null, null, "Blue", "Red"],
+                        [null, null, "Blue", "Red"],
+                        [null, null, null, "Red"]
+                    ]},
+                    "turn": "Blue",
+                    "history": {"moves": [
+                        [0, "Red"], [0, "Blue"], [1, "Red"], [1, "Blue"],
+                        [2, "Red"], [2, "Blue"], [3, "Red"]
+                    ]}
+                }"#,
+            ),
+            (
+                "in_progress.json",
+                r#"{
+                    "board": {"rows": 4, "cols": 4, "disks": [
+                        [null, null, null, null],
+                        [null, null, null, "Red"],
+                        [null, null, null, null],
+                        [null, null, null, null]
+                    ]},
+                    "turn": "Blue",
+                    "history": {"moves": [[1, "Red"]]}
+                }"#,
+            ),
+            ("broken.json", "not a save"),
+            ("notes.txt", "not a save either"),
+        ];
+        for (name, contents) in files {
+            std::fs::write(dir.join(name), contents).unwrap();
+        }
+
+        let stats = aggregate(&dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(stats.games, 2);
+        assert_eq!(stats.wins, [1, 0, 0, 0]);
+        assert_eq!(stats.moves, 8);
+        assert_eq!(stats.average_length(), Some(4.0));
+        assert_eq!(stats.most_common_winning_col(), Some(3));
+    }
+
+    #[test]
+    fn missing_folder_has_no_games() {
+        let stats = aggregate(Path::new("no/such/folder"));
+        assert_eq!(stats.games, 0);
+        assert_eq!(stats.average_length(), None);
+        assert_eq!(stats.most_common_winning_col(), None);
+    }
+}
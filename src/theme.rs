@@ -0,0 +1,159 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{game::MAX_PLAYERS, settings::SwapColors};
+
+// All the colors used by the game and menus, which change together with the theme
+#[derive(Clone, Copy)]
+pub struct ThemeColors {
+    pub background: Color,
+    pub board: Color,
+    // One color per player, in turn order
+    pub disks: [Color; MAX_PLAYERS as usize],
+    pub winner: Color,
+    pub last_move: Color,
+    pub title: Color,
+    pub text: Color,
+    // Ring around every disk (transparent when the theme has none)
+    pub disk_outline: Color,
+    // Size of the text in the game, compared to the normal size
+    pub text_scale: f32,
+    // Menu buttons, normally and when hovered or pressed
+    pub button: Color,
+    pub button_hovered: Color,
+    pub button_pressed: Color,
+}
+
+const CLASSIC: ThemeColors = ThemeColors {
+    background: Color::rgb(0.0, 0.0, 0.0),
+    board: Color::rgb(1.0, 1.0, 1.0),
+    disks: [
+        Color::rgb(1.0, 0.0, 0.0),
+        Color::rgb(0.0, 0.0, 1.0),
+        Color::rgb(0.0, 0.8, 0.0),
+        Color::rgb(1.0, 0.5, 0.0),
+    ],
+    winner: Color::rgb(1.0, 1.0, 0.0),
+    last_move: Color::rgb(0.3, 0.3, 0.3),
+    title: Color::WHITE,
+    text: Color::WHITE,
+    disk_outline: Color::NONE,
+    text_scale: 1.0,
+    button: Color::rgb(0.15, 0.15, 0.15),
+    button_hovered: Color::rgb(0.25, 0.25, 0.25),
+    button_pressed: Color::rgb(0.35, 0.75, 0.35),
+};
+
+const WOOD: ThemeColors = ThemeColors {
+    background: Color::rgb(0.15, 0.1, 0.05),
+    board: Color::rgb(0.6, 0.4, 0.2),
+    disks: [
+        Color::rgb(0.7, 0.15, 0.1),
+        Color::rgb(0.95, 0.9, 0.75),
+        Color::rgb(0.4, 0.5, 0.2),
+        Color::rgb(0.25, 0.13, 0.05),
+    ],
+    winner: Color::rgb(1.0, 0.8, 0.3),
+    last_move: Color::rgb(0.35, 0.2, 0.1),
+    title: Color::rgb(1.0, 0.8, 0.3),
+    text: Color::rgb(0.95, 0.9, 0.75),
+    disk_outline: Color::NONE,
+    text_scale: 1.0,
+    button: Color::rgb(0.15, 0.15, 0.15),
+    button_hovered: Color::rgb(0.25, 0.25, 0.25),
+    button_pressed: Color::rgb(0.35, 0.75, 0.35),
+};
+
+const NEON: ThemeColors = ThemeColors {
+    background: Color::rgb(0.02, 0.0, 0.08),
+    board: Color::rgb(0.15, 0.05, 0.3),
+    disks: [
+        Color::rgb(1.0, 0.0, 0.8),
+        Color::rgb(0.0, 1.0, 1.0),
+        Color::rgb(0.6, 1.0, 0.0),
+        Color::rgb(1.0, 0.9, 0.0),
+    ],
+    winner: Color::WHITE,
+    last_move: Color::rgb(0.5, 0.5, 1.0),
+    title: Color::rgb(1.0, 0.0, 0.8),
+    text: Color::rgb(0.8, 1.0, 1.0),
+    disk_outline: Color::NONE,
+    text_scale: 1.0,
+    button: Color::rgb(0.15, 0.15, 0.15),
+    button_hovered: Color::rgb(0.25, 0.25, 0.25),
+    button_pressed: Color::rgb(0.35, 0.75, 0.35),
+};
+
+// For low vision: a bright board, disks with thick outlines and big text.
+// The winning line is yellow, which stands out against the red and blue disks and the outlines.
+const HIGH_CONTRAST: ThemeColors = ThemeColors {
+    background: Color::BLACK,
+    board: Color::WHITE,
+    disks: [
+        Color::rgb(0.85, 0.0, 0.0),
+        Color::rgb(0.0, 0.25, 1.0),
+        Color::rgb(0.0, 0.55, 0.0),
+        Color::rgb(1.0, 0.5, 0.0),
+    ],
+    winner: Color::rgb(1.0, 1.0, 0.0),
+    last_move: Color::rgb(1.0, 0.0, 1.0),
+    title: Color::rgb(1.0, 1.0, 0.0),
+    text: Color::WHITE,
+    disk_outline: Color::BLACK,
+    text_scale: 1.5,
+    button: Color::rgb(0.0, 0.0, 0.5),
+    button_hovered: Color::rgb(0.0, 0.0, 0.85),
+    button_pressed: Color::rgb(0.0, 0.5, 0.0),
+};
+
+impl ThemeColors {
+    // The same colors, with the disks of the first two players traded if the setting says so
+    pub fn swapped(&self, swap_colors: SwapColors) -> ThemeColors {
+        let mut colors = *self;
+        if swap_colors.0 {
+            colors.disks.swap(0, 1);
+        }
+        colors
+    }
+}
+
+// The selected color preset, saved with the settings
+#[derive(Resource, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum Theme {
+    #[default]
+    Classic,
+    Wood,
+    Neon,
+    HighContrast,
+}
+
+impl Theme {
+    pub fn colors(self) -> &'static ThemeColors {
+        match self {
+            Theme::Classic => &CLASSIC,
+            Theme::Wood => &WOOD,
+            Theme::Neon => &NEON,
+            Theme::HighContrast => &HIGH_CONTRAST,
+        }
+    }
+
+    // Name shown in the settings
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Classic => "Classic",
+            Theme::Wood => "Wood",
+            Theme::Neon => "Neon",
+            Theme::HighContrast => "Contrast",
+        }
+    }
+
+    // The theme after this one, wrapping around (used by the menu to cycle through them)
+    pub fn next(self) -> Theme {
+        match self {
+            Theme::Classic => Theme::Wood,
+            Theme::Wood => Theme::Neon,
+            Theme::Neon => Theme::HighContrast,
+            Theme::HighContrast => Theme::Classic,
+        }
+    }
+}